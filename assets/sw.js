@@ -1,25 +1,113 @@
-var cacheName = 'egui-template-pwa';
+/* @BUILD@ is replaced with a hash of the wasm by the post_build hook in Trunk.toml, so every
+   deploy gets a cache of its own and the old one is dropped once the new worker takes over. */
+var version = '@BUILD@';
+var appCache = 'hedgehog-app-' + version;
+var mediaCache = 'hedgehog-media';
 var filesToCache = [
   './',
   './index.html',
-  './hedgehog.js',
-  './hedgehog_bg.wasm',
+  './manifest.json',
+  './favicon.ico',
+  './icon-256.png',
 ];
 
-/* Start the service worker and cache all of the app's content */
+/* Avatars and media are kept until there are this many, then the oldest go first. */
+var maxMediaEntries = 500;
+/* Anything bigger than this (usually video) is left to the browser's own cache. */
+var maxMediaBytes = 5 * 1024 * 1024;
+var mediaPattern = /\.(png|jpe?g|gif|webp|avif)$/i;
+
+/* Start the service worker and cache the app's shell. The wasm and js have hashed names, so they
+   are cached as they're fetched. */
 self.addEventListener('install', function (e) {
   e.waitUntil(
-    caches.open(cacheName).then(function (cache) {
+    caches.open(appCache).then(function (cache) {
       return cache.addAll(filesToCache);
     })
   );
 });
 
-/* Serve cached content when offline */
-self.addEventListener('fetch', function (e) {
-  e.respondWith(
-    caches.match(e.request).then(function (response) {
-      return response || fetch(e.request);
+/* Once this version is in charge, drop the caches of the ones before it. */
+self.addEventListener('activate', function (e) {
+  e.waitUntil(
+    caches.keys().then(function (names) {
+      return Promise.all(names
+        .filter(function (name) {
+          return name.startsWith('hedgehog-app-') && name !== appCache;
+        })
+        .map(function (name) {
+          return caches.delete(name);
+        }));
+    }).then(function () {
+      return self.clients.claim();
     })
   );
 });
+
+/* The page asks the waiting worker to take over when the user chooses to reload. */
+self.addEventListener('message', function (e) {
+  if (e.data === 'skipWaiting') {
+    self.skipWaiting();
+  }
+});
+
+self.addEventListener('fetch', function (e) {
+  var request = e.request;
+  if (request.method !== 'GET') {
+    return;
+  }
+  var url = new URL(request.url);
+  if (url.origin === self.location.origin) {
+    e.respondWith(networkFirst(request));
+  } else if (mediaPattern.test(url.pathname)) {
+    e.respondWith(cacheFirst(request));
+  }
+  /* Everything else, like the Mastodon API, always goes to the network. */
+});
+
+/* The app itself: fetch the latest, and fall back to the cache when offline. */
+function networkFirst(request) {
+  return fetch(request).then(function (response) {
+    if (response.ok) {
+      var copy = response.clone();
+      caches.open(appCache).then(function (cache) {
+        cache.put(request, copy);
+      });
+    }
+    return response;
+  }).catch(function () {
+    return caches.match(request).then(function (cached) {
+      return cached || Response.error();
+    });
+  });
+}
+
+/* Avatars and media don't change once uploaded, so the cache is good enough when it has them. */
+function cacheFirst(request) {
+  return caches.open(mediaCache).then(function (cache) {
+    return cache.match(request).then(function (cached) {
+      if (cached) {
+        return cached;
+      }
+      return fetch(request).then(function (response) {
+        var length = Number(response.headers.get('Content-Length') || 0);
+        if (response.ok && length <= maxMediaBytes) {
+          cache.put(request, response.clone()).then(function () {
+            return trimMedia(cache);
+          });
+        }
+        return response;
+      });
+    });
+  });
+}
+
+/* Keys come back in the order they were added, so the oldest are at the front. */
+function trimMedia(cache) {
+  return cache.keys().then(function (keys) {
+    var excess = keys.slice(0, Math.max(0, keys.length - maxMediaEntries));
+    return Promise.all(excess.map(function (key) {
+      return cache.delete(key);
+    }));
+  });
+}
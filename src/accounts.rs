@@ -0,0 +1,227 @@
+use std::{collections::HashMap, fmt};
+
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use serde::de::IgnoredAny;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_json, send_page, ApiPage, RawApi},
+    channels::{cancellable, send_reply, Message, Spawner},
+    ratelimit::Priority,
+    registry::{Service, ServiceFuture},
+};
+
+/// Profiles, follows and follow requests.
+pub struct AccountsService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for AccountsService {
+    type Message = AccountsMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<AccountsMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_accounts_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_accounts_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<AccountsMessage>>,
+) {
+    let mut state = AccountsState {
+        api: RawApi::new(&mastodon),
+        pages: HashMap::new(),
+    };
+
+    debug!("entered accounts service");
+
+    loop {
+        // wait for messages
+        match rx.recv().await {
+            Some(rx) => match rx {
+                Message::Request { msg, mut reply } => {
+                    let Some(result) = cancellable(&mut reply, state.handle(msg)).await else {
+                        debug!("Accounts request was cancelled");
+                        continue;
+                    };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Accounts request failed: {}", e);
+                            AccountsMessage::Error(e)
+                        }
+                    };
+                    if send_reply(reply, response).is_err() {
+                        warn!("Failed to send accounts reply");
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Accounts service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+/// A paged list of accounts that can be browsed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AccountList {
+    Followers(String),
+    Following(String),
+    FollowRequests,
+}
+
+impl fmt::Display for AccountList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountList::Followers(_) => write!(f, "followers"),
+            AccountList::Following(_) => write!(f, "following"),
+            AccountList::FollowRequests => write!(f, "follow requests"),
+        }
+    }
+}
+
+/// An account alongside the signed in user's relationship to it.
+#[derive(Clone, Debug)]
+pub struct AccountRow {
+    pub account: Account,
+    pub relationship: Option<Relationship>,
+}
+
+#[derive(Debug)]
+pub enum AccountsMessage {
+    /// Fetch the first page of a list, discarding any pages loaded before.
+    LoadList(AccountList),
+    /// Fetch the page following the last one loaded for a list.
+    NextPage(AccountList),
+    ListPage {
+        list: AccountList,
+        rows: Vec<AccountRow>,
+        has_more: bool,
+        replace: bool,
+    },
+    Follow(AccountId),
+    Unfollow(AccountId),
+    RelationshipUpdated(Relationship),
+    AuthorizeFollowRequest(AccountId),
+    RejectFollowRequest(AccountId),
+    FollowRequestResolved(AccountId),
+    Error(String),
+}
+
+struct AccountsState {
+    api: RawApi,
+    /// The url of each list's next page, or `None` once there are no more.
+    pages: HashMap<AccountList, Option<String>>,
+}
+
+impl AccountList {
+    fn path(&self) -> String {
+        match self {
+            AccountList::Followers(id) => format!("/api/v1/accounts/{}/followers", id),
+            AccountList::Following(id) => format!("/api/v1/accounts/{}/following", id),
+            AccountList::FollowRequests => "/api/v1/follow_requests".to_string(),
+        }
+    }
+}
+
+impl AccountsState {
+    async fn handle(&mut self, msg: AccountsMessage) -> Result<AccountsMessage, String> {
+        match msg {
+            AccountsMessage::LoadList(list) => {
+                let page: ApiPage<Account> = send_page(self.api.get(&list.path()), Priority::User)
+                    .await
+                    .map_err(|e| format!("Failed to load {}: {}", list, e))?;
+                self.pages.insert(list.clone(), page.next);
+                let has_more = !page.items.is_empty();
+                let rows = self.with_relationships(page.items).await?;
+                Ok(AccountsMessage::ListPage {
+                    list,
+                    rows,
+                    has_more,
+                    replace: true,
+                })
+            }
+            AccountsMessage::NextPage(list) => {
+                let next = self
+                    .pages
+                    .get(&list)
+                    .ok_or_else(|| format!("No {} have been loaded yet", list))?;
+                let page: ApiPage<Account> = match next {
+                    Some(url) => send_page(self.api.follow(url), Priority::User)
+                        .await
+                        .map_err(|e| format!("Failed to load more {}: {}", list, e))?,
+                    None => Default::default(),
+                };
+                self.pages.insert(list.clone(), page.next);
+                let has_more = !page.items.is_empty();
+                let rows = self.with_relationships(page.items).await?;
+                Ok(AccountsMessage::ListPage {
+                    list,
+                    rows,
+                    has_more,
+                    replace: false,
+                })
+            }
+            AccountsMessage::Follow(id) => {
+                send_json(self.api.post(&format!("/api/v1/accounts/{}/follow", id)))
+                    .await
+                    .map(AccountsMessage::RelationshipUpdated)
+                    .map_err(|e| format!("Failed to follow: {}", e))
+            }
+            AccountsMessage::Unfollow(id) => {
+                send_json(self.api.post(&format!("/api/v1/accounts/{}/unfollow", id)))
+                    .await
+                    .map(AccountsMessage::RelationshipUpdated)
+                    .map_err(|e| format!("Failed to unfollow: {}", e))
+            }
+            AccountsMessage::AuthorizeFollowRequest(id) => {
+                let _: IgnoredAny = send_json(
+                    self.api
+                        .post(&format!("/api/v1/follow_requests/{}/authorize", id)),
+                )
+                .await
+                .map_err(|e| format!("Failed to accept follow request: {}", e))?;
+                Ok(AccountsMessage::FollowRequestResolved(id))
+            }
+            AccountsMessage::RejectFollowRequest(id) => {
+                let _: IgnoredAny = send_json(
+                    self.api
+                        .post(&format!("/api/v1/follow_requests/{}/reject", id)),
+                )
+                .await
+                .map_err(|e| format!("Failed to reject follow request: {}", e))?;
+                Ok(AccountsMessage::FollowRequestResolved(id))
+            }
+            other => Err(format!("Unexpected accounts request: {:?}", other)),
+        }
+    }
+
+    /// Look up relationships for a page of accounts in one batched call.
+    async fn with_relationships(&self, accounts: Vec<Account>) -> Result<Vec<AccountRow>, String> {
+        if accounts.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids: Vec<(&str, String)> = accounts
+            .iter()
+            .map(|a| ("id[]", a.id.to_string()))
+            .collect();
+        let relationships: Vec<Relationship> =
+            send_json(self.api.get("/api/v1/accounts/relationships").query(&ids))
+                .await
+                .map_err(|e| format!("Failed to load relationships: {}", e))?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| {
+                let relationship = relationships.iter().find(|r| r.id == account.id).cloned();
+                AccountRow {
+                    account,
+                    relationship,
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,294 @@
+//! Requests to the instance, sent directly rather than through mastodon-async so that they all
+//! keep to the rate limit and show up in diagnostics.
+
+use std::fmt;
+
+use instant::{Duration, Instant};
+use log::debug;
+use mastodon_async::Mastodon;
+use reqwest::{header::HeaderMap, Method, Request, RequestBuilder};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    authenticate::build_http_client,
+    diagnostics,
+    ratelimit::{self, EndpointClass, Priority},
+    timer::sleep,
+};
+
+#[derive(Clone)]
+pub struct RawApi {
+    client: reqwest::Client,
+    base: String,
+    token: String,
+}
+
+impl RawApi {
+    pub fn new(mastodon: &Mastodon) -> Self {
+        RawApi {
+            client: build_http_client().expect("Unable to build http client"),
+            base: mastodon.data.base.trim_end_matches('/').to_string(),
+            token: mastodon.data.token.to_string(),
+        }
+    }
+
+    /// Start an authenticated request. `path` is relative to the instance, e.g. `/api/v1/mutes`.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.base, path))
+            .bearer_auth(&self.token)
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.request(Method::POST, path)
+    }
+
+    /// Start an authenticated request to a full url, such as the next page's from a `Link`.
+    pub fn follow(&self, url: &str) -> RequestBuilder {
+        self.client.get(url).bearer_auth(&self.token)
+    }
+
+    /// Start a request for something that may be on another server, like an image, which isn't
+    /// given the token.
+    pub fn fetch(&self, url: &str) -> RequestBuilder {
+        self.client.get(url)
+    }
+}
+
+/// One page of a list the server splits up, and where to find the next.
+#[derive(Debug)]
+pub struct ApiPage<T> {
+    pub items: Vec<T>,
+    /// The url of the next page, from the `Link` header. `None` once there are no more.
+    pub next: Option<String>,
+}
+
+impl<T> Default for ApiPage<T> {
+    fn default() -> Self {
+        ApiPage {
+            items: vec![],
+            next: None,
+        }
+    }
+}
+
+/// Why a request failed, and whether sending it again later could work.
+#[derive(Debug)]
+pub struct ApiError {
+    pub message: String,
+    /// The server couldn't be reached, or was too busy to answer.
+    pub retryable: bool,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Like `send_json`, but keeps track of whether the failure was temporary.
+pub async fn try_send_json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ApiError> {
+    try_send_json_as(request, Priority::User).await
+}
+
+/// How often to send a request again after a temporary failure, if it's safe to.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts after the first.
+    pub retries: u32,
+    /// The wait before the first retry, doubled for each after that.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// For callers that retry in their own way, like the outbox.
+    pub const NONE: RetryPolicy = RetryPolicy {
+        retries: 0,
+        backoff: Duration::ZERO,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Whether sending a request twice does no more than sending it once.
+fn is_idempotent(request: &Request) -> bool {
+    matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    ) || request.headers().contains_key("Idempotency-Key")
+}
+
+/// Like `try_send_json`, for requests that can wait if the instance's rate limit is running low.
+pub async fn try_send_json_as<T: DeserializeOwned>(
+    request: RequestBuilder,
+    priority: Priority,
+) -> Result<T, ApiError> {
+    try_send_json_with(request, priority, RetryPolicy::default()).await
+}
+
+/// Like `try_send_json_as`, retrying idempotent requests as `policy` says.
+pub async fn try_send_json_with<T: DeserializeOwned>(
+    request: RequestBuilder,
+    priority: Priority,
+    policy: RetryPolicy,
+) -> Result<T, ApiError> {
+    let (value, _) = send_with(request, priority, policy).await?;
+    Ok(value)
+}
+
+/// Fetch a page of a list, along with the link to the next one.
+pub async fn send_page<T: DeserializeOwned>(
+    request: RequestBuilder,
+    priority: Priority,
+) -> Result<ApiPage<T>, String> {
+    let (items, headers) = send_with(request, priority, RetryPolicy::default())
+        .await
+        .map_err(|e| e.message)?;
+    let next = headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(next_link);
+    Ok(ApiPage { items, next })
+}
+
+/// The `rel="next"` url in a `Link` header, like
+/// `<https://example.social/api/v1/mutes?max_id=12>; rel="next", <…>; rel="prev"`.
+pub fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        let is_next = params.split(';').any(|param| {
+            let param = param.trim().replace(' ', "");
+            param == "rel=\"next\"" || param == "rel=next"
+        });
+        let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+        is_next.then(|| url.to_string())
+    })
+}
+
+/// Fetch the body of a response as it is, such as an image.
+pub async fn send_bytes(request: RequestBuilder, priority: Priority) -> Result<Vec<u8>, String> {
+    let (body, _) = send_raw(request, priority, RetryPolicy::default())
+        .await
+        .map_err(|e| e.message)?;
+    Ok(body)
+}
+
+async fn send_with<T: DeserializeOwned>(
+    request: RequestBuilder,
+    priority: Priority,
+    policy: RetryPolicy,
+) -> Result<(T, HeaderMap), ApiError> {
+    let (body, headers) = send_raw(request, priority, policy).await?;
+    let value = serde_json::from_slice::<T>(&body).map_err(|e| ApiError {
+        message: format!("Unexpected response: {}", e),
+        retryable: false,
+    })?;
+    Ok((value, headers))
+}
+
+async fn send_raw(
+    request: RequestBuilder,
+    priority: Priority,
+    policy: RetryPolicy,
+) -> Result<(Vec<u8>, HeaderMap), ApiError> {
+    let (client, request) = request.build_split();
+    let request = request.map_err(|e| ApiError {
+        message: format!("Bad request: {}", e),
+        retryable: false,
+    })?;
+    let retries = if is_idempotent(&request) {
+        policy.retries
+    } else {
+        0
+    };
+    let mut backoff = policy.backoff;
+    for _ in 0..retries {
+        // Requests with streamed bodies can't be copied, so only get the one try.
+        let Some(attempt) = request.try_clone() else {
+            break;
+        };
+        match send_once(&client, attempt, priority).await {
+            Err(e) if e.retryable => {
+                debug!("Retrying in {:?} after: {}", backoff, e);
+                sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    send_once(&client, request, priority).await
+}
+
+async fn send_once(
+    client: &reqwest::Client,
+    request: Request,
+    priority: Priority,
+) -> Result<(Vec<u8>, HeaderMap), ApiError> {
+    let limiter = ratelimit::for_url(request.url());
+    let class = EndpointClass::of(request.method(), request.url().path());
+    limiter.wait(class, priority).await;
+
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let started = Instant::now();
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            let message = format!("Request failed: {}", e);
+            diagnostics::record_http(&method, &url, started.elapsed(), Err(&message), None);
+            return Err(ApiError {
+                message,
+                retryable: true,
+            });
+        }
+    };
+    let status = response.status();
+    let headers = response.headers().clone();
+    limiter.record(class, status, &headers);
+    let body = response.bytes().await;
+    let elapsed = started.elapsed();
+    // Images and the like aren't text, and are left out.
+    let text = body
+        .as_ref()
+        .ok()
+        .and_then(|body| std::str::from_utf8(body).ok());
+    diagnostics::record_http(&method, &url, elapsed, Ok((status, &headers)), text);
+    if !status.is_success() {
+        return Err(ApiError {
+            message: format!("{}: {}", status, text.unwrap_or_default()),
+            retryable: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        });
+    }
+    let body = body.map_err(|e| ApiError {
+        message: format!("Unexpected response: {}", e),
+        retryable: false,
+    })?;
+    Ok((body.to_vec(), headers))
+}
+
+/// Send a request and deserialize the response body, turning http errors into messages.
+pub async fn send_json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, String> {
+    try_send_json(request).await.map_err(|e| e.message)
+}
+
+/// Like `send_json`, for requests that can wait if the instance's rate limit is running low.
+pub async fn send_json_as<T: DeserializeOwned>(
+    request: RequestBuilder,
+    priority: Priority,
+) -> Result<T, String> {
+    try_send_json_as(request, priority)
+        .await
+        .map_err(|e| e.message)
+}
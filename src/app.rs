@@ -1,13 +1,19 @@
-use std::sync::mpsc::{Receiver, Sender};
-
-use log::debug;
+use log::{debug, warn};
 
 use crate::{
-    authenticate::AuthMessage,
-    channels::{AsyncRequestBridge, AsyncRequestBridgeState},
-    service::AsyncServiceMessage,
+    authenticate::{AuthMessage, AuthService},
+    channels::{wake_ui_with, AsyncRequestBridge},
+    drafts::DraftStore,
+    launch::LaunchAction,
+    outbox::OutboxStore,
+    registry::Registry,
+    service::{EchoMessage, EchoService},
+    views::{diagnostics::DiagnosticsWindow, SessionView},
 };
 
+/// How long services get to finish up when the app closes.
+const SHUTDOWN_DEADLINE: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
@@ -15,26 +21,38 @@ pub struct TemplateApp {
     // Example stuff:
     label: String,
     instance: String,
+    drafts: DraftStore,
+    outbox: OutboxStore,
 
     #[serde(skip)] // This how you opt-out of serialization of a field
     value: f32,
 
-    #[serde(skip)] // This how you opt-out of serialization of a field
-    async_bridge: Option<AsyncRequestBridge<AsyncServiceMessage, AsyncAppState>>, // #[serde(skip)]
-                                                                                  // tx: Sender<AsyncMessage>,
+    /// What the app was opened to do, carried out once signed in.
+    #[serde(skip)]
+    launch: Option<LaunchAction>,
 
-                                                                                  // #[serde(skip)]
-                                                                                  // rx: Receiver<AsyncMessage>,
-}
+    #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
+    history: crate::web::History,
 
-pub struct AsyncAppState {
+    #[serde(skip)]
+    services: Option<Registry>,
+
+    #[serde(skip)]
+    echo_bridge: Option<AsyncRequestBridge<EchoMessage, u32>>,
+
+    /// Set once the user picks an instance to log in to.
+    #[serde(skip)]
     auth_bridge: Option<AsyncRequestBridge<AuthMessage, AuthUiState>>,
-    counter: u32,
+
+    #[serde(skip)]
+    diagnostics: DiagnosticsWindow,
 }
 
 pub enum AuthUiState {
     WaitingForAuthCode { auth_url: String, auth_code: String },
-    SignedIn,
+    SignedIn(Box<SessionView>),
+    Failed(String),
 }
 
 impl Default for TemplateApp {
@@ -43,8 +61,16 @@ impl Default for TemplateApp {
             // Example stuff:
             label: "Hello World!".to_owned(),
             instance: "".to_owned(),
+            drafts: Default::default(),
+            outbox: Default::default(),
             value: 2.7,
-            async_bridge: None,
+            launch: None,
+            #[cfg(target_arch = "wasm32")]
+            history: Default::default(),
+            services: None,
+            echo_bridge: None,
+            auth_bridge: None,
+            diagnostics: Default::default(),
         }
     }
 }
@@ -53,7 +79,8 @@ impl TemplateApp {
     /// Called once before the first frame.
     pub fn new(
         cc: &eframe::CreationContext<'_>,
-        async_bridge: AsyncRequestBridge<AsyncServiceMessage, AsyncAppState>,
+        services: Registry,
+        launch: Option<LaunchAction>,
     ) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
@@ -65,9 +92,26 @@ impl TemplateApp {
         } else {
             Default::default()
         };
-        app.async_bridge = Some(async_bridge);
+        app.echo_bridge = services.bridge::<EchoService, _>();
+        app.services = Some(services);
+        app.launch = launch;
+        // Services wake the ui when they answer, rather than it polling them every frame.
+        wake_ui_with(&cc.egui_ctx);
+        // Decodes the avatars and media each session's `ImageLoader` fetches.
+        egui_extras::install_image_loaders(&cc.egui_ctx);
         app
     }
+
+    /// Start without eframe or anything saved, e.g. in tests. Call `channels::wake_ui_with` to
+    /// have services wake the ui when they answer.
+    pub fn with_services(services: Registry, launch: Option<LaunchAction>) -> Self {
+        TemplateApp {
+            echo_bridge: services.bridge::<EchoService, _>(),
+            services: Some(services),
+            launch,
+            ..Default::default()
+        }
+    }
 }
 
 impl eframe::App for TemplateApp {
@@ -76,23 +120,54 @@ impl eframe::App for TemplateApp {
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
+    /// Save often, so drafts survive the window being closed unexpectedly.
+    fn auto_save_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(5)
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.ui(ctx);
+    }
+
+    /// Called once the window is closing, after the last `save`.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.shutdown();
+    }
+}
+
+impl TemplateApp {
+    pub fn auth_bridge(&self) -> Option<&AsyncRequestBridge<AuthMessage, AuthUiState>> {
+        self.auth_bridge.as_ref()
+    }
+
+    /// Stop the services, letting them finish caching first.
+    ///
+    /// The outbox doesn't send anything while closing, as eframe has already saved for the last
+    /// time and the results would be lost. Whatever is pending is sent on the next run.
+    pub fn shutdown(&mut self) {
+        // Dropping the session's bridges closes the channels to its services, which is their
+        // cue to stop.
+        self.auth_bridge = None;
+        self.echo_bridge = None;
+        if let Some(services) = self.services.take() {
+            services.shutdown(SHUTDOWN_DEADLINE);
+        }
+    }
+
+    /// Everything `update` draws. It doesn't need an `eframe::Frame`, so it can run headless.
+    pub fn ui(&mut self, ctx: &egui::Context) {
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
-        if let Some(br) = &mut self.async_bridge {
-            if br.pump_messages() {
+        if let Some(echo_bridge) = &mut self.echo_bridge {
+            if echo_bridge.pump_messages() {
                 ctx.request_repaint();
             }
-            if let AsyncRequestBridgeState::Complete(AsyncAppState {
-                auth_bridge: Some(auth_bridge),
-                ..
-            }) = &mut br.state
-            {
-                if auth_bridge.pump_messages() {
-                    ctx.request_repaint()
-                }
+        }
+        if let Some(auth_bridge) = &mut self.auth_bridge {
+            if auth_bridge.pump_messages() {
+                ctx.request_repaint()
             }
         }
 
@@ -112,18 +187,37 @@ impl eframe::App for TemplateApp {
                 }
 
                 egui::widgets::global_dark_light_mode_buttons(ui);
+                ui.add_space(16.0);
+                ui.toggle_value(&mut self.diagnostics.open, "Diagnostics");
+
+                #[cfg(target_arch = "wasm32")]
+                {
+                    if crate::web::update_ready() {
+                        ui.add_space(16.0);
+                        ui.label("A new version of hedgehog is available.");
+                        if ui.button("Reload").clicked() {
+                            crate::web::apply_update();
+                        }
+                    } else {
+                        // Nothing tells egui when the service worker finishes installing an
+                        // update, so look again every so often.
+                        ctx.request_repaint_after(std::time::Duration::from_secs(60));
+                    }
+                }
             });
         });
 
+        self.diagnostics.ui(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
             ui.heading("eframe template");
 
-            if let Some(async_bridge) = &mut self.async_bridge {
+            if let Some(echo_bridge) = &mut self.echo_bridge {
                 ui.horizontal(|ui| {
                     ui.label(format!(
                         "Async state: {:?}",
-                        match &async_bridge.state {
+                        match &echo_bridge.state {
                             crate::channels::AsyncRequestBridgeState::Init =>
                                 "Init (not run yet)".to_string(),
                             crate::channels::AsyncRequestBridgeState::Awaiting {
@@ -134,137 +228,128 @@ impl eframe::App for TemplateApp {
 
                             crate::channels::AsyncRequestBridgeState::Updating =>
                                 "Updating".to_string(),
-                            crate::channels::AsyncRequestBridgeState::Complete(AsyncAppState {
-                                counter: n,
-                                ..
-                            }) => format!("Complete: {}", &n),
+                            crate::channels::AsyncRequestBridgeState::Complete(n) =>
+                                format!("Complete: {}", &n),
                             crate::channels::AsyncRequestBridgeState::Error(e) =>
                                 format!("Error: {}", e),
                         }
                     ));
                     if ui.button("Async invoke").clicked() {
-                        async_bridge.send(
-                            AsyncServiceMessage::Echo(1),
-                            Box::new(|m, prev_state| match (m, prev_state) {
-                                (AsyncServiceMessage::Echo(n), Some(mut prev_app_state)) => {
-                                    prev_app_state.counter += n;
-                                    prev_app_state
-                                }
-                                (AsyncServiceMessage::Echo(n), None) => AsyncAppState {
-                                    auth_bridge: None,
-                                    counter: n,
-                                },
-                                _ => panic!("can't handle this response."),
-                            }),
+                        echo_bridge.send(
+                            EchoMessage(1),
+                            Box::new(|EchoMessage(n), prev_state| prev_state.unwrap_or(0) + n),
                         );
                     }
                 });
-                ui.horizontal(|ui| {
-                    // Is there an authenticated service existing?
-                    if let AsyncRequestBridgeState::Complete(AsyncAppState {
-                        auth_bridge: Some(auth_ui_bridge),
-                        ..
-                    }) = &mut async_bridge.state
-                    {
-                        // It exists, so there is a mastodon instance
-                        match &mut auth_ui_bridge.state {
-                            crate::channels::AsyncRequestBridgeState::Init => {
-                                // In init state, show a button that will continue the login when
-                                // clicked.
-                                if ui
-                                    .button(format!("Continue logging in to {}", &self.instance))
-                                    .clicked()
-                                {
-                                    auth_ui_bridge.send(
-                                        AuthMessage::Initialize(self.instance.clone()),
-                                        Box::new(|m, prev_state| {
-                                            debug!("ui received authorize url");
-                                            match (m, prev_state) {
-                                                (AuthMessage::AuthorizeUrl(url), _) => {
-                                                    AuthUiState::WaitingForAuthCode {
-                                                        auth_url: url,
-                                                        auth_code: "".to_string(),
-                                                    }
+            }
+
+            ui.vertical(|ui| {
+                // Is there an authenticated service existing?
+                if let Some(auth_ui_bridge) = &mut self.auth_bridge {
+                    // It exists, so there is a mastodon instance
+                    match &mut auth_ui_bridge.state {
+                        crate::channels::AsyncRequestBridgeState::Init => {
+                            // In init state, show a button that will continue the login when
+                            // clicked.
+                            if ui
+                                .button(format!("Continue logging in to {}", &self.instance))
+                                .clicked()
+                            {
+                                auth_ui_bridge.send(
+                                    AuthMessage::Initialize(self.instance.clone()),
+                                    Box::new(|m, prev_state| {
+                                        debug!("ui received authorize url");
+                                        match (m, prev_state) {
+                                            (AuthMessage::AuthorizeUrl(url), _) => {
+                                                AuthUiState::WaitingForAuthCode {
+                                                    auth_url: url,
+                                                    auth_code: "".to_string(),
                                                 }
-                                                _ => panic!("can't handle this response."),
                                             }
-                                        }),
-                                    );
-                                }
-                            }
-                            crate::channels::AsyncRequestBridgeState::Awaiting {
-                                response,
-                                prev_state,
-                                handler,
-                            } => {
-                                ui.label("Logging in...");
-                            }
-                            crate::channels::AsyncRequestBridgeState::Updating => {
-                                ui.label("updating");
+                                            (AuthMessage::Error(e), _) => AuthUiState::Failed(e),
+                                            _ => panic!("can't handle this response."),
+                                        }
+                                    }),
+                                );
                             }
-                            crate::channels::AsyncRequestBridgeState::Complete(
-                                AuthUiState::WaitingForAuthCode {
-                                    auth_url,
-                                    ref mut auth_code,
-                                },
-                            ) => {
-                                ui.hyperlink_to("get auth code from here", auth_url);
+                        }
+                        crate::channels::AsyncRequestBridgeState::Awaiting {
+                            response,
+                            prev_state,
+                            handler,
+                        } => {
+                            ui.label("Logging in...");
+                        }
+                        crate::channels::AsyncRequestBridgeState::Updating => {
+                            ui.label("updating");
+                        }
+                        crate::channels::AsyncRequestBridgeState::Complete(
+                            AuthUiState::WaitingForAuthCode {
+                                auth_url,
+                                ref mut auth_code,
+                            },
+                        ) => {
+                            ui.hyperlink_to("get auth code from here", auth_url);
 
+                            let mut submit = false;
+                            ui.horizontal(|ui| {
                                 ui.label("and paste it here:");
                                 ui.text_edit_singleline(auth_code);
+                                submit = ui.button("Sign in").clicked();
+                            });
+                            if submit {
+                                let auth_code = auth_code.clone();
+                                auth_ui_bridge.send(
+                                    AuthMessage::CompleteAuth(auth_code),
+                                    Box::new(|m, _| match m {
+                                        AuthMessage::SignedIn(channels) => AuthUiState::SignedIn(
+                                            Box::new(SessionView::new(channels)),
+                                        ),
+                                        AuthMessage::Error(e) => AuthUiState::Failed(e),
+                                        _ => panic!("can't handle this response."),
+                                    }),
+                                );
                             }
-                            crate::channels::AsyncRequestBridgeState::Complete(
-                                AuthUiState::SignedIn,
-                            ) => {
-                                ui.label(format!("complete. signed in"));
-                            }
-                            crate::channels::AsyncRequestBridgeState::Error(e) => {
-                                ui.label(format!("error: {}", e));
+                        }
+                        crate::channels::AsyncRequestBridgeState::Complete(
+                            AuthUiState::SignedIn(session),
+                        ) => {
+                            session.ui(ui, &mut self.drafts, &mut self.outbox);
+                            if let Some(action) = self.launch.take() {
+                                session.launch(action, &mut self.drafts);
                             }
+                            #[cfg(target_arch = "wasm32")]
+                            self.history.sync(ui.ctx(), session);
                         }
-                    } else {
-                        // There's no backend yet. Allow specifying and connecting to one
+                        crate::channels::AsyncRequestBridgeState::Complete(
+                            AuthUiState::Failed(e),
+                        ) => {
+                            ui.label(format!("error: {}", e));
+                        }
+                        crate::channels::AsyncRequestBridgeState::Error(e) => {
+                            ui.label(format!("error: {}", e));
+                        }
+                    }
+                } else {
+                    // There's no backend yet. Allow specifying and connecting to one
+                    let mut log_in = false;
+                    ui.horizontal(|ui| {
                         ui.label("Instance:");
                         ui.text_edit_singleline(&mut self.instance);
-                        if ui.button("Log in").clicked() {
-                            debug!("Send start auth message");
-                            async_bridge.send(
-                                AsyncServiceMessage::StartAuth,
-                                Box::new(|m, prev_state| {
-                                    debug!("Received reply for start auth service");
-                                    match (m, prev_state) {
-                                        // Upon receiving a channel for our attempted login, update the
-                                        // state. If there is state already, add it to that
-                                        (AsyncServiceMessage::AuthChannel(ac), Some(mut p)) => {
-                                            p.auth_bridge = Some(AsyncRequestBridge::<
-                                                AuthMessage,
-                                                AuthUiState,
-                                            >::new(
-                                                ac
-                                            ));
-
-                                            p
-                                        }
-                                        // Or create async app state
-                                        (AsyncServiceMessage::AuthChannel(ac), None) => {
-                                            AsyncAppState {
-                                                auth_bridge: Some(AsyncRequestBridge::<
-                                                    AuthMessage,
-                                                    AuthUiState,
-                                                >::new(
-                                                    ac
-                                                )),
-                                                counter: 0,
-                                            }
-                                        }
-                                        _ => panic!("can't handle this response."),
-                                    }
-                                }),
-                            );
+                        log_in = ui.button("Log in").clicked();
+                    });
+                    if log_in {
+                        debug!("Start logging in");
+                        self.auth_bridge = self
+                            .services
+                            .as_ref()
+                            .and_then(|services| services.bridge::<AuthService, _>());
+                        if self.auth_bridge.is_none() {
+                            warn!("There's no auth service to log in with");
                         }
                     }
-                });
-            }
+                }
+            });
 
             ui.horizontal(|ui| {
                 ui.label("Write something: ");
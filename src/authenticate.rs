@@ -1,17 +1,32 @@
+use std::borrow::Cow;
+
 use instant::Duration;
 
 use log::{debug, warn};
-use mastodon_async::{Mastodon, Registration};
+use mastodon_async::{prelude::Account, Data, Mastodon};
+use reqwest::Url;
+use serde::Deserialize;
 use tokio::sync::mpsc;
 
-use crate::channels::Message;
+use crate::{
+    api::{send_json, RawApi},
+    channels::{send_reply, Message, Spawner},
+    registry::{Service, ServiceFuture},
+    session::{start_session_services, SessionChannels},
+};
+
+/// Registers hedgehog with an instance and signs in, starting the session's services.
+pub struct AuthService;
+
+impl Service for AuthService {
+    type Message = AuthMessage;
 
-#[cfg(not(target_arch = "wasm"))]
-use tokio::time::*;
-#[cfg(target_arch = "wasm")]
-use wasmtimer::tokio::*;
+    fn run(self, rx: mpsc::Receiver<Message<AuthMessage>>, spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_auth_service(rx, spawner))
+    }
+}
 
-pub async fn start_auth_service(mut rx: mpsc::Receiver<Message<AuthMessage>>) {
+pub async fn start_auth_service(mut rx: mpsc::Receiver<Message<AuthMessage>>, spawner: Spawner) {
     let mut state: AuthState = Default::default();
 
     debug!("entered auth service");
@@ -24,16 +39,56 @@ pub async fn start_auth_service(mut rx: mpsc::Receiver<Message<AuthMessage>>) {
                     AuthMessage::Initialize(instance) => {
                         debug!("Initializing masto client");
                         let client = build_http_client().unwrap();
-                        let registration = Registration::new_with_client(instance, client)
-                            .client_name("hedgehog")
-                            .redirect_uris("urn:ietf:wg:oauth:2.0:oob")
-                            .build()
-                            .await
-                            .unwrap();
+                        #[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+                        let instance = match crate::record::from_env(&instance) {
+                            Some(recording) => {
+                                let base = recording.base.clone();
+                                state.recording = Some(recording);
+                                base
+                            }
+                            None => instance,
+                        };
+                        let registration = match register(instance, client).await {
+                            Ok(registration) => registration,
+                            Err(e) => {
+                                if send_reply(reply, AuthMessage::Error(e)).is_err() {
+                                    warn!("Failed to send auth reply");
+                                }
+                                continue;
+                            }
+                        };
                         debug!("registration created");
-                        let url = registration.authorize_url().unwrap();
+                        let url = registration.authorize_url();
+                        // The user signs in with their browser, which isn't recorded.
+                        #[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+                        let url = match &state.recording {
+                            Some(recording) => recording.upstream_url(&url),
+                            None => url,
+                        };
                         debug!("authorize url: {}", &url);
-                        reply.send(AuthMessage::AuthorizeUrl(url)).unwrap();
+                        state.registration = Some(registration);
+                        send_reply(reply, AuthMessage::AuthorizeUrl(url)).unwrap();
+                    }
+                    AuthMessage::CompleteAuth(code) => {
+                        let response = match complete_auth(&state, code.trim()).await {
+                            Ok(mastodon) => match verify_credentials(&mastodon).await {
+                                Ok(account) => {
+                                    debug!("signed in as {}", &account.acct);
+                                    state.mastodon = Some(mastodon.clone());
+                                    AuthMessage::SignedIn(start_session_services(
+                                        mastodon, account, &spawner,
+                                    ))
+                                }
+                                Err(e) => AuthMessage::Error(format!(
+                                    "Failed to verify credentials: {}",
+                                    e
+                                )),
+                            },
+                            Err(e) => AuthMessage::Error(e),
+                        };
+                        if send_reply(reply, response).is_err() {
+                            warn!("Failed to send auth reply");
+                        }
                     }
                     _ => {}
                 },
@@ -52,15 +107,115 @@ pub enum AuthMessage {
     Initialize(String),
     MastodonData(String),
     AuthorizeUrl(String),
+    /// Exchange the code the user copied from the authorize url for a token.
+    CompleteAuth(String),
+    SignedIn(SessionChannels),
+    Error(String),
+}
+
+/// Where the user is sent to sign in, which gives them a code to paste back.
+const REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+const SCOPES: &str = "read write follow push";
+
+/// Hedgehog's credentials with an instance, before anyone has signed in.
+struct Registered {
+    base: String,
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+}
+
+impl Registered {
+    fn authorize_url(&self) -> String {
+        let params = [
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("response_type", "code"),
+            ("scope", SCOPES),
+        ];
+        match Url::parse_with_params(&format!("{}/oauth/authorize", self.base), &params) {
+            Ok(url) => url.to_string(),
+            Err(_) => format!("{}/oauth/authorize", self.base),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct App {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
 }
 
 #[derive(Default)]
 struct AuthState {
+    registration: Option<Registered>,
     mastodon: Option<Mastodon>,
+    /// Set by `HEDGEHOG_RECORD_FIXTURE`, and kept for as long as the session.
+    #[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+    recording: Option<crate::record::Recording>,
+}
+
+/// Register hedgehog as an app with the instance at `base`.
+async fn register(base: String, client: reqwest::Client) -> Result<Registered, String> {
+    let base = base.trim_end_matches('/').to_string();
+    let app: App = send_json(client.post(format!("{}/api/v1/apps", base)).form(&[
+        ("client_name", "hedgehog"),
+        ("redirect_uris", REDIRECT_URI),
+        ("scopes", SCOPES),
+    ]))
+    .await
+    .map_err(|e| format!("Failed to register with {}: {}", base, e))?;
+    Ok(Registered {
+        base,
+        client,
+        client_id: app.client_id,
+        client_secret: app.client_secret,
+    })
+}
+
+async fn complete_auth(state: &AuthState, code: &str) -> Result<Mastodon, String> {
+    let registration = state
+        .registration
+        .as_ref()
+        .ok_or_else(|| "Login was not started".to_string())?;
+    let token: Token = send_json(
+        registration
+            .client
+            .post(format!("{}/oauth/token", registration.base))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("client_id", &registration.client_id),
+                ("client_secret", &registration.client_secret),
+                ("redirect_uri", REDIRECT_URI),
+                ("code", code),
+            ]),
+    )
+    .await
+    .map_err(|e| format!("Failed to complete login: {}", e))?;
+    Ok(Mastodon::new(
+        registration.client.clone(),
+        Data {
+            base: Cow::Owned(registration.base.clone()),
+            client_id: Cow::Owned(registration.client_id.clone()),
+            client_secret: Cow::Owned(registration.client_secret.clone()),
+            redirect: Cow::Borrowed(REDIRECT_URI),
+            token: Cow::Owned(token.access_token),
+        },
+    ))
+}
+
+async fn verify_credentials(mastodon: &Mastodon) -> Result<Account, String> {
+    let api = RawApi::new(mastodon);
+    send_json(api.get("/api/v1/accounts/verify_credentials")).await
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn build_http_client() -> reqwest::Result<reqwest::Client> {
+pub(crate) fn build_http_client() -> reqwest::Result<reqwest::Client> {
     // Outside of a browser, we must provide a user agent, or some servers will reject us (such as
     // GTS)
     reqwest::Client::builder()
@@ -69,7 +224,7 @@ fn build_http_client() -> reqwest::Result<reqwest::Client> {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn build_http_client() -> reqwest::Result<reqwest::Client> {
+pub(crate) fn build_http_client() -> reqwest::Result<reqwest::Client> {
     // Inside a browser, providing a user agent will cause a CORS error.
     reqwest::Client::builder().build()
 }
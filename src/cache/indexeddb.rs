@@ -0,0 +1,252 @@
+use rexie::{Index, KeyRange, ObjectStore, Rexie, TransactionMode};
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::JsValue;
+
+use super::{CacheError, APP_ID};
+
+/// Bumping this makes the browser upgrade the database to the object stores declared in `open`.
+const SCHEMA_VERSION: u32 = 1;
+
+const ENTRIES: &str = "entries";
+const LISTS: &str = "lists";
+const META: &str = "meta";
+const BY_AGE: &str = "by_age";
+/// The key of the record in `META` that keeps the total size, since IndexedDB can't sum it.
+const SIZE: &str = "size";
+
+/// How many of the oldest entries are dropped at a time while the cache is over its size.
+const EVICTION_BATCH: u32 = 200;
+
+impl From<rexie::Error> for CacheError {
+    fn from(e: rexie::Error) -> Self {
+        // Browsers report running out of quota as a DOMException named QuotaExceededError.
+        let message = format!("{:?}", e);
+        if message.contains("QuotaExceeded") {
+            CacheError::QuotaExceeded
+        } else {
+            CacheError::Storage(e.to_string())
+        }
+    }
+}
+
+fn js_error(e: serde_wasm_bindgen::Error) -> CacheError {
+    CacheError::Storage(e.to_string())
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Entry {
+    /// `kind/id`
+    key: String,
+    fetched_at: i64,
+    size: u64,
+    json: Option<String>,
+    data: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct List {
+    ids: Vec<String>,
+}
+
+/// An IndexedDB database per account, in the browser's storage for the page's origin.
+pub struct Store {
+    db: Rexie,
+}
+
+impl Store {
+    pub async fn open(name: &str) -> Result<Store, CacheError> {
+        let db = Rexie::builder(&format!("{}-cache-{}", APP_ID, name))
+            .version(SCHEMA_VERSION)
+            .add_object_store(
+                ObjectStore::new(ENTRIES)
+                    .key_path("key")
+                    .add_index(Index::new(BY_AGE, "fetched_at")),
+            )
+            .add_object_store(ObjectStore::new(LISTS))
+            .add_object_store(ObjectStore::new(META))
+            .build()
+            .await
+            .map_err(|e| CacheError::Unavailable(e.to_string()))?;
+        Ok(Store { db })
+    }
+
+    pub async fn put(
+        &mut self,
+        kind: &str,
+        entries: Vec<(String, String)>,
+    ) -> Result<(), CacheError> {
+        let entries = entries
+            .into_iter()
+            .map(|(id, json)| Entry {
+                key: format!("{}/{}", kind, id),
+                fetched_at: now(),
+                size: json.len() as u64,
+                json: Some(json),
+                data: None,
+            })
+            .collect();
+        self.put_entries(entries).await
+    }
+
+    pub async fn get(&mut self, kind: &str, ids: &[String]) -> Result<Vec<String>, CacheError> {
+        let tx = self.db.transaction(&[ENTRIES], TransactionMode::ReadOnly)?;
+        let store = tx.store(ENTRIES)?;
+        let mut found = vec![];
+        for id in ids {
+            let value = store
+                .get(&JsValue::from_str(&format!("{}/{}", kind, id)))
+                .await?;
+            if let Some(json) = from_js::<Entry>(value)?.and_then(|entry| entry.json) {
+                found.push(json);
+            }
+        }
+        tx.done().await?;
+        Ok(found)
+    }
+
+    pub async fn put_blob(
+        &mut self,
+        kind: &str,
+        id: &str,
+        data: Vec<u8>,
+    ) -> Result<(), CacheError> {
+        let entry = Entry {
+            key: format!("{}/{}", kind, id),
+            fetched_at: now(),
+            size: data.len() as u64,
+            json: None,
+            data: Some(serde_bytes::ByteBuf::from(data)),
+        };
+        self.put_entries(vec![entry]).await
+    }
+
+    pub async fn get_blob(&mut self, kind: &str, id: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let tx = self.db.transaction(&[ENTRIES], TransactionMode::ReadOnly)?;
+        let value = tx
+            .store(ENTRIES)?
+            .get(&JsValue::from_str(&format!("{}/{}", kind, id)))
+            .await?;
+        tx.done().await?;
+        Ok(from_js::<Entry>(value)?
+            .and_then(|entry| entry.data)
+            .map(|data| data.into_vec()))
+    }
+
+    pub async fn list(&mut self, name: &str) -> Result<Vec<String>, CacheError> {
+        let tx = self.db.transaction(&[LISTS], TransactionMode::ReadOnly)?;
+        let value = tx.store(LISTS)?.get(&JsValue::from_str(name)).await?;
+        tx.done().await?;
+        Ok(from_js::<List>(value)?
+            .map(|list| list.ids)
+            .unwrap_or_default())
+    }
+
+    pub async fn set_list(&mut self, name: &str, ids: &[String]) -> Result<(), CacheError> {
+        let list = to_js(&List { ids: ids.to_vec() })?;
+        let tx = self.db.transaction(&[LISTS], TransactionMode::ReadWrite)?;
+        tx.store(LISTS)?
+            .put(&list, Some(&JsValue::from_str(name)))
+            .await?;
+        tx.done().await?;
+        Ok(())
+    }
+
+    /// Drop entries fetched before `older_than` (a unix timestamp), then the oldest of the rest
+    /// until the cache fits in `max_bytes`.
+    pub async fn evict(&mut self, older_than: i64, max_bytes: u64) -> Result<(), CacheError> {
+        let stale = KeyRange::upper_bound(&JsValue::from_f64(older_than as f64), true)?;
+        self.delete_oldest(Some(&stale), None).await?;
+        while self.size().await? > max_bytes {
+            if self.delete_oldest(None, Some(EVICTION_BATCH)).await? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn size(&mut self) -> Result<u64, CacheError> {
+        let tx = self.db.transaction(&[META], TransactionMode::ReadOnly)?;
+        let value = tx.store(META)?.get(&JsValue::from_str(SIZE)).await?;
+        tx.done().await?;
+        Ok(from_js::<u64>(value)?.unwrap_or(0))
+    }
+
+    pub async fn clear(&mut self) -> Result<(), CacheError> {
+        let tx = self
+            .db
+            .transaction(&[ENTRIES, LISTS, META], TransactionMode::ReadWrite)?;
+        tx.store(ENTRIES)?.clear().await?;
+        tx.store(LISTS)?.clear().await?;
+        tx.store(META)?.clear().await?;
+        tx.done().await?;
+        Ok(())
+    }
+
+    /// Write entries, keeping the total size up to date with whatever they replace.
+    async fn put_entries(&mut self, entries: Vec<Entry>) -> Result<(), CacheError> {
+        let tx = self
+            .db
+            .transaction(&[ENTRIES, META], TransactionMode::ReadWrite)?;
+        let store = tx.store(ENTRIES)?;
+        let meta = tx.store(META)?;
+        let size_key = JsValue::from_str(SIZE);
+        let mut size = from_js::<u64>(meta.get(&size_key).await?)?.unwrap_or(0);
+        for entry in entries {
+            let key = JsValue::from_str(&entry.key);
+            if let Some(old) = from_js::<Entry>(store.get(&key).await?)? {
+                size = size.saturating_sub(old.size);
+            }
+            size += entry.size;
+            store.put(&to_js(&entry)?, None).await?;
+        }
+        meta.put(&to_js(&size)?, Some(&size_key)).await?;
+        tx.done().await?;
+        Ok(())
+    }
+
+    /// Delete the oldest entries, optionally only those in `range` of ages. Returns how many went.
+    async fn delete_oldest(
+        &mut self,
+        range: Option<&KeyRange>,
+        limit: Option<u32>,
+    ) -> Result<usize, CacheError> {
+        let tx = self
+            .db
+            .transaction(&[ENTRIES, META], TransactionMode::ReadWrite)?;
+        let store = tx.store(ENTRIES)?;
+        let meta = tx.store(META)?;
+        let oldest = store
+            .index(BY_AGE)?
+            .get_all(range, limit, None, None)
+            .await?;
+        let size_key = JsValue::from_str(SIZE);
+        let mut size = from_js::<u64>(meta.get(&size_key).await?)?.unwrap_or(0);
+        for (_, value) in &oldest {
+            if let Some(entry) = from_js::<Entry>(value.clone())? {
+                size = size.saturating_sub(entry.size);
+                store.delete(&JsValue::from_str(&entry.key)).await?;
+            }
+        }
+        meta.put(&to_js(&size)?, Some(&size_key)).await?;
+        tx.done().await?;
+        Ok(oldest.len())
+    }
+}
+
+fn now() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, CacheError> {
+    serde_wasm_bindgen::to_value(value).map_err(js_error)
+}
+
+/// IndexedDB gives back `undefined` for missing keys.
+fn from_js<T: DeserializeOwned>(value: JsValue) -> Result<Option<T>, CacheError> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    serde_wasm_bindgen::from_value(value)
+        .map(Some)
+        .map_err(js_error)
+}
@@ -0,0 +1,214 @@
+//! Statuses, accounts, notifications and images kept on disk, so timelines can be shown before the
+//! server has answered.
+//!
+//! The typed `Cache` is the same on every target. Underneath it, `Store` keeps serialized entries
+//! by kind and id, plus ordered lists of ids for each timeline. Natively that's a SQLite database,
+//! and in the browser it's IndexedDB.
+
+use std::fmt;
+
+use log::warn;
+use mastodon_async::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod sqlite;
+#[cfg(not(target_arch = "wasm32"))]
+use sqlite::Store;
+#[cfg(target_arch = "wasm32")]
+mod indexeddb;
+#[cfg(target_arch = "wasm32")]
+use indexeddb::Store;
+
+/// Used to find the directory the cache lives in.
+pub const APP_ID: &str = "hedgehog";
+
+/// Entries not refreshed for this long are dropped when the cache is opened.
+pub const MAX_AGE: time::Duration = time::Duration::days(14);
+/// Beyond this, the least recently fetched entries are dropped when the cache is opened.
+pub const MAX_BYTES: u64 = 64 * 1024 * 1024;
+/// How many statuses of each timeline are remembered.
+const TIMELINE_LENGTH: usize = 400;
+
+const STATUS: &str = "status";
+const ACCOUNT: &str = "account";
+const NOTIFICATION: &str = "notification";
+/// The list of notifications, newest first, next to the timelines' lists.
+const NOTIFICATIONS: &str = "notifications";
+const IMAGE: &str = "image";
+
+#[derive(Debug)]
+pub enum CacheError {
+    /// There's nowhere to keep a cache on this platform or machine.
+    Unavailable(String),
+    /// The disk, or the browser's storage quota for the page, is full.
+    QuotaExceeded,
+    Storage(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Unavailable(e) => write!(f, "No cache available: {}", e),
+            CacheError::QuotaExceeded => write!(f, "Cache is out of space"),
+            CacheError::Storage(e) => write!(f, "Cache error: {}", e),
+        }
+    }
+}
+
+/// One account's cache.
+pub struct Cache {
+    store: Store,
+}
+
+impl Cache {
+    /// Open the cache for an account (see `SessionChannels::account_key`), evicting stale entries.
+    pub async fn open(account_key: &str) -> Result<Cache, CacheError> {
+        let mut store = Store::open(account_key).await?;
+        let oldest = time::OffsetDateTime::now_utc() - MAX_AGE;
+        store.evict(oldest.unix_timestamp(), MAX_BYTES).await?;
+        Ok(Cache { store })
+    }
+
+    /// Remember a page of a timeline, either in place of what was there or after it.
+    pub async fn store_timeline(
+        &mut self,
+        name: &str,
+        statuses: &[Status],
+        replace: bool,
+    ) -> Result<(), CacheError> {
+        let authors = statuses
+            .iter()
+            .flat_map(|s| std::iter::once(&s.account).chain(s.reblog.as_ref().map(|r| &r.account)));
+        self.put(ACCOUNT, authors.map(|a| (a.id.to_string(), a)))
+            .await?;
+        self.put(STATUS, statuses.iter().map(|s| (s.id.to_string(), s)))
+            .await?;
+        let ids = statuses.iter().map(|s| s.id.to_string());
+        self.extend_list(name, ids, replace).await
+    }
+
+    pub async fn load_timeline(&mut self, name: &str) -> Result<Vec<Status>, CacheError> {
+        let ids = self.store.list(name).await?;
+        self.get(STATUS, &ids).await
+    }
+
+    pub async fn store_notifications(
+        &mut self,
+        notifications: &[Notification],
+        replace: bool,
+    ) -> Result<(), CacheError> {
+        self.put(
+            ACCOUNT,
+            notifications
+                .iter()
+                .map(|n| (n.account.id.to_string(), &n.account)),
+        )
+        .await?;
+        self.put(
+            NOTIFICATION,
+            notifications.iter().map(|n| (n.id.to_string(), n)),
+        )
+        .await?;
+        let ids = notifications.iter().map(|n| n.id.to_string());
+        self.extend_list(NOTIFICATIONS, ids, replace).await
+    }
+
+    pub async fn load_notifications(&mut self) -> Result<Vec<Notification>, CacheError> {
+        let ids = self.store.list(NOTIFICATIONS).await?;
+        self.get(NOTIFICATION, &ids).await
+    }
+
+    pub async fn account(&mut self, id: &AccountId) -> Result<Option<Account>, CacheError> {
+        let mut accounts = self.get(ACCOUNT, &[id.to_string()]).await?;
+        Ok(accounts.pop())
+    }
+
+    /// Keep the bytes of an image (an avatar, or a media attachment), by its url.
+    pub async fn store_image(&mut self, url: &str, data: Vec<u8>) -> Result<(), CacheError> {
+        match self.store.put_blob(IMAGE, url, data.clone()).await {
+            Err(CacheError::QuotaExceeded) => {
+                self.make_room().await?;
+                self.store.put_blob(IMAGE, url, data).await
+            }
+            result => result,
+        }
+    }
+
+    pub async fn image(&mut self, url: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        self.store.get_blob(IMAGE, url).await
+    }
+
+    /// Roughly how much space the cache takes up, in bytes.
+    pub async fn size(&mut self) -> Result<u64, CacheError> {
+        self.store.size().await
+    }
+
+    pub async fn clear(&mut self) -> Result<(), CacheError> {
+        self.store.clear().await
+    }
+
+    async fn put<'a, T: Serialize + 'a>(
+        &mut self,
+        kind: &str,
+        items: impl Iterator<Item = (String, &'a T)>,
+    ) -> Result<(), CacheError> {
+        let entries = items
+            .filter_map(|(id, item)| match serde_json::to_string(item) {
+                Ok(json) => Some((id, json)),
+                Err(e) => {
+                    warn!("Couldn't cache {} {}: {}", kind, id, e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        match self.store.put(kind, entries.clone()).await {
+            Err(CacheError::QuotaExceeded) => {
+                self.make_room().await?;
+                self.store.put(kind, entries).await
+            }
+            result => result,
+        }
+    }
+
+    /// Out of space, so give up the older half of the cache before trying again.
+    async fn make_room(&mut self) -> Result<(), CacheError> {
+        let size = self.store.size().await?;
+        warn!("Cache is out of space at {} bytes, evicting", size);
+        self.store.evict(i64::MIN, size / 2).await
+    }
+
+    /// Entries in the order of `ids`. Missing entries, and ones written by an older version that
+    /// no longer parse, are left out.
+    async fn get<T: DeserializeOwned>(
+        &mut self,
+        kind: &str,
+        ids: &[String],
+    ) -> Result<Vec<T>, CacheError> {
+        let entries = self.store.get(kind, ids).await?;
+        Ok(entries
+            .iter()
+            .filter_map(|json| serde_json::from_str(json).ok())
+            .collect())
+    }
+
+    async fn extend_list(
+        &mut self,
+        name: &str,
+        ids: impl Iterator<Item = String>,
+        replace: bool,
+    ) -> Result<(), CacheError> {
+        let mut list = if replace {
+            vec![]
+        } else {
+            self.store.list(name).await?
+        };
+        for id in ids {
+            if !list.contains(&id) {
+                list.push(id);
+            }
+        }
+        list.truncate(TIMELINE_LENGTH);
+        self.store.set_list(name, &list).await
+    }
+}
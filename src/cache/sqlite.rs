@@ -0,0 +1,292 @@
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use super::{CacheError, APP_ID};
+
+/// Each entry brings the schema up from the version before it, tracked in `user_version`.
+const MIGRATIONS: &[&str] = &[SCHEMA_V1, SCHEMA_V2];
+
+const SCHEMA_V1: &str = "
+    CREATE TABLE entries (
+        kind TEXT NOT NULL,
+        id TEXT NOT NULL,
+        json TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        PRIMARY KEY (kind, id)
+    );
+    CREATE INDEX entries_by_age ON entries (fetched_at);
+    CREATE TABLE lists (
+        name TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        PRIMARY KEY (name, position)
+    );
+";
+
+const SCHEMA_V2: &str = "
+    CREATE TABLE blobs (
+        kind TEXT NOT NULL,
+        id TEXT NOT NULL,
+        data BLOB NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        PRIMARY KEY (kind, id)
+    );
+    CREATE INDEX blobs_by_age ON blobs (fetched_at);
+";
+
+/// How many of the oldest entries are dropped at a time while the cache is over its size.
+const EVICTION_BATCH: i64 = 200;
+
+impl From<rusqlite::Error> for CacheError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::SqliteFailure(failure, _)
+                if failure.code == rusqlite::ErrorCode::DiskFull =>
+            {
+                CacheError::QuotaExceeded
+            }
+            e => CacheError::Storage(e.to_string()),
+        }
+    }
+}
+
+/// A SQLite database per account, in the app's storage directory.
+///
+/// SQLite blocks, so every call runs on tokio's blocking threads rather than holding up the
+/// services sharing the runtime.
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    pub async fn open(name: &str) -> Result<Store, CacheError> {
+        // Tests, and anyone else who wants the cache somewhere else, can say where it goes.
+        let dir = match std::env::var_os("HEDGEHOG_CACHE_DIR") {
+            Some(dir) => dir.into(),
+            None => eframe::storage_dir(APP_ID)
+                .ok_or_else(|| CacheError::Unavailable("no storage directory".to_string()))?
+                .join("cache"),
+        };
+        let file_name: String = name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '@' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        let conn = blocking(move || {
+            std::fs::create_dir_all(&dir).map_err(|e| CacheError::Storage(e.to_string()))?;
+            let mut conn = Connection::open(dir.join(format!("{}.sqlite3", file_name)))?;
+            migrate(&mut conn)?;
+            Ok(conn)
+        })
+        .await?;
+        Ok(Store {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run `f` with the connection, off the async threads.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, CacheError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, CacheError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        blocking(move || f(&mut conn.lock().unwrap())).await
+    }
+
+    pub async fn put(
+        &mut self,
+        kind: &str,
+        entries: Vec<(String, String)>,
+    ) -> Result<(), CacheError> {
+        let kind = kind.to_string();
+        self.with_conn(move |conn| {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare_cached(
+                    "INSERT OR REPLACE INTO entries (kind, id, json, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (id, json) in entries {
+                    insert.execute(params![kind, id, json, now])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get(&mut self, kind: &str, ids: &[String]) -> Result<Vec<String>, CacheError> {
+        let kind = kind.to_string();
+        let ids = ids.to_vec();
+        self.with_conn(move |conn| {
+            let mut select =
+                conn.prepare_cached("SELECT json FROM entries WHERE kind = ?1 AND id = ?2")?;
+            let mut entries = vec![];
+            for id in ids {
+                if let Some(json) = select
+                    .query_row(params![kind, id], |row| row.get(0))
+                    .optional()?
+                {
+                    entries.push(json);
+                }
+            }
+            Ok(entries)
+        })
+        .await
+    }
+
+    pub async fn put_blob(
+        &mut self,
+        kind: &str,
+        id: &str,
+        data: Vec<u8>,
+    ) -> Result<(), CacheError> {
+        let (kind, id) = (kind.to_string(), id.to_string());
+        self.with_conn(move |conn| {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            conn.execute(
+                "INSERT OR REPLACE INTO blobs (kind, id, data, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+                params![kind, id, data, now],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_blob(&mut self, kind: &str, id: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let (kind, id) = (kind.to_string(), id.to_string());
+        self.with_conn(move |conn| {
+            let data = conn
+                .query_row(
+                    "SELECT data FROM blobs WHERE kind = ?1 AND id = ?2",
+                    params![kind, id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(data)
+        })
+        .await
+    }
+
+    pub async fn list(&mut self, name: &str) -> Result<Vec<String>, CacheError> {
+        let name = name.to_string();
+        self.with_conn(move |conn| {
+            let mut select =
+                conn.prepare_cached("SELECT id FROM lists WHERE name = ?1 ORDER BY position")?;
+            let ids = select
+                .query_map(params![name], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    pub async fn set_list(&mut self, name: &str, ids: &[String]) -> Result<(), CacheError> {
+        let name = name.to_string();
+        let ids = ids.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM lists WHERE name = ?1", params![name])?;
+            {
+                let mut insert = tx
+                    .prepare_cached("INSERT INTO lists (name, position, id) VALUES (?1, ?2, ?3)")?;
+                for (position, id) in ids.iter().enumerate() {
+                    insert.execute(params![name, position as i64, id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Drop entries fetched before `older_than` (a unix timestamp), then the oldest of the rest
+    /// until the cache fits in `max_bytes`.
+    pub async fn evict(&mut self, older_than: i64, max_bytes: u64) -> Result<(), CacheError> {
+        self.with_conn(move |conn| {
+            for table in ["entries", "blobs"] {
+                conn.execute(
+                    &format!("DELETE FROM {} WHERE fetched_at < ?1", table),
+                    params![older_than],
+                )?;
+            }
+            while size(conn)? > max_bytes {
+                // Images take the most room, so they go first.
+                let mut removed = conn.execute(
+                    "DELETE FROM blobs WHERE rowid IN
+                        (SELECT rowid FROM blobs ORDER BY fetched_at LIMIT ?1)",
+                    params![EVICTION_BATCH],
+                )?;
+                if removed == 0 {
+                    removed = conn.execute(
+                        "DELETE FROM entries WHERE rowid IN
+                            (SELECT rowid FROM entries ORDER BY fetched_at LIMIT ?1)",
+                        params![EVICTION_BATCH],
+                    )?;
+                }
+                if removed == 0 {
+                    break;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn size(&mut self) -> Result<u64, CacheError> {
+        self.with_conn(|conn| size(conn)).await
+    }
+
+    pub async fn clear(&mut self) -> Result<(), CacheError> {
+        self.with_conn(|conn| {
+            conn.execute_batch("DELETE FROM entries; DELETE FROM lists; DELETE FROM blobs;")?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, CacheError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, CacheError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| CacheError::Storage(e.to_string()))?
+}
+
+fn size(conn: &Connection) -> Result<u64, CacheError> {
+    let size: i64 = conn.query_row(
+        "SELECT (SELECT COALESCE(SUM(LENGTH(json)), 0) FROM entries)
+            + (SELECT COALESCE(SUM(LENGTH(data)), 0) FROM blobs)",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(size as u64)
+}
+
+/// Runs as one write transaction, so services opening the same cache at once can't both migrate
+/// it.
+fn migrate(conn: &mut Connection) -> Result<(), CacheError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut version: usize = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        // Written by a newer version of the app. It's only a cache, so start over.
+        tx.execute_batch(
+            "DROP TABLE IF EXISTS entries; DROP TABLE IF EXISTS lists; DROP TABLE IF EXISTS blobs;",
+        )?;
+        version = 0;
+    }
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    tx.commit()?;
+    Ok(())
+}
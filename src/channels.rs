@@ -1,11 +1,41 @@
-use std::mem;
+use std::{collections::VecDeque, future::Future, mem, sync::Mutex};
 
+use futures::future::{select, Either};
+use instant::Duration;
 use log::{debug, warn};
 use tokio::sync::{
-    self, mpsc,
+    self,
+    mpsc::{self, error::TrySendError},
     oneshot::{self, error::TryRecvError},
 };
 
+use crate::{diagnostics::BridgeProbe, timer::timeout};
+
+/// How long a service gets to answer a request before giving up on it.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The ui to wake when a service has something for it.
+static UI: Mutex<Option<egui::Context>> = Mutex::new(None);
+
+/// Have services wake `ctx` when they answer, so the ui can sit idle until then.
+pub fn wake_ui_with(ctx: &egui::Context) {
+    *UI.lock().unwrap() = Some(ctx.clone());
+}
+
+/// Ask for a frame, so bridges get pumped and whatever came in is shown.
+pub fn wake_ui() {
+    if let Some(ctx) = &*UI.lock().unwrap() {
+        ctx.request_repaint();
+    }
+}
+
+/// Answer a request, and wake the ui to see the answer.
+pub fn send_reply<TMsg>(reply: oneshot::Sender<TMsg>, msg: TMsg) -> Result<(), TMsg> {
+    let sent = reply.send(msg);
+    wake_ui();
+    sent
+}
+
 pub fn new_channel_pair<T>() -> (mpsc::Sender<Message<T>>, mpsc::Receiver<Message<T>>) {
     mpsc::channel(255)
 }
@@ -20,9 +50,38 @@ pub enum Message<TMsg> {
     },
 }
 
+/// Work on a request until it's done, it takes longer than `REQUEST_TIMEOUT`, or whoever asked
+/// cancels it by closing the reply, as `AsyncRequestBridge::cancel` does.
+///
+/// Giving up drops `work`, and with it any http request it was in the middle of. Returns `None`
+/// if the request was cancelled, since then there's no one to answer.
+pub async fn cancellable<TMsg, T>(
+    reply: &mut oneshot::Sender<TMsg>,
+    work: impl Future<Output = Result<T, String>>,
+) -> Option<Result<T, String>> {
+    let work = Box::pin(timeout(REQUEST_TIMEOUT, work));
+    let cancelled = Box::pin(reply.closed());
+    match select(work, cancelled).await {
+        Either::Left((Ok(result), _)) => Some(result),
+        Either::Left((Err(_), _)) => Some(Err(format!(
+            "No answer after {} seconds",
+            REQUEST_TIMEOUT.as_secs()
+        ))),
+        Either::Right(_) => None,
+    }
+}
+
 pub struct AsyncRequestBridge<TMsg, TState> {
     tx: sync::mpsc::Sender<Message<TMsg>>,
+    /// Requests that didn't fit in the channel yet. Sends never block, as in the browser the
+    /// service that would make room runs on the same thread.
+    overflow: VecDeque<Message<TMsg>>,
     pub state: AsyncRequestBridgeState<TMsg, TState>,
+    /// Requests replaced by newer ones. They're kept open so the service still finishes them,
+    /// but their answers are thrown away.
+    superseded: Vec<oneshot::Receiver<TMsg>>,
+    /// Shows the state in the diagnostics window.
+    probe: BridgeProbe,
 }
 
 pub enum AsyncRequestBridgeState<TMsg, TState> {
@@ -37,24 +96,46 @@ pub enum AsyncRequestBridgeState<TMsg, TState> {
     Error(String),
 }
 
+impl<TMsg, TState> AsyncRequestBridgeState<TMsg, TState> {
+    /// The name of the state, e.g. for diagnostics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AsyncRequestBridgeState::Init => "Init",
+            AsyncRequestBridgeState::Awaiting { .. } => "Awaiting",
+            AsyncRequestBridgeState::Updating => "Updating",
+            AsyncRequestBridgeState::Complete(_) => "Complete",
+            AsyncRequestBridgeState::Error(_) => "Error",
+        }
+    }
+}
+
 impl<TMsg, TState> AsyncRequestBridge<TMsg, TState> {
     pub fn new(tx: sync::mpsc::Sender<Message<TMsg>>) -> AsyncRequestBridge<TMsg, TState> {
         AsyncRequestBridge {
             tx,
+            overflow: VecDeque::new(),
             state: AsyncRequestBridgeState::Init,
+            superseded: Vec::new(),
+            probe: BridgeProbe::new(std::any::type_name::<TMsg>()),
         }
     }
+
+    /// Let the diagnostics window know what state the bridge is in.
+    fn report(&self) {
+        self.probe.update(self.state.label(), self.has_queued());
+    }
     pub fn send(&mut self, msg: TMsg, handler: Box<dyn FnOnce(TMsg, Option<TState>) -> TState>) {
         // Only one outbound request at a time
         let prev_state = match mem::replace(&mut self.state, AsyncRequestBridgeState::Updating) {
             AsyncRequestBridgeState::Init => None,
             AsyncRequestBridgeState::Awaiting {
-                mut response,
+                response,
                 prev_state,
                 handler: _,
             } => {
-                // Cancel the previous request and start a new one.
-                response.close();
+                // Stop waiting for the previous request, without cancelling it: it may be a
+                // vote or a delete that has to go through. Only `cancel` stops work.
+                self.superseded.push(response);
                 prev_state
             }
             AsyncRequestBridgeState::Updating => {
@@ -70,19 +151,127 @@ impl<TMsg, TState> AsyncRequestBridge<TMsg, TState> {
             prev_state,
             handler,
         };
-        match self.tx.blocking_send(Message::Request {
+        // Anything cancelled while it was still queued has no one waiting for its answer.
+        self.overflow.retain(|queued| match queued {
+            Message::Request { reply, .. } => !reply.is_closed(),
+            Message::Notification { .. } => true,
+        });
+        self.overflow.push_back(Message::Request {
             msg,
             reply: resp_tx,
-        }) {
-            Ok(_) => (),
-            Err(e) => {
-                warn!("Failed to send request: {:?}", e);
-                self.state = AsyncRequestBridgeState::Error(format!("Failed to send req: {:?}", e));
+        });
+        self.flush();
+        self.report();
+    }
+
+    /// Send `msg` without waiting for an answer. Like a request, it's held on to until there's
+    /// room in the channel, rather than dropped.
+    pub fn notify(&mut self, msg: TMsg) {
+        self.overflow.push_back(Message::Notification { msg });
+        self.flush();
+        self.report();
+    }
+
+    /// Hand queued requests to the service, for as long as there's room in the channel.
+    fn flush(&mut self) {
+        while let Some(message) = self.overflow.pop_front() {
+            match self.tx.try_send(message) {
+                Ok(()) => (),
+                Err(TrySendError::Full(message)) => {
+                    debug!("Service is busy, holding on to a request");
+                    self.overflow.push_front(message);
+                    return;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    warn!("Failed to send request: the service has stopped");
+                    self.overflow.clear();
+                    self.state = AsyncRequestBridgeState::Error("The service has stopped".into());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Whether there are requests waiting for room in the channel.
+    pub fn has_queued(&self) -> bool {
+        !self.overflow.is_empty()
+    }
+
+    /// Stop waiting for the request in flight, if there is one, going back to the state before
+    /// it. The service drops the request as soon as it notices.
+    pub fn cancel(&mut self) {
+        if !self.is_awaiting() {
+            return;
+        }
+        if let AsyncRequestBridgeState::Awaiting {
+            mut response,
+            prev_state,
+            ..
+        } = mem::replace(&mut self.state, AsyncRequestBridgeState::Init)
+        {
+            response.close();
+            if let Some(s) = prev_state {
+                self.state = AsyncRequestBridgeState::Complete(s);
             }
         }
+        self.report();
+    }
+
+    /// The most recent completed state, including while a newer request is in flight.
+    pub fn current_state(&self) -> Option<&TState> {
+        match &self.state {
+            AsyncRequestBridgeState::Complete(s) => Some(s),
+            AsyncRequestBridgeState::Awaiting {
+                prev_state: Some(s),
+                ..
+            } => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn current_state_mut(&mut self) -> Option<&mut TState> {
+        match &mut self.state {
+            AsyncRequestBridgeState::Complete(s) => Some(s),
+            AsyncRequestBridgeState::Awaiting {
+                prev_state: Some(s),
+                ..
+            } => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn is_awaiting(&self) -> bool {
+        matches!(self.state, AsyncRequestBridgeState::Awaiting { .. })
+    }
+
+    /// Takes a completed state out of the bridge, returning it to `Init`.
+    pub fn take_complete(&mut self) -> Option<TState> {
+        if !matches!(self.state, AsyncRequestBridgeState::Complete(_)) {
+            return None;
+        }
+        match mem::replace(&mut self.state, AsyncRequestBridgeState::Init) {
+            AsyncRequestBridgeState::Complete(s) => Some(s),
+            _ => None,
+        }
     }
 
     pub fn pump_messages(&mut self) -> bool {
+        let changed = self.receive();
+        // Reported every frame, as views also set the state directly.
+        self.report();
+        changed
+    }
+
+    fn receive(&mut self) -> bool {
+        self.superseded
+            .retain_mut(|response| matches!(response.try_recv(), Err(TryRecvError::Empty)));
+        if !self.overflow.is_empty() {
+            self.flush();
+            if let AsyncRequestBridgeState::Error(_) = self.state {
+                return true;
+            }
+        }
+
         let reciever = match &mut self.state {
             AsyncRequestBridgeState::Awaiting {
                 response,
@@ -105,8 +294,11 @@ impl<TMsg, TState> AsyncRequestBridge<TMsg, TState> {
                 return false;
             }
             Err(oneshot::error::TryRecvError::Closed) => {
+                // The service stopped, or dropped the request without answering it.
                 warn!("Response channel closed unexpectedly");
-                return false;
+                self.state =
+                    AsyncRequestBridgeState::Error("The request was dropped unanswered".into());
+                return true;
             }
         };
 
@@ -133,6 +325,9 @@ impl<TMsg, TState> AsyncRequestBridge<TMsg, TState> {
 #[derive(Clone)]
 pub struct Spawner {
     rt: std::sync::Arc<tokio::runtime::Runtime>,
+    /// What's been spawned and might still be running, to wait for on shutdown.
+    tasks: std::sync::Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>,
+    roots: std::sync::Arc<Mutex<Vec<std::thread::JoinHandle<()>>>>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -143,6 +338,8 @@ impl Spawner {
 
         Spawner {
             rt: std::sync::Arc::new(rt),
+            tasks: Default::default(),
+            roots: Default::default(),
         }
     }
 
@@ -152,13 +349,14 @@ impl Spawner {
         <F as std::future::Future>::Output: Send,
     {
         let rt_arc = self.rt.clone();
-        std::thread::spawn(move || {
+        let root = std::thread::spawn(move || {
             rt_arc.block_on(async {
                 debug!("start async service");
                 f.await;
                 debug!("finished async service.");
             })
         });
+        self.roots.lock().unwrap().push(root);
         self
     }
 
@@ -168,9 +366,44 @@ impl Spawner {
         <F as std::future::Future>::Output: Send,
     {
         debug!("enter spawn_async");
-        self.rt.spawn(f);
+        let task = self.rt.spawn(async {
+            f.await;
+        });
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
         debug!("exit spawn_async");
     }
+
+    /// Wait up to `deadline` for everything spawned to finish, then stop whatever hasn't.
+    ///
+    /// Services finish once every sender to them is dropped, so drop those first. This blocks,
+    /// so mustn't be called from inside the runtime.
+    pub fn shutdown(&self, deadline: Duration) {
+        let tasks = mem::take(&mut *self.tasks.lock().unwrap());
+        let roots = mem::take(&mut *self.roots.lock().unwrap());
+        let started = instant::Instant::now();
+
+        let aborts: Vec<_> = tasks.iter().map(|task| task.abort_handle()).collect();
+        let finished = self
+            .rt
+            .block_on(timeout(deadline, futures::future::join_all(tasks)));
+        if finished.is_err() {
+            warn!("Services didn't stop within {:?}, stopping them", deadline);
+            aborts.iter().for_each(|task| task.abort());
+        }
+
+        for root in roots {
+            while !root.is_finished() && started.elapsed() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            if root.is_finished() {
+                let _ = root.join();
+            } else {
+                warn!("A root service didn't stop within {:?}", deadline);
+            }
+        }
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -199,4 +432,8 @@ impl Spawner {
             f.await;
         });
     }
+
+    /// Nothing can be waited for in the browser, where this would block the only thread.
+    /// Services still stop once every sender to them is dropped.
+    pub fn shutdown(&self, _deadline: Duration) {}
 }
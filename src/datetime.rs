@@ -0,0 +1,120 @@
+use std::sync::OnceLock;
+
+use time::{
+    format_description::{well_known::Rfc3339, FormatItem},
+    macros::format_description,
+    Duration, OffsetDateTime, PrimitiveDateTime, UtcOffset,
+};
+
+/// How times are written for the user to read or type, in their own timezone.
+const LOCAL_FORMAT: &[FormatItem<'_>] = format_description!("[year]-[month]-[day] [hour]:[minute]");
+
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// The user's timezone. On some platforms this can only be read while the process has a single
+/// thread, so call it once at startup; if it can't be read, times are shown in UTC.
+pub fn local_offset() -> UtcOffset {
+    *LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC))
+}
+
+/// A time as the user would write it, such as "2024-03-01 18:30".
+pub fn format_local(datetime: OffsetDateTime) -> String {
+    datetime
+        .to_offset(local_offset())
+        .format(LOCAL_FORMAT)
+        .unwrap_or_default()
+}
+
+/// A time the server sent as RFC 3339, as the user would write it. If the server sent something
+/// else, it's shown as it was.
+pub fn format_server_time(text: &str) -> String {
+    match OffsetDateTime::parse(text, &Rfc3339) {
+        Ok(datetime) => format_local(datetime),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Read a time written like `format_local` does, in the user's timezone.
+pub fn parse_local(text: &str) -> Result<OffsetDateTime, String> {
+    PrimitiveDateTime::parse(text.trim(), LOCAL_FORMAT)
+        .map(|datetime| datetime.assume_offset(local_offset()))
+        .map_err(|_| "Times should look like 2024-03-01 18:30.".to_string())
+}
+
+/// A rough, human readable length of time, such as "3 hours".
+pub fn format_duration(duration: Duration) -> String {
+    let (amount, unit) = if duration.whole_days() > 0 {
+        (duration.whole_days(), "day")
+    } else if duration.whole_hours() > 0 {
+        (duration.whole_hours(), "hour")
+    } else if duration.whole_minutes() > 0 {
+        (duration.whole_minutes(), "minute")
+    } else {
+        (duration.whole_seconds().max(0), "second")
+    };
+    if amount == 1 {
+        format!("{} {}", amount, unit)
+    } else {
+        format!("{} {}s", amount, unit)
+    }
+}
+
+/// How long is left until `deadline`, or none if it has passed.
+pub fn remaining_until(deadline: OffsetDateTime) -> Option<Duration> {
+    let remaining = deadline - OffsetDateTime::now_utc();
+    if remaining.is_positive() {
+        Some(remaining)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn local_times_read_back_the_same() {
+        let at = datetime!(2024-03-01 18:30 UTC);
+        assert_eq!(parse_local(&format_local(at)), Ok(at));
+        assert_eq!(parse_local(&format!(" {} ", format_local(at))), Ok(at));
+    }
+
+    #[test]
+    fn refuses_times_in_other_shapes() {
+        assert!(parse_local("tomorrow").is_err());
+        assert!(parse_local("2024-03-01").is_err());
+        assert!(parse_local("2024-03-01T18:30:00Z").is_err());
+    }
+
+    #[test]
+    fn server_times_are_shown_locally() {
+        assert_eq!(
+            format_server_time("2024-03-01T18:30:00.000Z"),
+            format_local(datetime!(2024-03-01 18:30 UTC))
+        );
+        // Anything that isn't RFC 3339 is shown as the server sent it.
+        assert_eq!(format_server_time("last tuesday"), "last tuesday");
+    }
+
+    #[test]
+    fn durations_are_rounded_down_to_one_unit() {
+        assert_eq!(
+            format_duration(Duration::days(2) + Duration::hours(5)),
+            "2 days"
+        );
+        assert_eq!(format_duration(Duration::hours(1)), "1 hour");
+        assert_eq!(format_duration(Duration::seconds(90)), "1 minute");
+        assert_eq!(format_duration(Duration::seconds(1)), "1 second");
+        assert_eq!(format_duration(Duration::seconds(-5)), "0 seconds");
+    }
+
+    #[test]
+    fn nothing_remains_of_past_deadlines() {
+        let now = OffsetDateTime::now_utc();
+        assert_eq!(remaining_until(now - Duration::minutes(1)), None);
+        assert!(remaining_until(now + Duration::hours(1)).is_some());
+    }
+}
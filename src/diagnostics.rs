@@ -0,0 +1,276 @@
+//! What the app has been doing, kept for the diagnostics window and for bug reports.
+//!
+//! Recent log records and http exchanges are kept in ring buffers, along with the state of every
+//! live `AsyncRequestBridge`. Every request to the instance goes through `api`, which records it.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use instant::Duration;
+use reqwest::{header::HeaderMap, Method, StatusCode, Url};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{cache::APP_ID, redact};
+
+/// How many log records to keep.
+const LOG_CAPACITY: usize = 500;
+/// How many http exchanges to keep.
+const HTTP_CAPACITY: usize = 200;
+/// Bodies longer than this are cut short.
+const BODY_LIMIT: usize = 4096;
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct LogRecord {
+    pub at: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct HttpRecord {
+    pub at: String,
+    pub method: String,
+    pub url: String,
+    /// None if no response came back.
+    pub status: Option<u16>,
+    pub elapsed_ms: u64,
+    /// The `X-RateLimit-*` and `Retry-After` headers that came back.
+    pub rate_limit: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct BridgeRecord {
+    /// The type of messages the bridge carries.
+    pub messages: String,
+    pub state: &'static str,
+    /// Whether requests are waiting for room in the service's channel.
+    pub queued: bool,
+}
+
+/// Everything recorded, as exported.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct Snapshot {
+    pub version: String,
+    pub logs: Vec<LogRecord>,
+    pub http: Vec<HttpRecord>,
+    pub bridges: Vec<BridgeRecord>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+struct Diagnostics {
+    logs: VecDeque<LogRecord>,
+    http: VecDeque<HttpRecord>,
+    bridges: BTreeMap<u64, BridgeRecord>,
+}
+
+static DIAGNOSTICS: Mutex<Diagnostics> = Mutex::new(Diagnostics {
+    logs: VecDeque::new(),
+    http: VecDeque::new(),
+    bridges: BTreeMap::new(),
+});
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+fn push<T>(buffer: &mut VecDeque<T>, capacity: usize, item: T) {
+    if buffer.len() == capacity {
+        buffer.pop_front();
+    }
+    buffer.push_back(item);
+}
+
+/// A copy of everything recorded so far.
+pub fn snapshot() -> Snapshot {
+    let diagnostics = DIAGNOSTICS.lock().unwrap();
+    Snapshot {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        logs: diagnostics.logs.iter().cloned().collect(),
+        http: diagnostics.http.iter().cloned().collect(),
+        bridges: diagnostics.bridges.values().cloned().collect(),
+    }
+}
+
+/// Save everything recorded for a bug report, returning where it went.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export() -> Result<String, String> {
+    let dir = eframe::storage_dir(APP_ID).ok_or("No storage directory")?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(export_name());
+    std::fs::write(&path, snapshot().to_json()).map_err(|e| e.to_string())?;
+    Ok(path.display().to_string())
+}
+
+/// Download everything recorded for a bug report, returning the file's name.
+#[cfg(target_arch = "wasm32")]
+pub fn export() -> Result<String, String> {
+    let name = export_name();
+    crate::web::download(&name, &snapshot().to_json())?;
+    Ok(name)
+}
+
+fn export_name() -> String {
+    format!(
+        "{}-diagnostics-{}.json",
+        APP_ID,
+        OffsetDateTime::now_utc().unix_timestamp()
+    )
+}
+
+/// Forget the log and http exchanges recorded so far.
+pub fn clear() {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap();
+    diagnostics.logs.clear();
+    diagnostics.http.clear();
+}
+
+/// Passes records on to the platform's logger, keeping a copy of each.
+struct Recorder {
+    inner: Box<dyn log::Log>,
+}
+
+impl log::Log for Recorder {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        keep(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if keep(record.metadata()) {
+            let record = LogRecord {
+                at: now(),
+                level: record.level().to_string(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            };
+            push(&mut DIAGNOSTICS.lock().unwrap().logs, LOG_CAPACITY, record);
+        }
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// The app's own debug messages are kept, but only the warnings of the crates it uses, which
+/// would otherwise crowd them out.
+fn keep(metadata: &log::Metadata<'_>) -> bool {
+    if metadata.target().starts_with("hedgehog") {
+        metadata.level() <= log::Level::Debug
+    } else {
+        metadata.level() <= log::Level::Warn
+    }
+}
+
+/// Log through `inner`, at the levels it was set up with, keeping records for diagnostics too.
+pub fn init_logger(inner: Box<dyn log::Log>, inner_level: log::LevelFilter) {
+    let level = inner_level.max(log::LevelFilter::Debug);
+    if log::set_boxed_logger(Box::new(Recorder { inner })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Take note of an http exchange. `body` is the response body, or `None` if there wasn't one.
+pub fn record_http(
+    method: &Method,
+    url: &Url,
+    elapsed: Duration,
+    response: Result<(StatusCode, &HeaderMap), &str>,
+    body: Option<&str>,
+) {
+    let (status, rate_limit, error) = match response {
+        Ok((status, headers)) => {
+            let rate_limit = headers
+                .iter()
+                .filter(|(name, _)| {
+                    name.as_str().starts_with("x-ratelimit-") || name.as_str() == "retry-after"
+                })
+                .map(|(name, value)| {
+                    let value = value.to_str().unwrap_or_default();
+                    (name.to_string(), value.to_string())
+                })
+                .collect();
+            (Some(status.as_u16()), rate_limit, None)
+        }
+        Err(e) => (None, vec![], Some(e.to_string())),
+    };
+    let record = HttpRecord {
+        at: now(),
+        method: method.to_string(),
+        url: redact::query(url.as_str()),
+        status,
+        elapsed_ms: elapsed.as_millis() as u64,
+        rate_limit,
+        body: body.map(redact_body),
+        error,
+    };
+    push(&mut DIAGNOSTICS.lock().unwrap().http, HTTP_CAPACITY, record);
+}
+
+/// The body with its secrets blanked out, cut short if it's long.
+fn redact_body(body: &str) -> String {
+    let mut body = redact::body(body);
+    if body.len() > BODY_LIMIT {
+        let mut end = BODY_LIMIT;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push('…');
+    }
+    body
+}
+
+static NEXT_BRIDGE: AtomicU64 = AtomicU64::new(0);
+
+/// Keeps the diagnostics' view of a bridge's state up to date, and forgets it when dropped.
+pub struct BridgeProbe {
+    id: u64,
+}
+
+impl BridgeProbe {
+    pub fn new(messages: &str) -> Self {
+        let id = NEXT_BRIDGE.fetch_add(1, Ordering::Relaxed);
+        // Just the type's own name, without its module path.
+        let short = messages.rsplit("::").next().unwrap_or(messages);
+        DIAGNOSTICS.lock().unwrap().bridges.insert(
+            id,
+            BridgeRecord {
+                messages: short.to_string(),
+                state: "Init",
+                queued: false,
+            },
+        );
+        BridgeProbe { id }
+    }
+
+    pub fn update(&self, state: &'static str, queued: bool) {
+        if let Some(bridge) = DIAGNOSTICS.lock().unwrap().bridges.get_mut(&self.id) {
+            bridge.state = state;
+            bridge.queued = queued;
+        }
+    }
+}
+
+impl Drop for BridgeProbe {
+    fn drop(&mut self) {
+        DIAGNOSTICS.lock().unwrap().bridges.remove(&self.id);
+    }
+}
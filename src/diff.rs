@@ -0,0 +1,75 @@
+/// A run of words in a comparison between two texts.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffSpan<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Compare two texts word by word, keeping as many words in common as possible.
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> Vec<DiffSpan<'a>> {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+
+    // lengths[i][j] is the longest common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut spans = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            spans.push(DiffSpan::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            spans.push(DiffSpan::Added(new[j]));
+            j += 1;
+        } else {
+            spans.push(DiffSpan::Removed(old[i]));
+            i += 1;
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_words, DiffSpan::*};
+
+    #[test]
+    fn marks_replaced_words() {
+        assert_eq!(
+            diff_words("a b c", "a x c"),
+            vec![Same("a"), Added("x"), Removed("b"), Same("c")]
+        );
+    }
+
+    #[test]
+    fn keeps_the_longest_run_in_common() {
+        assert_eq!(
+            diff_words("the quick fox", "quick brown fox"),
+            vec![Removed("the"), Same("quick"), Added("brown"), Same("fox")]
+        );
+    }
+
+    #[test]
+    fn everything_is_added_or_removed_from_nothing() {
+        assert_eq!(diff_words("", "a b"), vec![Added("a"), Added("b")]);
+        assert_eq!(diff_words("a b", ""), vec![Removed("a"), Removed("b")]);
+        assert_eq!(diff_words("", ""), vec![]);
+    }
+
+    #[test]
+    fn whitespace_changes_are_not_differences() {
+        assert_eq!(diff_words("a  b\n", "a b"), vec![Same("a"), Same("b")]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use time::OffsetDateTime;
+
+use crate::statuses::ComposeDraft;
+
+/// Unposted drafts for every account that has signed in, kept in eframe's persistence.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct DraftStore {
+    /// Keyed by `SessionChannels::account_key`, so drafts never leak between accounts.
+    accounts: HashMap<String, AccountDrafts>,
+}
+
+impl DraftStore {
+    pub fn for_account(&mut self, key: &str) -> &mut AccountDrafts {
+        self.accounts.entry(key.to_string()).or_default()
+    }
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct AccountDrafts {
+    /// Whatever is in the composer, restored the next time the account signs in.
+    pub current: ComposeDraft,
+    pub saved: Vec<NamedDraft>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct NamedDraft {
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub saved_at: OffsetDateTime,
+    pub draft: ComposeDraft,
+}
+
+impl AccountDrafts {
+    /// Keep a copy of a draft under a name, replacing any draft already saved with that name.
+    pub fn save_as(&mut self, name: &str, draft: ComposeDraft) {
+        self.saved.retain(|d| d.name != name);
+        self.saved.insert(
+            0,
+            NamedDraft {
+                name: name.to_string(),
+                saved_at: OffsetDateTime::now_utc(),
+                draft,
+            },
+        );
+    }
+
+    /// Take a saved draft out of the list, to be opened in the composer.
+    pub fn take(&mut self, name: &str) -> Option<ComposeDraft> {
+        let index = self.saved.iter().position(|d| d.name == name)?;
+        Some(self.saved.remove(index).draft)
+    }
+}
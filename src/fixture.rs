@@ -0,0 +1,45 @@
+//! The format recorded conversations with an instance are kept in, for tests to replay.
+//!
+//! Fixtures are written by `record`, when the app is built with the `record` feature, and
+//! replayed by `tests/support/fixtures.rs`.
+
+use std::path::Path;
+
+/// Stands in for the recorded instance's address in fixtures.
+pub const BASE_PLACEHOLDER: &str = "https://fixture.invalid";
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Fixture {
+    /// The server software and version it was recorded from, e.g. `GoToSocial 0.13.0`.
+    pub software: String,
+    pub exchanges: Vec<Exchange>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Exchange {
+    pub method: String,
+    /// Including the query string.
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Fixture, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("bad fixture {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+}
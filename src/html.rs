@@ -0,0 +1,143 @@
+//! Just enough html handling to display status content, which mastodon sends as html.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContentSpan {
+    Text(String),
+    Link { text: String, href: String },
+}
+
+/// Split status html into text and links, turning paragraphs and line breaks into newlines.
+pub fn parse_content(html: &str) -> Vec<ContentSpan> {
+    let mut spans = vec![];
+    let mut text = String::new();
+    // The href and text of the anchor we're inside of, if any.
+    let mut link: Option<(String, String)> = None;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(tag_start) = rest.strip_prefix('<') {
+            let end = match tag_start.find('>') {
+                Some(end) => end,
+                None => break,
+            };
+            let tag = &tag_start[..end];
+            rest = &tag_start[end + 1..];
+
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or("")
+                .to_ascii_lowercase();
+            let closing = tag.starts_with('/');
+            match (name.as_str(), closing) {
+                ("br", _) => push_text(&mut text, &mut link, "\n"),
+                ("p", true) => push_text(&mut text, &mut link, "\n\n"),
+                ("a", false) => {
+                    if !text.is_empty() {
+                        spans.push(ContentSpan::Text(std::mem::take(&mut text)));
+                    }
+                    link = Some((attribute(tag, "href").unwrap_or_default(), String::new()));
+                }
+                ("a", true) => {
+                    if let Some((href, text)) = link.take() {
+                        spans.push(ContentSpan::Link { text, href });
+                    }
+                }
+                _ => (),
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            push_text(&mut text, &mut link, &decode_entities(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    if let Some((href, text)) = link {
+        spans.push(ContentSpan::Link { text, href });
+    }
+    let text = text.trim_end();
+    if !text.is_empty() {
+        spans.push(ContentSpan::Text(text.to_string()));
+    }
+    spans
+}
+
+/// Status content with all markup removed.
+pub fn to_plain_text(html: &str) -> String {
+    parse_content(html)
+        .into_iter()
+        .map(|span| match span {
+            ContentSpan::Text(text) => text,
+            ContentSpan::Link { text, .. } => text,
+        })
+        .collect()
+}
+
+fn push_text(text: &mut String, link: &mut Option<(String, String)>, s: &str) {
+    match link {
+        Some((_, link_text)) => link_text.push_str(s),
+        None => text.push_str(s),
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')? + start;
+    Some(decode_entities(&tag[start..end]))
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_out_links() {
+        let html = r#"<p>Hi <a href="https://b.social/@a" class="u-url mention">@<span>a</span></a></p><p>Bye</p>"#;
+        assert_eq!(
+            parse_content(html),
+            vec![
+                ContentSpan::Text("Hi ".to_string()),
+                ContentSpan::Link {
+                    text: "@a".to_string(),
+                    href: "https://b.social/@a".to_string(),
+                },
+                ContentSpan::Text("\n\nBye".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn paragraphs_and_breaks_become_newlines() {
+        assert_eq!(
+            to_plain_text("<p>one<br>two<br />three</p><p>four</p>"),
+            "one\ntwo\nthree\n\nfour"
+        );
+    }
+
+    #[test]
+    fn decodes_entities_once() {
+        assert_eq!(
+            to_plain_text("<p>a &amp; b &lt;3 &quot;c&#39;</p>"),
+            "a & b <3 \"c'"
+        );
+        assert_eq!(to_plain_text("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn unclosed_markup_keeps_what_came_before() {
+        assert_eq!(to_plain_text("text <a href=\"x\">link"), "text link");
+        assert_eq!(to_plain_text("text <p"), "text");
+    }
+}
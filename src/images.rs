@@ -0,0 +1,118 @@
+//! Avatars and media, fetched once and then kept in the account's cache.
+
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_bytes, RawApi},
+    cache::Cache,
+    channels::{cancellable, send_reply, Message, Spawner},
+    ratelimit::Priority,
+    registry::{Service, ServiceFuture},
+};
+
+/// Loads images by their url, from the cache if it has them. `views::images::ImageLoader` hands
+/// them to egui.
+pub struct ImagesService {
+    pub mastodon: Mastodon,
+    /// See `SessionChannels::account_key`.
+    pub account_key: String,
+}
+
+impl Service for ImagesService {
+    type Message = ImageMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<ImageMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_images_service(self.mastodon, self.account_key, rx))
+    }
+}
+
+pub async fn start_images_service(
+    mastodon: Mastodon,
+    account_key: String,
+    mut rx: mpsc::Receiver<Message<ImageMessage>>,
+) {
+    // Without a cache, images are fetched every time they're shown.
+    let cache = match Cache::open(&account_key).await {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    };
+    let mut state = ImagesState {
+        api: RawApi::new(&mastodon),
+        cache,
+    };
+
+    debug!("entered images service");
+
+    loop {
+        // wait for messages
+        match rx.recv().await {
+            Some(rx) => match rx {
+                Message::Request { msg, mut reply } => {
+                    let Some(result) = cancellable(&mut reply, state.handle(msg)).await else {
+                        debug!("Image request was cancelled");
+                        continue;
+                    };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Image request failed: {}", e);
+                            ImageMessage::Error(e)
+                        }
+                    };
+                    if send_reply(reply, response).is_err() {
+                        warn!("Failed to send image reply");
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Images service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+pub enum ImageMessage {
+    /// Fetch an image by its url.
+    Load(String),
+    /// The image's bytes, still encoded as they were sent.
+    Loaded(Vec<u8>),
+    Error(String),
+}
+
+struct ImagesState {
+    api: RawApi,
+    cache: Option<Cache>,
+}
+
+impl ImagesState {
+    async fn handle(&mut self, msg: ImageMessage) -> Result<ImageMessage, String> {
+        let ImageMessage::Load(url) = msg else {
+            return Err("Unexpected image request".to_string());
+        };
+        if let Some(cache) = &mut self.cache {
+            match cache.image(&url).await {
+                Ok(Some(data)) => return Ok(ImageMessage::Loaded(data)),
+                Ok(None) => (),
+                Err(e) => warn!("{}", e),
+            }
+        }
+        // Images are nice to have, so they wait while the rate limit is low.
+        let data = send_bytes(self.api.fetch(&url), Priority::Background)
+            .await
+            .map_err(|e| format!("Failed to load {}: {}", url, e))?;
+        if let Some(cache) = &mut self.cache {
+            // Failing to cache shouldn't fail showing the image, so errors are only logged.
+            if let Err(e) = cache.store_image(&url, data.clone()).await {
+                warn!("{}", e);
+            }
+        }
+        Ok(ImageMessage::Loaded(data))
+    }
+}
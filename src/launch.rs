@@ -0,0 +1,207 @@
+//! What the app was opened to do, when another app or a link launched it.
+//!
+//! In the browser that's the query string of the page, as filled in by the `share_target` and
+//! `protocol_handlers` of `manifest.json`, or the fragment of its url. Natively it's the command
+//! line arguments.
+
+use reqwest::Url;
+
+use crate::router::Route;
+
+const PROTOCOL: &str = "web+mastodon:";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LaunchAction {
+    /// Text or a link shared from another app, to start a post with.
+    Share { text: String },
+    /// A post or profile to find on the user's instance, by its url.
+    Open(String),
+    /// A page of the app.
+    Go(Route),
+}
+
+impl LaunchAction {
+    /// Read the query string the page was opened with, e.g. `?text=hi&url=https://…`.
+    pub fn from_query(query: &str) -> Option<LaunchAction> {
+        let query = query.trim_start_matches('?');
+        if query.is_empty() {
+            return None;
+        }
+        let url = Url::parse(&format!("http://localhost/?{}", query)).ok()?;
+        let mut title = None;
+        let mut text = None;
+        let mut link = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "handler" => return LaunchAction::from_protocol_link(&value),
+                "title" => title = Some(value.into_owned()),
+                "text" => text = Some(value.into_owned()),
+                "url" => link = Some(value.into_owned()),
+                _ => (),
+            }
+        }
+        // Apps fill these in inconsistently, often repeating the link in the text.
+        let mut parts: Vec<String> = vec![];
+        for part in [title, text, link].into_iter().flatten() {
+            let part = part.trim();
+            if !part.is_empty() && !parts.iter().any(|p| p.contains(part)) {
+                parts.push(part.to_string());
+            }
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        Some(LaunchAction::Share {
+            text: parts.join("\n\n"),
+        })
+    }
+
+    /// Read a `web+mastodon:` link, in the forms Mastodon itself hands out:
+    /// `web+mastodon://share?text=…`, `web+mastodon://follow?uri=acct:…`, or a post or profile
+    /// with the scheme swapped for `https`.
+    pub fn from_protocol_link(link: &str) -> Option<LaunchAction> {
+        let rest = link.strip_prefix(PROTOCOL)?.trim_start_matches('/');
+        let url = Url::parse(&format!("https://{}", rest)).ok()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        match url.host_str() {
+            Some("share") => param("text").map(|text| LaunchAction::Share { text }),
+            Some("follow") => param("uri").map(|uri| {
+                let account = uri.strip_prefix("acct:").unwrap_or(&uri);
+                LaunchAction::Go(Route::Profile(account.trim_start_matches('@').to_string()))
+            }),
+            Some(_) => Some(LaunchAction::Open(url.to_string())),
+            None => None,
+        }
+    }
+
+    /// Read the arguments given to the native app, without the name of the binary:
+    /// `--compose [text]`, a link to a post or profile, a `web+mastodon:` link, or a `Route`.
+    pub fn from_args(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<LaunchAction>, String> {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => return Ok(None),
+        };
+        let action = if arg == "--compose" {
+            LaunchAction::Share {
+                text: args.collect::<Vec<_>>().join(" "),
+            }
+        } else if arg.starts_with(PROTOCOL) {
+            LaunchAction::from_protocol_link(&arg)
+                .ok_or_else(|| format!("Not a link hedgehog understands: {}", arg))?
+        } else if arg.starts_with("https://") || arg.starts_with("http://") {
+            LaunchAction::Open(arg)
+        } else {
+            Route::from_arg(&arg)
+                .map(LaunchAction::Go)
+                .ok_or_else(|| format!("Unknown argument: {}", arg))?
+        };
+        match args.next() {
+            Some(extra) => Err(format!("Unexpected argument: {}", extra)),
+            None => Ok(Some(action)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn shares_leave_out_repeated_links() {
+        let query = "?title=Hi&text=Look%20at%20this%20https%3A%2F%2Fex.social%2F%40a\
+                     &url=https%3A%2F%2Fex.social%2F%40a";
+        assert_eq!(
+            LaunchAction::from_query(query),
+            Some(LaunchAction::Share {
+                text: "Hi\n\nLook at this https://ex.social/@a".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn queries_without_a_share_do_nothing() {
+        assert_eq!(LaunchAction::from_query(""), None);
+        assert_eq!(LaunchAction::from_query("?"), None);
+        assert_eq!(LaunchAction::from_query("?utm_source=homescreen"), None);
+        assert_eq!(LaunchAction::from_query("?text=%20"), None);
+    }
+
+    #[test]
+    fn protocol_links_come_in_through_the_handler_parameter() {
+        assert_eq!(
+            LaunchAction::from_query(
+                "?handler=web%2Bmastodon%3A%2F%2Ffollow%3Furi%3Dacct%3Aa%40b.social"
+            ),
+            Some(LaunchAction::Go(Route::Profile("a@b.social".to_string())))
+        );
+    }
+
+    #[test]
+    fn reads_protocol_links() {
+        assert_eq!(
+            LaunchAction::from_protocol_link("web+mastodon://share?text=Hello%20there"),
+            Some(LaunchAction::Share {
+                text: "Hello there".to_string()
+            })
+        );
+        assert_eq!(
+            LaunchAction::from_protocol_link("web+mastodon://follow?uri=acct:@a@b.social"),
+            Some(LaunchAction::Go(Route::Profile("a@b.social".to_string())))
+        );
+        assert_eq!(
+            LaunchAction::from_protocol_link("web+mastodon://ex.social/@a/123"),
+            Some(LaunchAction::Open("https://ex.social/@a/123".to_string()))
+        );
+        assert_eq!(
+            LaunchAction::from_protocol_link("web+mastodon://share"),
+            None
+        );
+        assert_eq!(
+            LaunchAction::from_protocol_link("https://ex.social/@a"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_command_line_arguments() {
+        assert_eq!(LaunchAction::from_args(args(&[])), Ok(None));
+        assert_eq!(
+            LaunchAction::from_args(args(&["--compose", "hello", "world"])),
+            Ok(Some(LaunchAction::Share {
+                text: "hello world".to_string()
+            }))
+        );
+        assert_eq!(
+            LaunchAction::from_args(args(&["https://ex.social/@a"])),
+            Ok(Some(LaunchAction::Open("https://ex.social/@a".to_string())))
+        );
+        assert_eq!(
+            LaunchAction::from_args(args(&["#rust"])),
+            Ok(Some(LaunchAction::Go(Route::Hashtag("rust".to_string()))))
+        );
+        assert_eq!(
+            LaunchAction::from_args(args(&["settings"])),
+            Ok(Some(LaunchAction::Go(Route::Settings)))
+        );
+    }
+
+    #[test]
+    fn refuses_arguments_it_doesnt_understand() {
+        assert!(LaunchAction::from_args(args(&["nonsense"])).is_err());
+        assert!(LaunchAction::from_args(args(&["web+mastodon://share"])).is_err());
+        assert!(LaunchAction::from_args(args(&["settings", "compose"])).is_err());
+    }
+}
@@ -1,7 +1,37 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+pub mod accounts;
+pub mod api;
 pub mod app;
 pub mod authenticate;
+pub mod cache;
 pub mod channels;
+pub mod datetime;
+pub mod diagnostics;
+pub mod diff;
+pub mod drafts;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fixture;
+pub mod html;
+pub mod images;
+pub mod launch;
+pub mod links;
+pub mod moderation;
+pub mod outbox;
+pub mod ratelimit;
+#[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+pub mod record;
+pub mod redact;
+pub mod registry;
+pub mod reports;
+pub mod router;
+pub mod search;
 pub mod service;
+pub mod session;
+pub mod statuses;
+pub mod timeline;
+pub mod timer;
+pub mod views;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
 pub use app::TemplateApp;
@@ -0,0 +1,73 @@
+//! Recognizing links in status content which probably point at posts or profiles on other
+//! fediverse servers, so they can be opened in the app instead of the browser.
+
+/// Path shapes used by common fediverse software for profiles and posts.
+const PROFILE_AND_POST_PREFIXES: &[&str] = &[
+    "/@",       // mastodon, gotosocial, misskey
+    "/users/",  // mastodon activitypub ids, gotosocial
+    "/notice/", // pleroma, akkoma
+    "/objects/",
+    "/notes/", // misskey, firefish
+    "/u/",     // lemmy, kbin
+    "/post/",  // lemmy
+    "/p/",     // pixelfed
+    "/profile/",
+    "/statuses/",
+];
+
+/// Whether clicking this link should try to resolve it on the user's instance first.
+pub fn is_likely_fediverse_url(url: &str) -> bool {
+    let rest = match url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let path = match rest.find('/') {
+        Some(i) => &rest[i..],
+        None => return false,
+    };
+    // Hashtag links can't be resolved through search.
+    if path.starts_with("/tags/") || path.starts_with("/tag/") {
+        return false;
+    }
+    PROFILE_AND_POST_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix) && path.len() > prefix.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_profiles_and_posts() {
+        for url in [
+            "https://b.social/@a",
+            "https://b.social/@a/123",
+            "http://b.social/users/a/statuses/123",
+            "https://pleroma.example/notice/AbC",
+            "https://misskey.example/notes/9abc",
+            "https://pixelfed.example/p/a/123",
+        ] {
+            assert!(is_likely_fediverse_url(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn leaves_other_links_to_the_browser() {
+        for url in [
+            "https://b.social",
+            "https://b.social/",
+            "https://b.social/@",
+            "https://b.social/tags/rust",
+            "https://b.social/tag/rust",
+            "https://example.com/about",
+            "mailto:a@b.social",
+            "b.social/@a",
+        ] {
+            assert!(!is_likely_fediverse_url(url), "{}", url);
+        }
+    }
+}
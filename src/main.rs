@@ -6,18 +6,27 @@
 fn main() -> eframe::Result<()> {
     use instant::Duration;
 
-    use hedgehog::{
-        app::AsyncAppState,
-        channels::{new_channel_pair, AsyncRequestBridge, Spawner},
-        service::{new_async_service_channels, start_async_service, AsyncServiceMessage},
-    };
-    use log::{debug, trace, warn};
-    use tokio::runtime::Runtime;
+    use hedgehog::service::start_services;
+    use log::warn;
+
+    // Log to stderr (if you run with `RUST_LOG=debug`), and to the diagnostics window.
+    let logger = env_logger::Builder::from_default_env().build();
+    let level = logger.filter();
+    hedgehog::diagnostics::init_logger(Box::new(logger), level);
+
+    // Read the timezone while there's still only one thread.
+    hedgehog::datetime::local_offset();
 
-    env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    // e.g. `hedgehog @user@host`, `hedgehog https://host/@user/123` or `hedgehog --compose hi`
+    let launch = match hedgehog::launch::LaunchAction::from_args(std::env::args().skip(1)) {
+        Ok(launch) => launch,
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    };
 
-    let svc_async_tx = start_async_service();
-    let ui_bridge = AsyncRequestBridge::<AsyncServiceMessage, AsyncAppState>::new(svc_async_tx);
+    let services = start_services();
 
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -33,34 +42,39 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "eframe template",
         native_options,
-        Box::new(|cc| Box::new(hedgehog::TemplateApp::new(cc, ui_bridge))),
+        Box::new(|cc| Box::new(hedgehog::TemplateApp::new(cc, services, launch))),
     )
 }
 
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
 fn main() {
-    use hedgehog::{
-        app::AsyncAppState,
-        channels::{new_channel_pair, AsyncRequestBridge, Spawner},
-        service::{new_async_service_channels, start_async_service, AsyncServiceMessage},
-    };
-    use log::{debug, trace, warn};
+    use hedgehog::service::start_services;
 
-    // Redirect `log` message to `console.log` and friends:
-    eframe::WebLogger::init(log::LevelFilter::Debug).ok();
+    // Redirect `log` message to `console.log` and friends, and to the diagnostics window:
+    let level = log::LevelFilter::Debug;
+    hedgehog::diagnostics::init_logger(Box::new(eframe::WebLogger::new(level)), level);
 
     let web_options = eframe::WebOptions::default();
 
-    let svc_async_tx = start_async_service();
-    let ui_bridge = AsyncRequestBridge::<AsyncServiceMessage, AsyncAppState>::new(svc_async_tx);
+    // Opened from another app's share sheet, or a web+mastodon: link (see manifest.json), or
+    // a link to somewhere in the app.
+    let launch = hedgehog::web::take_launch_query()
+        .and_then(|query| hedgehog::launch::LaunchAction::from_query(&query))
+        .or_else(|| {
+            hedgehog::web::fragment()
+                .and_then(|fragment| hedgehog::router::Route::from_fragment(&fragment))
+                .map(hedgehog::launch::LaunchAction::Go)
+        });
+
+    let services = start_services();
 
     wasm_bindgen_futures::spawn_local(async {
         eframe::WebRunner::new()
             .start(
                 "the_canvas_id", // hardcode it
                 web_options,
-                Box::new(|cc| Box::new(hedgehog::TemplateApp::new(cc, ui_bridge))),
+                Box::new(|cc| Box::new(hedgehog::TemplateApp::new(cc, services, launch))),
             )
             .await
             .expect("failed to start eframe");
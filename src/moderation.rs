@@ -0,0 +1,286 @@
+use std::{collections::HashMap, fmt};
+
+use instant::Duration;
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use reqwest::{Method, RequestBuilder};
+use serde::de::IgnoredAny;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_json, send_page, ApiPage, RawApi},
+    channels::{cancellable, send_reply, Message, Spawner},
+    ratelimit::Priority,
+    registry::{Service, ServiceFuture},
+};
+
+/// Mutes, blocks and blocked domains.
+pub struct ModerationService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for ModerationService {
+    type Message = ModerationMessage;
+
+    fn run(
+        self,
+        rx: mpsc::Receiver<Message<ModerationMessage>>,
+        _spawner: Spawner,
+    ) -> ServiceFuture {
+        Box::pin(start_moderation_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_moderation_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<ModerationMessage>>,
+) {
+    let mut state = ModerationState {
+        api: RawApi::new(&mastodon),
+        pages: HashMap::new(),
+    };
+
+    debug!("entered moderation service");
+
+    loop {
+        // wait for messages
+        match rx.recv().await {
+            Some(rx) => match rx {
+                Message::Request { msg, mut reply } => {
+                    let Some(result) = cancellable(&mut reply, state.handle(msg)).await else {
+                        debug!("Moderation request was cancelled");
+                        continue;
+                    };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Moderation request failed: {}", e);
+                            ModerationMessage::Error(e)
+                        }
+                    };
+                    if send_reply(reply, response).is_err() {
+                        warn!("Failed to send moderation reply");
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Moderation service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ModerationList {
+    Mutes,
+    Blocks,
+    DomainBlocks,
+}
+
+impl fmt::Display for ModerationList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModerationList::Mutes => write!(f, "muted accounts"),
+            ModerationList::Blocks => write!(f, "blocked accounts"),
+            ModerationList::DomainBlocks => write!(f, "blocked domains"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ModerationEntry {
+    Account(Account),
+    Domain(String),
+}
+
+/// A moderation action which has been applied on the server.
+#[derive(Clone, Debug)]
+pub enum ModerationEvent {
+    Muted(AccountId),
+    Unmuted(AccountId),
+    Blocked(AccountId),
+    Unblocked(AccountId),
+    DomainBlocked(String),
+    DomainUnblocked(String),
+}
+
+#[derive(Debug)]
+pub enum ModerationMessage {
+    Mute {
+        account: AccountId,
+        /// Whether notifications from the account should be hidden too.
+        notifications: bool,
+        /// How long until the mute expires, or forever if none.
+        duration: Option<Duration>,
+    },
+    Unmute(AccountId),
+    Block(AccountId),
+    Unblock(AccountId),
+    BlockDomain(String),
+    UnblockDomain(String),
+    Applied(ModerationEvent),
+    LoadList(ModerationList),
+    NextPage(ModerationList),
+    ListPage {
+        list: ModerationList,
+        entries: Vec<ModerationEntry>,
+        has_more: bool,
+        replace: bool,
+    },
+    Error(String),
+}
+
+struct ModerationState {
+    api: RawApi,
+    /// The url of each list's next page, or `None` once there are no more.
+    pages: HashMap<ModerationList, Option<String>>,
+}
+
+impl ModerationState {
+    async fn handle(&mut self, msg: ModerationMessage) -> Result<ModerationMessage, String> {
+        let event = match msg {
+            ModerationMessage::Mute {
+                account,
+                notifications,
+                duration,
+            } => {
+                let mut form = vec![("notifications", notifications.to_string())];
+                if let Some(duration) = duration {
+                    form.push(("duration", duration.as_secs().to_string()));
+                }
+                let _: IgnoredAny = send_json(
+                    self.api
+                        .post(&format!("/api/v1/accounts/{}/mute", account))
+                        .form(&form),
+                )
+                .await
+                .map_err(|e| format!("Failed to mute: {}", e))?;
+                ModerationEvent::Muted(account)
+            }
+            ModerationMessage::Unmute(account) => {
+                self.post(&format!("/api/v1/accounts/{}/unmute", account))
+                    .await
+                    .map_err(|e| format!("Failed to unmute: {}", e))?;
+                ModerationEvent::Unmuted(account)
+            }
+            ModerationMessage::Block(account) => {
+                self.post(&format!("/api/v1/accounts/{}/block", account))
+                    .await
+                    .map_err(|e| format!("Failed to block: {}", e))?;
+                ModerationEvent::Blocked(account)
+            }
+            ModerationMessage::Unblock(account) => {
+                self.post(&format!("/api/v1/accounts/{}/unblock", account))
+                    .await
+                    .map_err(|e| format!("Failed to unblock: {}", e))?;
+                ModerationEvent::Unblocked(account)
+            }
+            ModerationMessage::BlockDomain(domain) => {
+                let _: IgnoredAny = send_json(
+                    self.api
+                        .post("/api/v1/domain_blocks")
+                        .form(&[("domain", &domain)]),
+                )
+                .await
+                .map_err(|e| format!("Failed to block {}: {}", domain, e))?;
+                ModerationEvent::DomainBlocked(domain)
+            }
+            ModerationMessage::UnblockDomain(domain) => {
+                let _: IgnoredAny = send_json(
+                    self.api
+                        .request(Method::DELETE, "/api/v1/domain_blocks")
+                        .query(&[("domain", &domain)]),
+                )
+                .await
+                .map_err(|e| format!("Failed to unblock {}: {}", domain, e))?;
+                ModerationEvent::DomainUnblocked(domain)
+            }
+            ModerationMessage::LoadList(list) => return self.load_list(list).await,
+            ModerationMessage::NextPage(list) => return self.next_page(list).await,
+            other => return Err(format!("Unexpected moderation request: {:?}", other)),
+        };
+        Ok(ModerationMessage::Applied(event))
+    }
+
+    /// A post with nothing to send, whose answer isn't needed.
+    async fn post(&self, path: &str) -> Result<(), String> {
+        let _: IgnoredAny = send_json(self.api.post(path)).await?;
+        Ok(())
+    }
+
+    async fn load_list(&mut self, list: ModerationList) -> Result<ModerationMessage, String> {
+        let path = match list {
+            ModerationList::Mutes => "/api/v1/mutes",
+            ModerationList::Blocks => "/api/v1/blocks",
+            ModerationList::DomainBlocks => "/api/v1/domain_blocks",
+        };
+        let request = self.api.get(path);
+        let entries = self
+            .fetch(list, request)
+            .await
+            .map_err(|e| format!("Failed to load {}: {}", list, e))?;
+        Ok(ModerationMessage::ListPage {
+            list,
+            has_more: !entries.is_empty(),
+            entries,
+            replace: true,
+        })
+    }
+
+    async fn next_page(&mut self, list: ModerationList) -> Result<ModerationMessage, String> {
+        let next = self
+            .pages
+            .get(&list)
+            .ok_or_else(|| format!("No {} have been loaded yet", list))?;
+        let entries = match next {
+            Some(url) => {
+                let request = self.api.follow(url);
+                self.fetch(list, request)
+                    .await
+                    .map_err(|e| format!("Failed to load more {}: {}", list, e))?
+            }
+            None => vec![],
+        };
+        Ok(ModerationMessage::ListPage {
+            list,
+            has_more: !entries.is_empty(),
+            entries,
+            replace: false,
+        })
+    }
+
+    /// Fetch a page of `list`, remembering where the next one is.
+    async fn fetch(
+        &mut self,
+        list: ModerationList,
+        request: RequestBuilder,
+    ) -> Result<Vec<ModerationEntry>, String> {
+        let (entries, next) = match list {
+            ModerationList::Mutes | ModerationList::Blocks => {
+                let page: ApiPage<Account> = send_page(request, Priority::User).await?;
+                (accounts_to_entries(page.items), page.next)
+            }
+            ModerationList::DomainBlocks => {
+                let page: ApiPage<String> = send_page(request, Priority::User).await?;
+                (domains_to_entries(page.items), page.next)
+            }
+        };
+        self.pages.insert(list, next);
+        Ok(entries)
+    }
+}
+
+fn accounts_to_entries(accounts: Vec<Account>) -> Vec<ModerationEntry> {
+    accounts.into_iter().map(ModerationEntry::Account).collect()
+}
+
+fn domains_to_entries(domains: Vec<String>) -> Vec<ModerationEntry> {
+    domains.into_iter().map(ModerationEntry::Domain).collect()
+}
+
+/// The domain of a remote account, or none for accounts on the signed in instance.
+pub fn account_domain(account: &Account) -> Option<&str> {
+    account.acct.split_once('@').map(|(_, domain)| domain)
+}
@@ -0,0 +1,392 @@
+//! Actions that change something on the server, kept until the server has accepted them.
+//!
+//! Anything that fails because the server couldn't be reached is retried with exponential
+//! backoff, and the pending items are handed to the ui to be persisted between runs.
+
+use std::collections::HashMap;
+
+use futures::future::{select, Either};
+use instant::{Duration, Instant};
+use log::{debug, warn};
+use mastodon_async::Mastodon;
+use serde::de::IgnoredAny;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    api::{try_send_json_with, ApiError, RawApi, RetryPolicy},
+    channels::{send_reply, Message, Spawner, REQUEST_TIMEOUT},
+    ratelimit::Priority,
+    registry::{Service, ServiceFuture},
+    statuses::ComposeDraft,
+    timer::{sleep, timeout},
+};
+
+const FIRST_RETRY: Duration = Duration::from_secs(2);
+const LONGEST_RETRY: Duration = Duration::from_secs(5 * 60);
+
+/// Pending outbox items for every account that has signed in, kept in eframe's persistence.
+pub type OutboxStore = HashMap<String, Vec<OutboxItem>>;
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum OutboxAction {
+    Favourite { status: String, on: bool },
+    Boost { status: String, on: bool },
+    Bookmark { status: String, on: bool },
+    Post(ComposeDraft),
+}
+
+impl OutboxAction {
+    /// Toggles on the same status and of the same kind supersede each other.
+    fn conflicts_with(&self, other: &OutboxAction) -> bool {
+        use OutboxAction::*;
+        match (self, other) {
+            (Favourite { status: a, .. }, Favourite { status: b, .. })
+            | (Boost { status: a, .. }, Boost { status: b, .. })
+            | (Bookmark { status: a, .. }, Bookmark { status: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+
+    fn toggle(&self) -> Option<bool> {
+        match self {
+            OutboxAction::Favourite { on, .. }
+            | OutboxAction::Boost { on, .. }
+            | OutboxAction::Bookmark { on, .. } => Some(*on),
+            OutboxAction::Post(_) => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let verb = match self {
+            OutboxAction::Favourite { on: true, .. } => "Favourite",
+            OutboxAction::Favourite { on: false, .. } => "Unfavourite",
+            OutboxAction::Boost { on: true, .. } => "Boost",
+            OutboxAction::Boost { on: false, .. } => "Undo boost",
+            OutboxAction::Bookmark { on: true, .. } => "Bookmark",
+            OutboxAction::Bookmark { on: false, .. } => "Remove bookmark",
+            OutboxAction::Post(draft) => {
+                let preview: String = draft.text.chars().take(40).collect();
+                return format!("Post \"{}\"", preview);
+            }
+        };
+        format!("{} a post", verb)
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct OutboxItem {
+    pub id: u64,
+    /// Sent as an `Idempotency-Key`, so a post retried after a lost response isn't published twice.
+    pub key: String,
+    pub action: OutboxAction,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// The server refused the action, so it won't be retried unless the user asks.
+    pub failed: bool,
+    #[serde(skip)]
+    retry_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub enum OutboxMessage {
+    Enqueue(OutboxAction),
+    /// Put back items saved by an earlier run.
+    Restore(Vec<OutboxItem>),
+    /// Try everything again straight away, including items the server refused.
+    RetryAll,
+    Discard(u64),
+    /// Replies once the outbox has moved on from `version`, so the ui doesn't have to poll.
+    Watch(u64),
+    Changed {
+        version: u64,
+        items: Vec<OutboxItem>,
+    },
+}
+
+#[derive(Default)]
+struct Outbox {
+    items: Vec<OutboxItem>,
+    next_id: u64,
+    version: u64,
+}
+
+impl Outbox {
+    fn push(&mut self, action: OutboxAction) {
+        // A toggle that hasn't been sent yet is cancelled out by its opposite, and replaced by
+        // a repeat of itself.
+        if let Some(index) = self
+            .items
+            .iter()
+            .position(|item| item.action.conflicts_with(&action))
+        {
+            let existing = self.items.remove(index);
+            self.version += 1;
+            if existing.action.toggle() != action.toggle() {
+                debug!("Outbox dropped {:?} and its opposite", existing.action);
+                return;
+            }
+        }
+        self.next_id += 1;
+        self.items.push(OutboxItem {
+            id: self.next_id,
+            key: format!(
+                "hedgehog-{}-{}",
+                time::OffsetDateTime::now_utc().unix_timestamp_nanos(),
+                self.next_id
+            ),
+            action,
+            attempts: 0,
+            last_error: None,
+            failed: false,
+            retry_at: None,
+        });
+        self.version += 1;
+    }
+
+    fn restore(&mut self, items: Vec<OutboxItem>) {
+        for mut item in items {
+            self.next_id += 1;
+            item.id = self.next_id;
+            item.retry_at = None;
+            self.items.push(item);
+        }
+        self.version += 1;
+    }
+
+    fn retry_all(&mut self) {
+        for item in self.items.iter_mut() {
+            item.failed = false;
+            item.retry_at = None;
+        }
+        self.version += 1;
+    }
+
+    fn discard(&mut self, id: u64) {
+        self.items.retain(|item| item.id != id);
+        self.version += 1;
+    }
+
+    /// The oldest item that's ready to be sent.
+    fn due(&self, now: Instant) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| !item.failed && item.retry_at.map_or(true, |at| at <= now))
+    }
+
+    /// How long until the next retry, if anything is waiting for one.
+    fn next_wait(&self, now: Instant) -> Option<Duration> {
+        self.items
+            .iter()
+            .filter(|item| !item.failed)
+            .filter_map(|item| item.retry_at)
+            .min()
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    fn record(&mut self, index: usize, result: Result<(), ApiError>) {
+        self.version += 1;
+        match result {
+            Ok(()) => {
+                self.items.remove(index);
+            }
+            Err(e) => {
+                let item = &mut self.items[index];
+                item.attempts += 1;
+                item.failed = !e.retryable;
+                item.last_error = Some(e.message);
+                let backoff = FIRST_RETRY
+                    .saturating_mul(2u32.saturating_pow(item.attempts - 1))
+                    .min(LONGEST_RETRY);
+                item.retry_at = Some(Instant::now() + backoff);
+            }
+        }
+    }
+}
+
+/// Sends queued actions until the server accepts them.
+pub struct OutboxService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for OutboxService {
+    type Message = OutboxMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<OutboxMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_outbox_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_outbox_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<OutboxMessage>>,
+) {
+    let api = RawApi::new(&mastodon);
+    let mut outbox = Outbox::default();
+    let mut watcher: Option<(u64, oneshot::Sender<OutboxMessage>)> = None;
+
+    debug!("entered outbox service");
+
+    loop {
+        notify(&mut watcher, &outbox);
+
+        let now = Instant::now();
+        if let Some(index) = outbox.due(now) {
+            attempt(&api, &mut outbox, index).await;
+            continue;
+        }
+
+        // wait for messages, or for the next retry to come due
+        let incoming = match outbox.next_wait(now) {
+            Some(wait) => match select(Box::pin(rx.recv()), Box::pin(sleep(wait))).await {
+                Either::Left((incoming, _)) => incoming,
+                Either::Right(_) => continue,
+            },
+            None => rx.recv().await,
+        };
+        let msg = match incoming {
+            Some(Message::Notification { msg }) => msg,
+            Some(Message::Request { msg, reply }) => match msg {
+                OutboxMessage::Watch(version) => {
+                    watcher = Some((version, reply));
+                    continue;
+                }
+                // Everything else is fire and forget, the result shows up through `Watch`.
+                msg => msg,
+            },
+            None => {
+                // The app is closing, after its last save. Anything sent now would be sent again
+                // next time, so it's all left until then.
+                debug!("Outbox service out of messages");
+                break;
+            }
+        };
+        match msg {
+            OutboxMessage::Enqueue(action) => outbox.push(action),
+            OutboxMessage::Restore(items) => outbox.restore(items),
+            OutboxMessage::RetryAll => outbox.retry_all(),
+            OutboxMessage::Discard(id) => outbox.discard(id),
+            _ => warn!("Unhandled mssage type"),
+        }
+    }
+}
+
+async fn attempt(api: &RawApi, outbox: &mut Outbox, index: usize) {
+    let result = match timeout(REQUEST_TIMEOUT, send(api, &outbox.items[index])).await {
+        Ok(result) => result,
+        Err(_) => Err(ApiError {
+            message: "The server took too long to answer".to_string(),
+            retryable: true,
+        }),
+    };
+    if let Err(e) = &result {
+        warn!("Outbox action failed: {}", e);
+    }
+    outbox.record(index, result);
+}
+
+fn notify(watcher: &mut Option<(u64, oneshot::Sender<OutboxMessage>)>, outbox: &Outbox) {
+    if !matches!(watcher, Some((version, _)) if *version != outbox.version) {
+        return;
+    }
+    if let Some((_, reply)) = watcher.take() {
+        let changed = OutboxMessage::Changed {
+            version: outbox.version,
+            items: outbox.items.clone(),
+        };
+        if send_reply(reply, changed).is_err() {
+            debug!("Outbox watcher went away");
+        }
+    }
+}
+
+async fn send(api: &RawApi, item: &OutboxItem) -> Result<(), ApiError> {
+    let toggle = |id: &str, on: bool, yes: &str, no: &str| {
+        let action = if on { yes } else { no };
+        api.post(&format!("/api/v1/statuses/{}/{}", id, action))
+    };
+    let request = match &item.action {
+        OutboxAction::Favourite { status, on } => toggle(status, *on, "favourite", "unfavourite"),
+        OutboxAction::Boost { status, on } => toggle(status, *on, "reblog", "unreblog"),
+        OutboxAction::Bookmark { status, on } => toggle(status, *on, "bookmark", "unbookmark"),
+        OutboxAction::Post(draft) => api
+            .post("/api/v1/statuses")
+            .header("Idempotency-Key", &item.key)
+            .form(&draft.form()),
+    };
+    // The user is waiting on a first attempt, but retries can wait for the rate limit.
+    let priority = if item.attempts == 0 {
+        Priority::User
+    } else {
+        Priority::Background
+    };
+    // The outbox backs off and retries by itself, keeping the item's state up to date.
+    let _: IgnoredAny = try_send_json_with(request, priority, RetryPolicy::NONE).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn favourite(status: &str, on: bool) -> OutboxAction {
+        OutboxAction::Favourite {
+            status: status.to_string(),
+            on,
+        }
+    }
+
+    fn actions(outbox: &Outbox) -> Vec<OutboxAction> {
+        outbox
+            .items
+            .iter()
+            .map(|item| item.action.clone())
+            .collect()
+    }
+
+    #[test]
+    fn opposite_toggles_cancel_out() {
+        let mut outbox = Outbox::default();
+        outbox.push(favourite("1", true));
+        outbox.push(favourite("1", false));
+        assert!(outbox.items.is_empty());
+    }
+
+    #[test]
+    fn repeated_toggles_are_sent_once() {
+        let mut outbox = Outbox::default();
+        outbox.push(favourite("1", true));
+        outbox.push(favourite("1", true));
+        assert_eq!(actions(&outbox), vec![favourite("1", true)]);
+    }
+
+    #[test]
+    fn toggles_only_replace_their_own_kind_on_the_same_status() {
+        let mut outbox = Outbox::default();
+        outbox.push(favourite("1", true));
+        outbox.push(favourite("2", false));
+        outbox.push(OutboxAction::Boost {
+            status: "1".to_string(),
+            on: false,
+        });
+        assert_eq!(outbox.items.len(), 3);
+    }
+
+    #[test]
+    fn posts_are_never_merged() {
+        let mut outbox = Outbox::default();
+        outbox.push(OutboxAction::Post(ComposeDraft::default()));
+        outbox.push(OutboxAction::Post(ComposeDraft::default()));
+        assert_eq!(outbox.items.len(), 2);
+        // Each is sent with a key of its own, so the server doesn't take one for the other.
+        assert_ne!(outbox.items[0].key, outbox.items[1].key);
+    }
+
+    #[test]
+    fn every_change_moves_the_version_on() {
+        let mut outbox = Outbox::default();
+        outbox.push(favourite("1", true));
+        let version = outbox.version;
+        outbox.push(favourite("1", false));
+        assert!(outbox.version > version);
+    }
+}
@@ -0,0 +1,223 @@
+//! Keeping within an instance's rate limits.
+//!
+//! Mastodon sends `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` with its
+//! responses, and a 429 with `Retry-After` once the budget is spent. Each instance's budgets are
+//! tracked here, shared by every service talking to it. Background fetches wait for the budget to
+//! reset once it gets low, so whatever is left goes to things the user asked for.
+//!
+//! Every request the services make goes through `api`, which waits its turn here and reports the
+//! headers that came back.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use instant::{Duration, Instant};
+use log::debug;
+use reqwest::{header::HeaderMap, Method, StatusCode, Url};
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+};
+
+use crate::timer::sleep;
+
+/// The share of an instance's budget kept back for the user, when it's running low.
+const RESERVE_PERCENT: u32 = 10;
+/// Keep at least this many requests back, however small the budget.
+const MIN_RESERVE: u32 = 5;
+/// How long to hold off after a 429 that didn't say how long to wait.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Never sit on a request for longer than this, in case a reset time was nonsense.
+const LONGEST_WAIT: Duration = Duration::from_secs(5 * 60);
+
+/// Mastodon limits media uploads and status deletions separately from everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    Api,
+    Media,
+    Deletes,
+}
+
+impl EndpointClass {
+    pub fn of(method: &Method, path: &str) -> EndpointClass {
+        if *method == Method::POST && (path == "/api/v1/media" || path == "/api/v2/media") {
+            EndpointClass::Media
+        } else if *method == Method::DELETE && path.starts_with("/api/v1/statuses/") {
+            EndpointClass::Deletes
+        } else {
+            EndpointClass::Api
+        }
+    }
+}
+
+/// Who's waiting for a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// The user did something and is waiting to see what happens.
+    User,
+    /// Prefetching, refreshing and retrying, which can wait.
+    Background,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Budget {
+    limit: u32,
+    remaining: u32,
+    reset_at: Option<Instant>,
+    /// Set by a 429, when nothing should be sent at all.
+    blocked_until: Option<Instant>,
+}
+
+impl Budget {
+    fn reserve(&self) -> u32 {
+        (self.limit * RESERVE_PERCENT / 100).max(MIN_RESERVE)
+    }
+
+    /// How long a request should wait before it's sent.
+    fn delay(&self, priority: Priority, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.blocked_until.filter(|until| *until > now) {
+            return Some(until - now);
+        }
+        let reset_in = self.reset_at.filter(|at| *at > now).map(|at| at - now)?;
+        let needed = match priority {
+            Priority::User => 0,
+            Priority::Background => self.reserve(),
+        };
+        (self.remaining <= needed).then_some(reset_in)
+    }
+}
+
+/// How an instance's main budget stands, for the ui.
+#[derive(Clone, Copy, Debug)]
+pub struct RateStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    pub resets_in: Option<Duration>,
+    /// The instance refused requests, and asked for none until this has passed.
+    pub blocked_for: Option<Duration>,
+}
+
+impl RateStatus {
+    /// Whether background fetches are being held back.
+    pub fn is_low(&self) -> bool {
+        self.blocked_for.is_some() || self.remaining * 100 <= self.limit * RESERVE_PERCENT
+    }
+}
+
+/// The budgets of one instance. Clones share them.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    budgets: Arc<Mutex<HashMap<EndpointClass, Budget>>>,
+}
+
+/// The limiter for an instance, given its base url such as `https://example.social`.
+pub fn for_instance(base: &str) -> RateLimiter {
+    match Url::parse(base) {
+        Ok(url) => for_url(&url),
+        Err(_) => limiter(base.trim_end_matches('/').to_string()),
+    }
+}
+
+/// The limiter for the instance a request is going to.
+pub fn for_url(url: &Url) -> RateLimiter {
+    limiter(url.origin().ascii_serialization())
+}
+
+fn limiter(key: String) -> RateLimiter {
+    static LIMITERS: OnceLock<Mutex<HashMap<String, RateLimiter>>> = OnceLock::new();
+    let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap();
+    limiters.entry(key).or_default().clone()
+}
+
+impl RateLimiter {
+    /// Wait until a request can be sent without going over the instance's limits.
+    pub async fn wait(&self, class: EndpointClass, priority: Priority) {
+        let delay = self
+            .budgets
+            .lock()
+            .unwrap()
+            .get(&class)
+            .and_then(|budget| budget.delay(priority, Instant::now()));
+        if let Some(delay) = delay {
+            debug!(
+                "Holding a {:?} request for {:?} for {:?}",
+                priority, class, delay
+            );
+            sleep(delay.min(LONGEST_WAIT)).await;
+        }
+    }
+
+    /// Take note of the rate limit headers of a response.
+    pub fn record(&self, class: EndpointClass, status: StatusCode, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+
+        let limit = header("x-ratelimit-limit").and_then(|v| v.parse().ok());
+        let remaining = header("x-ratelimit-remaining").and_then(|v| v.parse().ok());
+        if let (Some(limit), Some(remaining)) = (limit, remaining) {
+            let reset_at = header("x-ratelimit-reset").and_then(|v| instant_from_date(v, now));
+            let budget = budgets.entry(class).or_insert(Budget {
+                limit,
+                remaining,
+                reset_at,
+                blocked_until: None,
+            });
+            budget.limit = limit;
+            budget.remaining = remaining;
+            budget.reset_at = reset_at;
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = header("retry-after").and_then(|v| match v.parse::<u64>() {
+                Ok(seconds) => Some(now + Duration::from_secs(seconds)),
+                Err(_) => instant_from_date(v, now),
+            });
+            let budget = budgets.entry(class).or_insert(Budget {
+                limit: 0,
+                remaining: 0,
+                reset_at: None,
+                blocked_until: None,
+            });
+            budget.remaining = 0;
+            budget.blocked_until = retry_after
+                .or(budget.reset_at)
+                .or(Some(now + DEFAULT_RETRY_AFTER));
+        } else if let Some(budget) = budgets.get_mut(&class) {
+            budget.blocked_until = None;
+        }
+    }
+
+    /// The main budget, once the instance has said what it is.
+    pub fn status(&self) -> Option<RateStatus> {
+        let now = Instant::now();
+        let budgets = self.budgets.lock().unwrap();
+        let budget = budgets.get(&EndpointClass::Api)?;
+        let resets_in = budget.reset_at.filter(|at| *at > now).map(|at| at - now);
+        Some(RateStatus {
+            limit: budget.limit,
+            // Once the reset has passed, the whole budget is back.
+            remaining: if resets_in.is_some() {
+                budget.remaining
+            } else {
+                budget.limit
+            },
+            resets_in,
+            blocked_for: budget
+                .blocked_until
+                .filter(|until| *until > now)
+                .map(|until| until - now),
+        })
+    }
+}
+
+/// Headers give times as dates, RFC 3339 for the reset and http dates for `Retry-After`.
+fn instant_from_date(value: &str, now: Instant) -> Option<Instant> {
+    let at = OffsetDateTime::parse(value, &Rfc3339)
+        .or_else(|_| OffsetDateTime::parse(value, &Rfc2822))
+        .ok()?;
+    let wait = at - OffsetDateTime::now_utc();
+    Some(now + Duration::try_from(wait).unwrap_or(Duration::ZERO))
+}
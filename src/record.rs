@@ -0,0 +1,245 @@
+//! Recording the app's conversation with an instance, to replay in tests.
+//!
+//! Only built with the `record` feature, so release builds don't carry the proxy. Run with
+//! `cargo run --features record`, set `HEDGEHOG_RECORD_FIXTURE` to a file and sign in, and every
+//! request the session makes is written to it along with the response, in the format of
+//! `fixture`. `HEDGEHOG_RECORD_SOFTWARE` says what the instance runs, e.g. `GoToSocial 0.13.0`.
+//!
+//! The session is pointed at a proxy on localhost, which passes requests on to the instance and
+//! writes them down, so what's recorded is exactly what went over the wire.
+//! Tokens, client secrets and auth codes are redacted before anything is written, and the
+//! instance's own address is replaced so replays can be served from anywhere. The streaming api
+//! isn't recorded.
+
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use hyper::{
+    header::{self, HeaderValue},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use log::{debug, warn};
+use tokio::sync::oneshot;
+
+use crate::{
+    authenticate::build_http_client,
+    fixture::{Exchange, Fixture, BASE_PLACEHOLDER},
+    redact,
+};
+
+/// The response headers the app looks at. Everything else is left out of fixtures.
+const KEPT_HEADERS: &[&str] = &[
+    "content-type",
+    "link",
+    "x-ratelimit-limit",
+    "x-ratelimit-remaining",
+    "x-ratelimit-reset",
+    "retry-after",
+];
+
+/// Start recording the session with `instance`, if `HEDGEHOG_RECORD_FIXTURE` asks for it.
+pub fn from_env(instance: &str) -> Option<Recording> {
+    let path = std::env::var_os("HEDGEHOG_RECORD_FIXTURE")?;
+    let software = std::env::var("HEDGEHOG_RECORD_SOFTWARE").unwrap_or_default();
+    let upstream = if instance.contains("://") {
+        instance.to_string()
+    } else {
+        format!("https://{}", instance)
+    };
+    match Recording::start(&upstream, PathBuf::from(path), software) {
+        Ok(recording) => Some(recording),
+        Err(e) => {
+            warn!("Failed to start recording: {}", e);
+            None
+        }
+    }
+}
+
+/// A proxy that records what passes through it. It stops when dropped.
+pub struct Recording {
+    /// Where to send requests meant for the instance, e.g. `http://127.0.0.1:43210`.
+    pub base: String,
+    upstream: String,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+struct Recorder {
+    upstream: String,
+    base: String,
+    client: reqwest::Client,
+    path: PathBuf,
+    fixture: Mutex<Fixture>,
+}
+
+impl Recording {
+    /// Pass requests on to `upstream`, writing them to the fixture at `path` as they happen.
+    pub fn start(upstream: &str, path: PathBuf, software: String) -> Result<Recording, String> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        let base = format!("http://{}", addr);
+        let upstream = upstream.trim_end_matches('/').to_string();
+        debug!("recording {} to {}", upstream, path.display());
+
+        let recorder = Arc::new(Recorder {
+            upstream: upstream.clone(),
+            base: base.clone(),
+            client: build_http_client().map_err(|e| e.to_string())?,
+            path,
+            fixture: Mutex::new(Fixture {
+                software,
+                exchanges: vec![],
+            }),
+        });
+
+        // A thread of its own, so it works the same under the app's runtime and in tests.
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        thread::spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(rt) => rt,
+                Err(e) => {
+                    warn!("Failed to start recording: {}", e);
+                    return;
+                }
+            };
+            rt.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let recorder = recorder.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            forward(recorder.clone(), request)
+                        }))
+                    }
+                });
+                let server = match Server::from_tcp(listener) {
+                    Ok(server) => server.serve(make_service),
+                    Err(e) => {
+                        warn!("Failed to start recording: {}", e);
+                        return;
+                    }
+                };
+                let stopping = async {
+                    let _ = stopped.await;
+                };
+                if let Err(e) = server.with_graceful_shutdown(stopping).await {
+                    warn!("Recording stopped: {}", e);
+                }
+            });
+        });
+
+        Ok(Recording {
+            base,
+            upstream,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// `url` as the instance knows it, for the user's browser, which doesn't go through the
+    /// proxy.
+    pub fn upstream_url(&self, url: &str) -> String {
+        url.replace(&self.base, &self.upstream)
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+fn plain(status: StatusCode, text: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(text));
+    *response.status_mut() = status;
+    response
+}
+
+async fn forward(
+    recorder: Arc<Recorder>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/".to_string(), |p| p.to_string());
+    // Events never stop coming, so there's no whole response to write down.
+    if path.starts_with("/api/v1/streaming") {
+        return Ok(plain(
+            StatusCode::NOT_IMPLEMENTED,
+            "The streaming api isn't recorded".to_string(),
+        ));
+    }
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let request_body = (!body.is_empty()).then(|| String::from_utf8_lossy(&body).into_owned());
+
+    let mut forwarded = recorder
+        .client
+        .request(method.clone(), format!("{}{}", recorder.upstream, path));
+    for (name, value) in parts.headers.iter() {
+        // The body is recorded as text, so it mustn't come back compressed.
+        if name != header::HOST && name != header::ACCEPT_ENCODING {
+            forwarded = forwarded.header(name.clone(), value.clone());
+        }
+    }
+    let response = match forwarded.body(body.to_vec()).send().await {
+        Ok(response) => response,
+        Err(e) => return Ok(plain(StatusCode::BAD_GATEWAY, e.to_string())),
+    };
+    let status = response.status();
+    let headers = response.headers().clone();
+    let text = response.text().await.unwrap_or_default();
+
+    let exchange = Exchange {
+        method: method.to_string(),
+        path: redact::query(&path),
+        request_body: request_body.map(|b| redact::body(&b)),
+        status: status.as_u16(),
+        headers: headers
+            .iter()
+            .filter(|(name, _)| KEPT_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| {
+                let value = value.to_str().unwrap_or_default();
+                let value = value.replace(&recorder.upstream, BASE_PLACEHOLDER);
+                (name.to_string(), value)
+            })
+            .collect(),
+        body: redact::body(&text.replace(&recorder.upstream, BASE_PLACEHOLDER)),
+    };
+    {
+        let mut fixture = recorder.fixture.lock().unwrap();
+        fixture.exchanges.push(exchange);
+        // Written as it goes, as there's no telling when the app will be closed.
+        if let Err(e) = fixture.save(&recorder.path) {
+            warn!("Failed to save recording: {}", e);
+        }
+    }
+
+    // Links, like the next page of a timeline, have to come back through the proxy too.
+    let mut reply = plain(status, text.replace(&recorder.upstream, &recorder.base));
+    for (name, value) in headers.iter() {
+        if name == header::CONTENT_LENGTH
+            || name == header::TRANSFER_ENCODING
+            || name == header::CONNECTION
+        {
+            continue;
+        }
+        let value = match value.to_str() {
+            Ok(text) => HeaderValue::from_str(&text.replace(&recorder.upstream, &recorder.base))
+                .unwrap_or_else(|_| value.clone()),
+            Err(_) => value.clone(),
+        };
+        reply.headers_mut().append(name.clone(), value);
+    }
+    Ok(reply)
+}
@@ -0,0 +1,53 @@
+//! Blanking out tokens and other secrets, before requests are written down anywhere.
+
+/// Stands in for a secret's value.
+pub const REDACTED: &str = "REDACTED";
+/// Fields whose values are secret, wherever they turn up in a body or query string.
+pub const SECRETS: &[&str] = &["access_token", "client_secret", "code", "password", "token"];
+
+/// A json or form body, with its secrets blanked out. Anything else is left as it is.
+pub fn body(body: &str) -> String {
+    if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) {
+        json(&mut value);
+        return value.to_string();
+    }
+    if body.contains('=') && !body.contains(char::is_whitespace) {
+        return form(body);
+    }
+    body.to_string()
+}
+
+pub fn json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRETS.contains(&key.as_str()) && value.is_string() {
+                    *value = REDACTED.into();
+                } else {
+                    json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(json),
+        _ => (),
+    }
+}
+
+/// A form, or a query string, such as `code=1234&scope=read`.
+pub fn form(form: &str) -> String {
+    form.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if SECRETS.contains(&key) => format!("{}={}", key, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// A url or path, with secrets in its query string blanked out.
+pub fn query(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, query)) => format!("{}?{}", path, form(query)),
+        None => url.to_string(),
+    }
+}
@@ -0,0 +1,105 @@
+//! Services the ui talks to, found by their type.
+//!
+//! A service is a task reading requests off a channel. Implement `Service` for it, start it with
+//! `Registry::start`, and anything holding the registry can get a bridge to it with
+//! `Registry::bridge`. Adding a service doesn't need anything here changed.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use instant::Duration;
+use log::debug;
+use tokio::sync::mpsc;
+
+use crate::channels::{new_channel_pair, AsyncRequestBridge, Message, Spawner};
+
+/// What a service runs as. Natively it may move between the runtime's threads, in the browser
+/// there's only the one.
+#[cfg(not(target_arch = "wasm32"))]
+pub type ServiceFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+#[cfg(target_arch = "wasm32")]
+pub type ServiceFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+pub trait Service: 'static {
+    /// The requests the service handles, and its replies to them.
+    type Message: Send + 'static;
+
+    /// Handle requests from `rx` until every sender is dropped. `spawner` is for starting any
+    /// services of its own.
+    fn run(self, rx: mpsc::Receiver<Message<Self::Message>>, spawner: Spawner) -> ServiceFuture;
+}
+
+impl Spawner {
+    /// Start `service`, returning the channel to talk to it with.
+    pub fn spawn_service<S: Service>(&self, service: S) -> mpsc::Sender<Message<S::Message>> {
+        let (tx, rx) = new_channel_pair();
+        self.spawn_async(service.run(rx, self.clone()));
+        tx
+    }
+}
+
+/// The running services, by type. Clones share them.
+#[derive(Clone)]
+pub struct Registry {
+    spawner: Spawner,
+    /// Each is the `mpsc::Sender` for its service's messages.
+    services: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>>,
+}
+
+impl Registry {
+    pub fn new(spawner: Spawner) -> Self {
+        Registry {
+            spawner,
+            services: Default::default(),
+        }
+    }
+
+    pub fn spawner(&self) -> &Spawner {
+        &self.spawner
+    }
+
+    /// Start `service`, taking over from any service of its type that was started before.
+    pub fn start<S: Service>(&self, service: S) -> mpsc::Sender<Message<S::Message>> {
+        debug!("starting {}", std::any::type_name::<S>());
+        let tx = self.spawner.spawn_service(service);
+        self.insert::<S>(tx.clone());
+        tx
+    }
+
+    /// Reach services of type `S` through `tx`, e.g. a stand-in for one in tests.
+    pub fn insert<S: Service>(&self, tx: mpsc::Sender<Message<S::Message>>) {
+        self.services
+            .lock()
+            .unwrap()
+            .insert(TypeId::of::<S>(), Box::new(tx));
+    }
+
+    pub fn sender<S: Service>(&self) -> Option<mpsc::Sender<Message<S::Message>>> {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&TypeId::of::<S>())
+            .and_then(|tx| tx.downcast_ref::<mpsc::Sender<Message<S::Message>>>())
+            .cloned()
+    }
+
+    /// A bridge for the ui to send the service requests with, if it's been started.
+    pub fn bridge<S: Service, TState>(&self) -> Option<AsyncRequestBridge<S::Message, TState>> {
+        self.sender::<S>().map(AsyncRequestBridge::new)
+    }
+
+    /// Stop every service, giving them up to `deadline` to finish what they're doing.
+    ///
+    /// Services stop when their channel closes, so anything else holding a sender or bridge to
+    /// one, like the ui, has to drop it first.
+    pub fn shutdown(&self, deadline: Duration) {
+        debug!("shutting down services");
+        self.services.lock().unwrap().clear();
+        self.spawner.shutdown(deadline);
+    }
+}
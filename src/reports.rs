@@ -0,0 +1,169 @@
+use std::fmt;
+
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use serde::de::IgnoredAny;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_json, RawApi},
+    channels::{cancellable, send_reply, Message, Spawner},
+    registry::{Service, ServiceFuture},
+};
+
+/// Reports to the instance's moderators.
+pub struct ReportsService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for ReportsService {
+    type Message = ReportsMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<ReportsMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_reports_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_reports_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<ReportsMessage>>,
+) {
+    let api = RawApi::new(&mastodon);
+
+    debug!("entered reports service");
+
+    loop {
+        // wait for messages
+        match rx.recv().await {
+            Some(rx) => match rx {
+                Message::Request { msg, mut reply } => {
+                    let Some(result) = cancellable(&mut reply, handle(&api, msg)).await else {
+                        debug!("Reports request was cancelled");
+                        continue;
+                    };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Reports request failed: {}", e);
+                            ReportsMessage::Error(e)
+                        }
+                    };
+                    if send_reply(reply, response).is_err() {
+                        warn!("Failed to send reports reply");
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Reports service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportCategory {
+    Spam,
+    Legal,
+    Violation,
+    Other,
+}
+
+impl ReportCategory {
+    pub const ALL: [ReportCategory; 4] = [
+        ReportCategory::Spam,
+        ReportCategory::Legal,
+        ReportCategory::Violation,
+        ReportCategory::Other,
+    ];
+
+    /// The value the reports endpoint expects for this category.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportCategory::Spam => "spam",
+            ReportCategory::Legal => "legal",
+            ReportCategory::Violation => "violation",
+            ReportCategory::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ReportCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportCategory::Spam => write!(f, "It's spam"),
+            ReportCategory::Legal => write!(f, "It's illegal"),
+            ReportCategory::Violation => write!(f, "It breaks server rules"),
+            ReportCategory::Other => write!(f, "It's something else"),
+        }
+    }
+}
+
+/// One of the instance's rules, which violation reports refer to.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Rule {
+    pub id: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReportDraft {
+    pub account: AccountId,
+    pub statuses: Vec<StatusId>,
+    pub category: ReportCategory,
+    pub rules: Vec<String>,
+    pub comment: String,
+    /// Also send the report to the moderators of the account's own instance.
+    pub forward: bool,
+}
+
+#[derive(Debug)]
+pub enum ReportsMessage {
+    LoadRules,
+    Rules(Vec<Rule>),
+    /// Fetch recent statuses by an account, so more of them can be attached to a report.
+    LoadAccountStatuses(AccountId),
+    AccountStatuses(Vec<Status>),
+    Submit(ReportDraft),
+    Submitted,
+    Error(String),
+}
+
+async fn handle(api: &RawApi, msg: ReportsMessage) -> Result<ReportsMessage, String> {
+    match msg {
+        ReportsMessage::LoadRules => send_json(api.get("/api/v1/instance/rules"))
+            .await
+            .map(ReportsMessage::Rules)
+            .map_err(|e| format!("Failed to load server rules: {}", e)),
+        ReportsMessage::LoadAccountStatuses(account) => send_json(
+            api.get(&format!("/api/v1/accounts/{}/statuses", account))
+                .query(&[("limit", "20"), ("exclude_reblogs", "true")]),
+        )
+        .await
+        .map(ReportsMessage::AccountStatuses)
+        .map_err(|e| format!("Failed to load statuses: {}", e)),
+        ReportsMessage::Submit(draft) => {
+            let mut form = vec![
+                ("account_id", draft.account.to_string()),
+                ("comment", draft.comment),
+                ("forward", draft.forward.to_string()),
+                ("category", draft.category.as_str().to_string()),
+            ];
+            form.extend(
+                draft
+                    .statuses
+                    .iter()
+                    .map(|id| ("status_ids[]", id.to_string())),
+            );
+            if draft.category == ReportCategory::Violation {
+                form.extend(draft.rules.into_iter().map(|id| ("rule_ids[]", id)));
+            }
+            let _: IgnoredAny = send_json(api.post("/api/v1/reports").form(&form))
+                .await
+                .map_err(|e| format!("Failed to send report: {}", e))?;
+            Ok(ReportsMessage::Submitted)
+        }
+        other => Err(format!("Unexpected reports request: {:?}", other)),
+    }
+}
@@ -0,0 +1,139 @@
+//! Places in the app that can be linked to, written as a url fragment like `#/@user@host`.
+//!
+//! In the browser the fragment follows the user around, so the back button works and the address
+//! can be bookmarked. Natively, the same places can be given on the command line.
+
+/// A page of the signed in session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Route {
+    Home,
+    Notifications,
+    Compose,
+    Scheduled,
+    Search,
+    /// Searching for posts with a hashtag, without the `#`.
+    Hashtag(String),
+    /// A profile by its `user@host`, or just `user` on the signed in instance.
+    Profile(String),
+    /// A post by its url, on any server.
+    Status(String),
+    FollowRequests,
+    Settings,
+}
+
+impl Route {
+    /// Read a url fragment, with or without the leading `#`. Unknown fragments give `None`.
+    pub fn from_fragment(fragment: &str) -> Option<Route> {
+        let path = fragment.trim_start_matches('#').strip_prefix('/')?;
+        let route = match path {
+            "" => Route::Home,
+            "notifications" => Route::Notifications,
+            "compose" => Route::Compose,
+            "scheduled" => Route::Scheduled,
+            "search" => Route::Search,
+            "follow-requests" => Route::FollowRequests,
+            "settings" => Route::Settings,
+            _ => {
+                if let Some(acct) = path.strip_prefix('@') {
+                    Route::Profile(acct.to_string())
+                } else if let Some(tag) = path.strip_prefix("tags/") {
+                    Route::Hashtag(tag.to_string())
+                } else if let Some(url) = path.strip_prefix("status/") {
+                    Route::Status(url.to_string())
+                } else {
+                    return None;
+                }
+            }
+        };
+        match &route {
+            Route::Profile(s) | Route::Hashtag(s) | Route::Status(s) if s.is_empty() => None,
+            _ => Some(route),
+        }
+    }
+
+    pub fn to_fragment(&self) -> String {
+        match self {
+            Route::Home => "#/".to_string(),
+            Route::Notifications => "#/notifications".to_string(),
+            Route::Compose => "#/compose".to_string(),
+            Route::Scheduled => "#/scheduled".to_string(),
+            Route::Search => "#/search".to_string(),
+            Route::Hashtag(tag) => format!("#/tags/{}", tag),
+            Route::Profile(acct) => format!("#/@{}", acct),
+            Route::Status(url) => format!("#/status/{}", url),
+            Route::FollowRequests => "#/follow-requests".to_string(),
+            Route::Settings => "#/settings".to_string(),
+        }
+    }
+
+    /// Read a command line argument: a fragment, `#tag`, `@user@host`, or the name of a page.
+    pub fn from_arg(arg: &str) -> Option<Route> {
+        if arg.starts_with("#/") {
+            return Route::from_fragment(arg);
+        }
+        if let Some(tag) = arg.strip_prefix('#') {
+            return (!tag.is_empty()).then(|| Route::Hashtag(tag.to_string()));
+        }
+        if let Some(acct) = arg.strip_prefix('@') {
+            return (!acct.is_empty()).then(|| Route::Profile(acct.to_string()));
+        }
+        Route::from_fragment(&format!("#/{}", arg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_read_back_as_the_same_route() {
+        let routes = [
+            Route::Home,
+            Route::Notifications,
+            Route::Compose,
+            Route::Scheduled,
+            Route::Search,
+            Route::Hashtag("rust".to_string()),
+            Route::Profile("a@b.social".to_string()),
+            Route::Status("https://b.social/@a/123".to_string()),
+            Route::FollowRequests,
+            Route::Settings,
+        ];
+        for route in routes {
+            assert_eq!(Route::from_fragment(&route.to_fragment()), Some(route));
+        }
+    }
+
+    #[test]
+    fn the_hash_is_optional() {
+        assert_eq!(Route::from_fragment("/settings"), Some(Route::Settings));
+        assert_eq!(Route::from_fragment("#/"), Some(Route::Home));
+    }
+
+    #[test]
+    fn unknown_or_empty_fragments_are_ignored() {
+        assert_eq!(Route::from_fragment(""), None);
+        assert_eq!(Route::from_fragment("#"), None);
+        assert_eq!(Route::from_fragment("#/nowhere"), None);
+        assert_eq!(Route::from_fragment("#/@"), None);
+        assert_eq!(Route::from_fragment("#/tags/"), None);
+        assert_eq!(Route::from_fragment("#/status/"), None);
+    }
+
+    #[test]
+    fn reads_command_line_arguments() {
+        assert_eq!(
+            Route::from_arg("@a@b.social"),
+            Some(Route::Profile("a@b.social".to_string()))
+        );
+        assert_eq!(
+            Route::from_arg("#rust"),
+            Some(Route::Hashtag("rust".to_string()))
+        );
+        assert_eq!(Route::from_arg("#/scheduled"), Some(Route::Scheduled));
+        assert_eq!(Route::from_arg("notifications"), Some(Route::Notifications));
+        assert_eq!(Route::from_arg("#"), None);
+        assert_eq!(Route::from_arg("@"), None);
+        assert_eq!(Route::from_arg("nowhere"), None);
+    }
+}
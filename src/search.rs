@@ -0,0 +1,186 @@
+use futures::future::{select, Either};
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_json, RawApi},
+    channels::{cancellable, send_reply, Message, Spawner},
+    registry::{Service, ServiceFuture},
+};
+
+/// Searches as the user types.
+pub struct SearchService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for SearchService {
+    type Message = SearchMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<SearchMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_search_service(self.mastodon, rx))
+    }
+}
+
+/// A second search service for resolving links, so they don't cancel a search being typed.
+pub struct LinksService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for LinksService {
+    type Message = SearchMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<SearchMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_search_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_search_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<SearchMessage>>,
+) {
+    let api = RawApi::new(&mastodon);
+    // A request which arrived while an older search was still running.
+    let mut next: Option<Message<SearchMessage>> = None;
+
+    debug!("entered search service");
+
+    loop {
+        // wait for messages
+        let incoming = match next.take() {
+            Some(msg) => Some(msg),
+            None => rx.recv().await,
+        };
+        match incoming {
+            Some(rx_msg) => match rx_msg {
+                Message::Request { msg, mut reply } => {
+                    // The browser can still open a link the instance can't look up.
+                    let fallback = match &msg {
+                        SearchMessage::Resolve(url) => Some(url.clone()),
+                        _ => None,
+                    };
+                    // Race the search against the next request, so a newer query drops the
+                    // in-flight http request instead of waiting for it.
+                    let searching = Box::pin(cancellable(&mut reply, handle(&api, msg)));
+                    let next_request = Box::pin(rx.recv());
+                    match select(searching, next_request).await {
+                        Either::Left((None, _)) => debug!("Search was cancelled"),
+                        Either::Left((Some(result), _)) => {
+                            let response = match (result, fallback) {
+                                (Ok(response), _) => response,
+                                (Err(e), Some(url)) => {
+                                    warn!("Couldn't resolve {}: {}", url, e);
+                                    SearchMessage::Resolved { url, link: None }
+                                }
+                                (Err(e), None) => {
+                                    warn!("Search failed: {}", e);
+                                    SearchMessage::Error(e)
+                                }
+                            };
+                            if send_reply(reply, response).is_err() {
+                                debug!("Search result was no longer wanted");
+                            }
+                        }
+                        Either::Right((Some(newer), _)) => {
+                            debug!("Search superseded by a newer request");
+                            next = Some(newer);
+                        }
+                        Either::Right((None, _)) => {
+                            debug!("Search service out of messages");
+                            break;
+                        }
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Search service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Hashtag {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct SearchResults {
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub hashtags: Vec<Hashtag>,
+    #[serde(default)]
+    pub statuses: Vec<Status>,
+}
+
+/// What a link to a remote post or profile turned out to be on the user's instance.
+#[derive(Clone, Debug)]
+pub enum ResolvedLink {
+    Status(Status),
+    Account(Account),
+}
+
+#[derive(Debug)]
+pub enum SearchMessage {
+    /// Search everything. Urls of remote posts and profiles are fetched into the instance.
+    Search(String),
+    Results {
+        query: String,
+        results: SearchResults,
+    },
+    /// Look up a single url, preferring a post over a profile.
+    Resolve(String),
+    /// Look up a profile by its `user@host`.
+    ResolveAccount(String),
+    Resolved {
+        url: String,
+        link: Option<ResolvedLink>,
+    },
+    Error(String),
+}
+
+async fn search(api: &RawApi, query: &str) -> Result<SearchResults, String> {
+    send_json(
+        api.get("/api/v2/search")
+            .query(&[("q", query), ("resolve", "true")]),
+    )
+    .await
+    .map_err(|e| format!("Search failed: {}", e))
+}
+
+async fn handle(api: &RawApi, msg: SearchMessage) -> Result<SearchMessage, String> {
+    match msg {
+        SearchMessage::Search(query) => {
+            let results = search(api, &query).await?;
+            Ok(SearchMessage::Results { query, results })
+        }
+        SearchMessage::Resolve(url) => {
+            let mut results = search(api, &url).await?;
+            let link = if !results.statuses.is_empty() {
+                Some(ResolvedLink::Status(results.statuses.swap_remove(0)))
+            } else if !results.accounts.is_empty() {
+                Some(ResolvedLink::Account(results.accounts.swap_remove(0)))
+            } else {
+                None
+            };
+            Ok(SearchMessage::Resolved { url, link })
+        }
+        SearchMessage::ResolveAccount(acct) => {
+            let mut results: SearchResults = send_json(api.get("/api/v2/search").query(&[
+                ("q", acct.as_str()),
+                ("type", "accounts"),
+                ("resolve", "true"),
+                ("limit", "1"),
+            ]))
+            .await
+            .map_err(|e| format!("Couldn't find @{}: {}", acct, e))?;
+            let link = results.accounts.pop().map(ResolvedLink::Account);
+            Ok(SearchMessage::Resolved { url: acct, link })
+        }
+        other => Err(format!("Unexpected search request: {:?}", other)),
+    }
+}
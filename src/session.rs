@@ -0,0 +1,109 @@
+use std::fmt;
+
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    accounts::AccountsService,
+    channels::{AsyncRequestBridge, Message, Spawner},
+    images::ImagesService,
+    moderation::ModerationService,
+    outbox::OutboxService,
+    registry::{Registry, Service},
+    reports::ReportsService,
+    search::{LinksService, SearchService},
+    statuses::StatusesService,
+    timeline::TimelineService,
+};
+
+/// The services which act on behalf of a signed in account.
+///
+/// They have a registry of their own, so they stop when the session is dropped.
+pub struct SessionChannels {
+    pub mastodon: Mastodon,
+    pub account: Account,
+    pub services: Registry,
+}
+
+impl SessionChannels {
+    /// Identifies the account across instances, such as `alice@example.social`.
+    pub fn account_key(&self) -> String {
+        account_key(&self.mastodon, &self.account)
+    }
+
+    /// The channel to one of the session's services, which are all started with it.
+    pub fn sender<S: Service>(&self) -> mpsc::Sender<Message<S::Message>> {
+        self.services.sender::<S>().unwrap_or_else(|| {
+            panic!(
+                "{} isn't one of the session's services",
+                std::any::type_name::<S>()
+            )
+        })
+    }
+
+    pub fn bridge<S: Service, TState>(&self) -> AsyncRequestBridge<S::Message, TState> {
+        AsyncRequestBridge::new(self.sender::<S>())
+    }
+}
+
+fn account_key(mastodon: &Mastodon, account: &Account) -> String {
+    let host = mastodon
+        .data
+        .base
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    format!("{}@{}", account.username, host)
+}
+
+impl fmt::Debug for SessionChannels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionChannels")
+            .field("instance", &self.mastodon.data.base)
+            .field("account", &self.account.acct)
+            .finish()
+    }
+}
+
+pub fn start_session_services(
+    mastodon: Mastodon,
+    account: Account,
+    spawner: &Spawner,
+) -> SessionChannels {
+    let services = Registry::new(spawner.clone());
+    services.start(AccountsService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(ImagesService {
+        mastodon: mastodon.clone(),
+        account_key: account_key(&mastodon, &account),
+    });
+    services.start(ModerationService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(OutboxService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(ReportsService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(SearchService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(LinksService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(StatusesService {
+        mastodon: mastodon.clone(),
+    });
+    services.start(TimelineService {
+        mastodon: mastodon.clone(),
+        account_key: account_key(&mastodon, &account),
+    });
+
+    SessionChannels {
+        mastodon,
+        account,
+        services,
+    }
+}
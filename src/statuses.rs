@@ -0,0 +1,599 @@
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use reqwest::Method;
+use serde::de::IgnoredAny;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{send_json, send_page, ApiPage, RawApi},
+    channels::{cancellable, send_reply, Message, Spawner},
+    datetime::remaining_until,
+    ratelimit::Priority,
+    registry::{Service, ServiceFuture},
+};
+
+/// Posting, editing and deleting statuses, and voting in polls.
+pub struct StatusesService {
+    pub mastodon: Mastodon,
+}
+
+impl Service for StatusesService {
+    type Message = StatusesMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<StatusesMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_statuses_service(self.mastodon, rx))
+    }
+}
+
+pub async fn start_statuses_service(
+    mastodon: Mastodon,
+    mut rx: mpsc::Receiver<Message<StatusesMessage>>,
+) {
+    let api = RawApi::new(&mastodon);
+
+    debug!("entered statuses service");
+
+    loop {
+        // wait for messages
+        match rx.recv().await {
+            Some(rx) => match rx {
+                Message::Request { msg, mut reply } => {
+                    let Some(result) = cancellable(&mut reply, handle(&api, msg)).await else {
+                        debug!("Statuses request was cancelled");
+                        continue;
+                    };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Statuses request failed: {}", e);
+                            StatusesMessage::Error(e)
+                        }
+                    };
+                    if send_reply(reply, response).is_err() {
+                        warn!("Failed to send statuses reply");
+                    }
+                }
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Statuses service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+/// How far ahead a post has to be scheduled; servers reject anything sooner.
+pub const MIN_SCHEDULE_LEAD: Duration = Duration::minutes(5);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostVisibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+impl From<&Visibility> for PostVisibility {
+    fn from(visibility: &Visibility) -> Self {
+        match visibility {
+            Visibility::Public => PostVisibility::Public,
+            Visibility::Unlisted => PostVisibility::Unlisted,
+            Visibility::Private => PostVisibility::Private,
+            Visibility::Direct => PostVisibility::Direct,
+        }
+    }
+}
+
+impl PostVisibility {
+    pub const ALL: [PostVisibility; 4] = [
+        PostVisibility::Public,
+        PostVisibility::Unlisted,
+        PostVisibility::Private,
+        PostVisibility::Direct,
+    ];
+
+    /// The value the statuses endpoint expects for this visibility.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostVisibility::Public => "public",
+            PostVisibility::Unlisted => "unlisted",
+            PostVisibility::Private => "private",
+            PostVisibility::Direct => "direct",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostVisibility::Public => "Public",
+            PostVisibility::Unlisted => "Unlisted",
+            PostVisibility::Private => "Followers only",
+            PostVisibility::Direct => "Mentioned people only",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct PollDraft {
+    pub options: Vec<String>,
+    /// Seconds the poll stays open for.
+    pub expires_in: u64,
+    pub multiple: bool,
+    /// Hide vote counts until the poll ends.
+    pub hide_totals: bool,
+}
+
+impl PollDraft {
+    /// A draft that recreates an existing poll, for editing or redrafting the status it's on.
+    pub fn from_poll(poll: &Poll) -> Self {
+        // A poll that has already closed gets a fresh default duration.
+        let expires_in = poll
+            .expires_at
+            .and_then(remaining_until)
+            .map_or(PollDraft::default().expires_in, |d| {
+                d.whole_seconds() as u64
+            });
+        PollDraft {
+            options: poll.options.iter().map(|o| o.title.clone()).collect(),
+            expires_in,
+            multiple: poll.multiple,
+            hide_totals: poll.options.iter().all(|o| o.votes_count.is_none()),
+        }
+    }
+}
+
+impl Default for PollDraft {
+    fn default() -> Self {
+        PollDraft {
+            options: vec![String::new(), String::new()],
+            expires_in: 24 * 60 * 60,
+            multiple: false,
+            hide_totals: false,
+        }
+    }
+}
+
+/// Everything needed to publish a status.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ComposeDraft {
+    pub text: String,
+    /// A content warning, shown in place of the text until expanded.
+    pub spoiler_text: String,
+    pub visibility: PostVisibility,
+    pub poll: Option<PollDraft>,
+    /// Attachments that were already uploaded, such as those kept when editing.
+    pub media_ids: Vec<String>,
+    /// The status this draft replies to.
+    pub in_reply_to_id: Option<String>,
+    /// The id of the status this draft replaces, when editing rather than posting.
+    pub editing: Option<String>,
+    /// When to publish the post, if not straight away.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub scheduled_at: Option<OffsetDateTime>,
+    /// The scheduled post this draft replaces, which is cancelled once the draft is scheduled.
+    pub scheduled_id: Option<String>,
+}
+
+impl ComposeDraft {
+    /// A reply to a status, mentioning its author and keeping its visibility.
+    pub fn reply_to(status: &Status) -> Self {
+        ComposeDraft {
+            text: format!("@{} ", status.account.acct),
+            spoiler_text: status.spoiler_text.clone(),
+            visibility: PostVisibility::from(&status.visibility),
+            in_reply_to_id: Some(status.id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Whether there's anything in the draft worth keeping.
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+            && self.spoiler_text.is_empty()
+            && self.poll.is_none()
+            && self.media_ids.is_empty()
+    }
+
+    /// A draft with everything but the text of an existing status, which has to be fetched from
+    /// its source since statuses only come with rendered html.
+    pub fn from_status(status: &Status) -> Self {
+        ComposeDraft {
+            text: String::new(),
+            spoiler_text: status.spoiler_text.clone(),
+            visibility: PostVisibility::from(&status.visibility),
+            poll: status.poll.as_ref().map(PollDraft::from_poll),
+            media_ids: status
+                .media_attachments
+                .iter()
+                .map(|a| a.id.to_string())
+                .collect(),
+            in_reply_to_id: status.in_reply_to_id.as_ref().map(|id| id.to_string()),
+            editing: Some(status.id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn form(&self) -> Vec<(&'static str, String)> {
+        let mut form = vec![
+            ("status", self.text.clone()),
+            ("visibility", self.visibility.as_str().to_string()),
+        ];
+        if !self.spoiler_text.is_empty() {
+            form.push(("spoiler_text", self.spoiler_text.clone()));
+        }
+        form.extend(self.media_ids.iter().map(|id| ("media_ids[]", id.clone())));
+        if let Some(id) = &self.in_reply_to_id {
+            form.push(("in_reply_to_id", id.clone()));
+        }
+        if let Some(at) = self.scheduled_at.and_then(|at| at.format(&Rfc3339).ok()) {
+            form.push(("scheduled_at", at));
+        }
+        if let Some(poll) = &self.poll {
+            form.extend(
+                poll.options
+                    .iter()
+                    .map(|option| ("poll[options][]", option.clone())),
+            );
+            form.push(("poll[expires_in]", poll.expires_in.to_string()));
+            form.push(("poll[multiple]", poll.multiple.to_string()));
+            form.push(("poll[hide_totals]", poll.hide_totals.to_string()));
+        }
+        form
+    }
+}
+
+/// Poll limits from the instance's configuration.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct PollLimits {
+    pub max_options: usize,
+    pub max_characters_per_option: usize,
+    /// Shortest allowed poll, in seconds.
+    pub min_expiration: u64,
+    /// Longest allowed poll, in seconds.
+    pub max_expiration: u64,
+}
+
+impl Default for PollLimits {
+    // Mastodon's defaults, for servers which don't say.
+    fn default() -> Self {
+        PollLimits {
+            max_options: 4,
+            max_characters_per_option: 50,
+            min_expiration: 300,
+            max_expiration: 2_629_746,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct StatusLimits {
+    pub max_characters: usize,
+}
+
+impl Default for StatusLimits {
+    fn default() -> Self {
+        StatusLimits {
+            max_characters: 500,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct InstanceLimits {
+    pub statuses: StatusLimits,
+    pub polls: PollLimits,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(default)]
+struct InstanceV2 {
+    configuration: InstanceLimits,
+}
+
+/// A post waiting to be published by the server.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ScheduledStatus {
+    pub id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub scheduled_at: OffsetDateTime,
+    pub params: ScheduledParams,
+}
+
+/// What a scheduled post will be published with. Servers leave out or null anything unset.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ScheduledParams {
+    pub text: Option<String>,
+    pub spoiler_text: Option<String>,
+    pub visibility: Option<PostVisibility>,
+    pub media_ids: Option<Vec<String>>,
+    pub poll: Option<ScheduledPoll>,
+    pub in_reply_to_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ScheduledPoll {
+    pub options: Vec<String>,
+    /// Mastodon sends this back as a string, so accept either.
+    #[serde(deserialize_with = "seconds")]
+    pub expires_in: u64,
+    pub multiple: Option<bool>,
+    pub hide_totals: Option<bool>,
+}
+
+fn seconds<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(u64),
+        Text(String),
+    }
+    match serde::Deserialize::deserialize(deserializer)? {
+        Seconds::Number(n) => Ok(n),
+        Seconds::Text(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+impl ScheduledStatus {
+    /// A draft that schedules this post again, replacing it.
+    pub fn draft(&self) -> ComposeDraft {
+        let params = &self.params;
+        ComposeDraft {
+            text: params.text.clone().unwrap_or_default(),
+            spoiler_text: params.spoiler_text.clone().unwrap_or_default(),
+            visibility: params.visibility.unwrap_or_default(),
+            poll: params.poll.as_ref().map(|poll| PollDraft {
+                options: poll.options.clone(),
+                expires_in: poll.expires_in,
+                multiple: poll.multiple.unwrap_or(false),
+                hide_totals: poll.hide_totals.unwrap_or(false),
+            }),
+            media_ids: params.media_ids.clone().unwrap_or_default(),
+            in_reply_to_id: params.in_reply_to_id.clone(),
+            editing: None,
+            scheduled_at: Some(self.scheduled_at),
+            scheduled_id: Some(self.id.clone()),
+        }
+    }
+}
+
+/// The plain text a status was written as.
+#[derive(Clone, Debug, serde::Deserialize)]
+struct StatusSource {
+    text: String,
+    #[serde(default)]
+    spoiler_text: String,
+}
+
+/// One revision of an edited status.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct StatusEdit {
+    pub content: String,
+    #[serde(default)]
+    pub spoiler_text: String,
+    pub created_at: String,
+}
+
+#[derive(Debug)]
+pub enum StatusesMessage {
+    /// Publish a draft, or save it over the status it's editing.
+    Post(ComposeDraft),
+    /// `warning` is set if the post went out but something after it didn't, such as cancelling
+    /// the scheduled copy it replaced.
+    Posted {
+        status: Status,
+        warning: Option<String>,
+    },
+    /// The reply to posting a draft with `scheduled_at` set, or to rescheduling.
+    Scheduled {
+        scheduled: ScheduledStatus,
+        warning: Option<String>,
+    },
+    LoadScheduled,
+    ScheduledList(Vec<ScheduledStatus>),
+    Reschedule {
+        id: String,
+        at: OffsetDateTime,
+    },
+    CancelScheduled(String),
+    ScheduledCancelled(String),
+    /// Fill in the text of a draft made with `ComposeDraft::from_status`.
+    LoadForEdit(ComposeDraft),
+    /// Delete the status a draft was made from, and hand the draft back to be posted anew.
+    DeleteAndRedraft(ComposeDraft),
+    EditReady(ComposeDraft),
+    Redrafted {
+        deleted: StatusId,
+        draft: ComposeDraft,
+    },
+    Delete(StatusId),
+    Deleted(StatusId),
+    LoadHistory(StatusId),
+    History(Vec<StatusEdit>),
+    /// Fetch limits that posts have to be validated against.
+    LoadLimits,
+    Limits(InstanceLimits),
+    Vote {
+        poll: PollId,
+        choices: Vec<usize>,
+    },
+    RefreshPoll(PollId),
+    PollUpdated(Poll),
+    Error(String),
+}
+
+async fn handle(api: &RawApi, msg: StatusesMessage) -> Result<StatusesMessage, String> {
+    match msg {
+        StatusesMessage::Post(draft) if draft.scheduled_at.is_some() => {
+            let scheduled = send_json(api.post("/api/v1/statuses").form(&draft.form()))
+                .await
+                .map_err(|e| format!("Failed to schedule: {}", e))?;
+            // Only drop the old copy once the new one is safely scheduled. Failing to drop it
+            // mustn't fail the request, or trying again would schedule a third copy.
+            let warning = match &draft.scheduled_id {
+                Some(old) => cancel_scheduled(api, old)
+                    .await
+                    .err()
+                    .map(|e| format!("Scheduled, but the old copy is still scheduled too. {}", e)),
+                None => None,
+            };
+            Ok(StatusesMessage::Scheduled { scheduled, warning })
+        }
+        StatusesMessage::Post(draft) => {
+            let request = match &draft.editing {
+                Some(id) => api.request(Method::PUT, &format!("/api/v1/statuses/{}", id)),
+                None => api.post("/api/v1/statuses"),
+            };
+            let posted = send_json(request.form(&draft.form()))
+                .await
+                .map_err(|e| format!("Failed to post: {}", e))?;
+            // A scheduled post that's been published now mustn't go out again later.
+            let warning = match &draft.scheduled_id {
+                Some(old) => cancel_scheduled(api, old)
+                    .await
+                    .err()
+                    .map(|e| format!("Posted, but the scheduled copy is still pending. {}", e)),
+                None => None,
+            };
+            Ok(StatusesMessage::Posted {
+                status: posted,
+                warning,
+            })
+        }
+        StatusesMessage::LoadScheduled => load_scheduled(api)
+            .await
+            .map(StatusesMessage::ScheduledList)
+            .map_err(|e| format!("Failed to load scheduled posts: {}", e)),
+        StatusesMessage::Reschedule { id, at } => {
+            let at = at.format(&Rfc3339).map_err(|e| e.to_string())?;
+            send_json(
+                api.request(Method::PUT, &format!("/api/v1/scheduled_statuses/{}", id))
+                    .form(&[("scheduled_at", at)]),
+            )
+            .await
+            .map(|scheduled| StatusesMessage::Scheduled {
+                scheduled,
+                warning: None,
+            })
+            .map_err(|e| format!("Failed to reschedule: {}", e))
+        }
+        StatusesMessage::CancelScheduled(id) => {
+            cancel_scheduled(api, &id).await?;
+            Ok(StatusesMessage::ScheduledCancelled(id))
+        }
+        StatusesMessage::LoadForEdit(draft) => {
+            let draft = with_source(api, draft).await?;
+            Ok(StatusesMessage::EditReady(draft))
+        }
+        StatusesMessage::DeleteAndRedraft(draft) => {
+            let mut draft = with_source(api, draft).await?;
+            // with_source has already checked there's an id.
+            let id = draft.editing.take().unwrap_or_default();
+            delete(api, &id).await?;
+            Ok(StatusesMessage::Redrafted {
+                deleted: StatusId::new(id),
+                draft,
+            })
+        }
+        StatusesMessage::Delete(id) => {
+            delete(api, &id.to_string()).await?;
+            Ok(StatusesMessage::Deleted(id))
+        }
+        StatusesMessage::LoadHistory(id) => {
+            send_json(api.get(&format!("/api/v1/statuses/{}/history", id)))
+                .await
+                .map(StatusesMessage::History)
+                .map_err(|e| format!("Failed to load edit history: {}", e))
+        }
+        StatusesMessage::LoadLimits => {
+            // Older servers don't have the v2 endpoint, so fall back to the defaults.
+            let limits = match send_json::<InstanceV2>(api.get("/api/v2/instance")).await {
+                Ok(instance) => instance.configuration,
+                Err(e) => {
+                    warn!("Couldn't load instance configuration: {}", e);
+                    InstanceLimits::default()
+                }
+            };
+            Ok(StatusesMessage::Limits(limits))
+        }
+        StatusesMessage::Vote { poll, choices } => {
+            let form: Vec<(&str, String)> = choices
+                .iter()
+                .map(|choice| ("choices[]", choice.to_string()))
+                .collect();
+            send_json(
+                api.post(&format!("/api/v1/polls/{}/votes", poll))
+                    .form(&form),
+            )
+            .await
+            .map(StatusesMessage::PollUpdated)
+            .map_err(|e| format!("Failed to vote: {}", e))
+        }
+        StatusesMessage::RefreshPoll(poll) => {
+            send_json(api.get(&format!("/api/v1/polls/{}", poll)))
+                .await
+                .map(StatusesMessage::PollUpdated)
+                .map_err(|e| format!("Failed to refresh poll: {}", e))
+        }
+        other => Err(format!("Unexpected statuses request: {:?}", other)),
+    }
+}
+
+async fn with_source(api: &RawApi, mut draft: ComposeDraft) -> Result<ComposeDraft, String> {
+    let id = draft
+        .editing
+        .clone()
+        .ok_or_else(|| "There's no status to edit".to_string())?;
+    let source: StatusSource = send_json(api.get(&format!("/api/v1/statuses/{}/source", id)))
+        .await
+        .map_err(|e| format!("Failed to load the post's source: {}", e))?;
+    draft.text = source.text;
+    draft.spoiler_text = source.spoiler_text;
+    Ok(draft)
+}
+
+/// Every scheduled post, following the pages the server splits them into. Servers only let an
+/// account schedule a few hundred, and the list is shown sorted, so it's fetched whole.
+async fn load_scheduled(api: &RawApi) -> Result<Vec<ScheduledStatus>, String> {
+    let mut page: ApiPage<ScheduledStatus> = send_page(
+        api.get("/api/v1/scheduled_statuses")
+            .query(&[("limit", "40")]),
+        Priority::User,
+    )
+    .await?;
+    let mut scheduled = page.items;
+    while let Some(next) = page.next {
+        page = send_page(api.follow(&next), Priority::User).await?;
+        // Some servers link to one more page past the end, which comes back empty.
+        if page.items.is_empty() {
+            break;
+        }
+        scheduled.append(&mut page.items);
+    }
+    Ok(scheduled)
+}
+
+async fn cancel_scheduled(api: &RawApi, id: &str) -> Result<(), String> {
+    let _: IgnoredAny = send_json(api.request(
+        Method::DELETE,
+        &format!("/api/v1/scheduled_statuses/{}", id),
+    ))
+    .await
+    .map_err(|e| format!("Failed to cancel scheduled post: {}", e))?;
+    Ok(())
+}
+
+async fn delete(api: &RawApi, id: &str) -> Result<(), String> {
+    let _: IgnoredAny = send_json(api.request(Method::DELETE, &format!("/api/v1/statuses/{}", id)))
+        .await
+        .map_err(|e| format!("Failed to delete: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+
+use futures::future::{select, Either};
+use log::{debug, warn};
+use mastodon_async::prelude::*;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    api::{send_page, RawApi},
+    cache::Cache,
+    channels::{cancellable, send_reply, Message, Spawner},
+    ratelimit::{self, EndpointClass, Priority, RateLimiter},
+    registry::{Service, ServiceFuture},
+};
+
+/// What the home timeline is called in the cache.
+const HOME: &str = "home";
+
+/// The home timeline and notifications, kept in the account's cache.
+pub struct TimelineService {
+    pub mastodon: Mastodon,
+    /// See `SessionChannels::account_key`.
+    pub account_key: String,
+}
+
+impl Service for TimelineService {
+    type Message = TimelineMessage;
+
+    fn run(self, rx: mpsc::Receiver<Message<TimelineMessage>>, _spawner: Spawner) -> ServiceFuture {
+        Box::pin(start_timeline_service(self.mastodon, self.account_key, rx))
+    }
+}
+
+pub async fn start_timeline_service(
+    mastodon: Mastodon,
+    account_key: String,
+    mut rx: mpsc::Receiver<Message<TimelineMessage>>,
+) {
+    // Everything still works without a cache, it's just slower to start.
+    let cache = match Cache::open(&account_key).await {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    };
+    let mut state = TimelineState {
+        limiter: ratelimit::for_instance(&mastodon.data.base),
+        api: RawApi::new(&mastodon),
+        home: None,
+        notifications: None,
+        cache,
+    };
+
+    // Requests that can wait for the rate limit wait here rather than in `handle`, so the
+    // user's own requests can go ahead of them.
+    let mut held: VecDeque<(TimelineMessage, oneshot::Sender<TimelineMessage>)> = VecDeque::new();
+
+    debug!("entered timeline service");
+
+    loop {
+        // Whoever asked for a held request may have stopped waiting for it.
+        held.retain(|(_, reply)| !reply.is_closed());
+        // wait for messages
+        let incoming = if held.is_empty() {
+            rx.recv().await
+        } else {
+            let ready = Box::pin(state.limiter.wait(EndpointClass::Api, Priority::Background));
+            match select(ready, Box::pin(rx.recv())).await {
+                Either::Left(_) => {
+                    let (msg, reply) = held.pop_front().unwrap();
+                    state.answer(msg, reply).await;
+                    continue;
+                }
+                Either::Right((incoming, _)) => incoming,
+            }
+        };
+        match incoming {
+            Some(rx) => match rx {
+                Message::Request { msg, reply } => match msg.priority() {
+                    Priority::User => state.answer(msg, reply).await,
+                    Priority::Background => held.push_back((msg, reply)),
+                },
+                Message::Notification { msg } => warn!("Unhandled mssage type"),
+            },
+            None => {
+                debug!("Timeline service out of messages");
+                break;
+            }
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum TimelineMessage {
+    /// Whatever was cached of the home timeline last time, to show while it's refreshed.
+    LoadCached,
+    Cached(Vec<Status>),
+    /// Fetch the newest statuses on the home timeline, discarding older pages.
+    LoadHome,
+    /// Fetch statuses older than the last page loaded.
+    LoadOlder,
+    Statuses {
+        statuses: Vec<Status>,
+        has_more: bool,
+        replace: bool,
+    },
+    /// Whatever was cached of the notifications last time, to show while they're refreshed.
+    LoadCachedNotifications,
+    CachedNotifications(Vec<Notification>),
+    /// Fetch the newest notifications, discarding older pages.
+    LoadNotifications,
+    /// Fetch notifications older than the last page loaded.
+    LoadOlderNotifications,
+    Notifications {
+        notifications: Vec<Notification>,
+        has_more: bool,
+        replace: bool,
+    },
+    CacheSize,
+    ClearCache,
+    /// How much space the cache takes up, in bytes.
+    CacheSizeIs(u64),
+    Error(String),
+}
+
+impl TimelineMessage {
+    fn priority(&self) -> Priority {
+        match self {
+            // Fetched ahead of scrolling, so it can wait.
+            TimelineMessage::LoadOlder | TimelineMessage::LoadOlderNotifications => {
+                Priority::Background
+            }
+            _ => Priority::User,
+        }
+    }
+}
+
+struct TimelineState {
+    api: RawApi,
+    limiter: RateLimiter,
+    /// `Some` once the home timeline has been loaded, with the url of the next page if there is
+    /// one.
+    home: Option<Option<String>>,
+    /// Likewise for notifications.
+    notifications: Option<Option<String>>,
+    cache: Option<Cache>,
+}
+
+impl TimelineState {
+    async fn answer(&mut self, msg: TimelineMessage, mut reply: oneshot::Sender<TimelineMessage>) {
+        let Some(result) = cancellable(&mut reply, self.handle(msg)).await else {
+            debug!("Timeline request was cancelled");
+            return;
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Timeline request failed: {}", e);
+                TimelineMessage::Error(e)
+            }
+        };
+        if send_reply(reply, response).is_err() {
+            warn!("Failed to send timeline reply");
+        }
+    }
+
+    async fn handle(&mut self, msg: TimelineMessage) -> Result<TimelineMessage, String> {
+        match msg {
+            TimelineMessage::LoadCached => {
+                let statuses = match &mut self.cache {
+                    Some(cache) => cache.load_timeline(HOME).await.unwrap_or_else(|e| {
+                        warn!("{}", e);
+                        vec![]
+                    }),
+                    None => vec![],
+                };
+                Ok(TimelineMessage::Cached(statuses))
+            }
+            TimelineMessage::LoadHome => {
+                let page = send_page(self.api.get("/api/v1/timelines/home"), Priority::User)
+                    .await
+                    .map_err(|e| format!("Failed to load home timeline: {}", e))?;
+                let statuses = page.items;
+                self.home = Some(page.next);
+                self.cache_home(&statuses, true).await;
+                Ok(TimelineMessage::Statuses {
+                    has_more: !statuses.is_empty(),
+                    statuses,
+                    replace: true,
+                })
+            }
+            TimelineMessage::LoadOlder => {
+                let next = self
+                    .home
+                    .as_ref()
+                    .ok_or_else(|| "The home timeline hasn't been loaded yet".to_string())?;
+                let page = match next {
+                    // Already held back in the service loop until the rate limit allowed it.
+                    Some(url) => send_page(self.api.follow(url), Priority::Background)
+                        .await
+                        .map_err(|e| format!("Failed to load older statuses: {}", e))?,
+                    None => Default::default(),
+                };
+                let statuses = page.items;
+                self.home = Some(page.next);
+                self.cache_home(&statuses, false).await;
+                Ok(TimelineMessage::Statuses {
+                    has_more: !statuses.is_empty(),
+                    statuses,
+                    replace: false,
+                })
+            }
+            TimelineMessage::LoadCachedNotifications => {
+                let notifications = match &mut self.cache {
+                    Some(cache) => cache.load_notifications().await.unwrap_or_else(|e| {
+                        warn!("{}", e);
+                        vec![]
+                    }),
+                    None => vec![],
+                };
+                Ok(TimelineMessage::CachedNotifications(notifications))
+            }
+            TimelineMessage::LoadNotifications => {
+                let page = send_page(self.api.get("/api/v1/notifications"), Priority::User)
+                    .await
+                    .map_err(|e| format!("Failed to load notifications: {}", e))?;
+                let notifications = page.items;
+                self.notifications = Some(page.next);
+                self.cache_notifications(&notifications, true).await;
+                Ok(TimelineMessage::Notifications {
+                    has_more: !notifications.is_empty(),
+                    notifications,
+                    replace: true,
+                })
+            }
+            TimelineMessage::LoadOlderNotifications => {
+                let next = self
+                    .notifications
+                    .as_ref()
+                    .ok_or_else(|| "Notifications haven't been loaded yet".to_string())?;
+                let page = match next {
+                    // Already held back in the service loop until the rate limit allowed it.
+                    Some(url) => send_page(self.api.follow(url), Priority::Background)
+                        .await
+                        .map_err(|e| format!("Failed to load older notifications: {}", e))?,
+                    None => Default::default(),
+                };
+                let notifications = page.items;
+                self.notifications = Some(page.next);
+                self.cache_notifications(&notifications, false).await;
+                Ok(TimelineMessage::Notifications {
+                    has_more: !notifications.is_empty(),
+                    notifications,
+                    replace: false,
+                })
+            }
+            TimelineMessage::CacheSize => {
+                let size = match &mut self.cache {
+                    Some(cache) => cache.size().await.map_err(|e| e.to_string())?,
+                    None => 0,
+                };
+                Ok(TimelineMessage::CacheSizeIs(size))
+            }
+            TimelineMessage::ClearCache => {
+                if let Some(cache) = &mut self.cache {
+                    cache.clear().await.map_err(|e| e.to_string())?;
+                }
+                Ok(TimelineMessage::CacheSizeIs(0))
+            }
+            other => Err(format!("Unexpected timeline request: {:?}", other)),
+        }
+    }
+
+    /// Failing to cache shouldn't fail loading the timeline, so errors are only logged.
+    async fn cache_home(&mut self, statuses: &[Status], replace: bool) {
+        if let Some(cache) = &mut self.cache {
+            if let Err(e) = cache.store_timeline(HOME, statuses, replace).await {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    async fn cache_notifications(&mut self, notifications: &[Notification], replace: bool) {
+        if let Some(cache) = &mut self.cache {
+            if let Err(e) = cache.store_notifications(notifications, replace).await {
+                warn!("{}", e);
+            }
+        }
+    }
+}
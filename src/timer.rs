@@ -0,0 +1,9 @@
+//! Timers that behave the same natively and in the browser.
+//!
+//! tokio's timers need a tokio runtime driving them, which there isn't on the web, so there they
+//! come from wasmtimer instead. Use these rather than either crate directly.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use tokio::time::{sleep, timeout};
+#[cfg(target_arch = "wasm32")]
+pub use wasmtimer::tokio::{sleep, timeout};
@@ -0,0 +1,249 @@
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    accounts::{AccountList, AccountRow, AccountsMessage},
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    views::{display_name, status::avatar, ViewAction},
+};
+
+pub struct AccountListState {
+    pub rows: Vec<AccountRow>,
+    pub has_more: bool,
+    pub error: Option<String>,
+}
+
+/// An infinitely scrolling list of accounts, such as someone's followers.
+pub struct AccountListView {
+    list: AccountList,
+    pages: AsyncRequestBridge<AccountsMessage, AccountListState>,
+    actions: AsyncRequestBridge<AccountsMessage, AccountsMessage>,
+    action_error: Option<String>,
+}
+
+impl AccountListView {
+    pub fn new(list: AccountList, tx: mpsc::Sender<Message<AccountsMessage>>) -> Self {
+        AccountListView {
+            list,
+            pages: AsyncRequestBridge::new(tx.clone()),
+            actions: AsyncRequestBridge::new(tx),
+            action_error: None,
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.pages.send(
+            AccountsMessage::LoadList(self.list.clone()),
+            Box::new(merge_page),
+        );
+    }
+
+    fn load_more(&mut self) {
+        self.pages.send(
+            AccountsMessage::NextPage(self.list.clone()),
+            Box::new(merge_page),
+        );
+    }
+
+    fn send_action(&mut self, msg: AccountsMessage) {
+        self.action_error = None;
+        self.actions.send(msg, Box::new(|m, _| m));
+    }
+
+    /// Apply the result of a follow or follow request action to the loaded rows.
+    fn apply_action_result(&mut self) {
+        let result = match self.actions.take_complete() {
+            Some(result) => result,
+            None => return,
+        };
+        let state = self.pages.current_state_mut();
+        match (result, state) {
+            (AccountsMessage::RelationshipUpdated(relationship), Some(state)) => {
+                for row in state.rows.iter_mut() {
+                    if row.account.id == relationship.id {
+                        row.relationship = Some(relationship.clone());
+                    }
+                }
+            }
+            (AccountsMessage::FollowRequestResolved(id), Some(state)) => {
+                state.rows.retain(|row| row.account.id != id);
+            }
+            (AccountsMessage::Error(e), _) => self.action_error = Some(e),
+            _ => (),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, me: &Account) -> Option<ViewAction> {
+        let pages_changed = self.pages.pump_messages();
+        if self.actions.pump_messages() || pages_changed {
+            ui.ctx().request_repaint();
+        }
+        self.apply_action_result();
+
+        if let AsyncRequestBridgeState::Init = self.pages.state {
+            self.reload();
+        }
+
+        if let AsyncRequestBridgeState::Error(e) = &self.pages.state {
+            ui.label(format!("error: {}", e));
+            if ui.button("Retry").clicked() {
+                self.reload();
+            }
+            return None;
+        }
+        if let Some(e) = &self.action_error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+
+        let mut action = None;
+        let mut pending = None;
+        let loading = self.pages.is_awaiting();
+        let mut reached_end = false;
+        let has_more = self.pages.current_state().map_or(false, |s| s.has_more);
+
+        egui::ScrollArea::vertical()
+            .id_source(format!("{:?}", self.list))
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                if let Some(state) = self.pages.current_state() {
+                    if let Some(e) = &state.error {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    } else if state.rows.is_empty() && !loading {
+                        ui.label(format!("No {}.", self.list));
+                    }
+                    for row in &state.rows {
+                        match account_row_ui(ui, row, &self.list, me) {
+                            Some(RowAction::Open) => {
+                                action = Some(ViewAction::OpenProfile(row.account.clone()))
+                            }
+                            Some(RowAction::Send(msg)) => pending = Some(msg),
+                            None => (),
+                        }
+                        ui.separator();
+                    }
+                }
+                if loading {
+                    ui.spinner();
+                } else if has_more {
+                    // Load the next page as soon as the end of the list scrolls into view.
+                    let end = ui.label("…");
+                    reached_end = ui.is_rect_visible(end.rect);
+                }
+            });
+
+        if let Some(msg) = pending {
+            self.send_action(msg);
+        }
+        if reached_end {
+            self.load_more();
+        }
+        action
+    }
+}
+
+fn merge_page(m: AccountsMessage, prev_state: Option<AccountListState>) -> AccountListState {
+    match (m, prev_state) {
+        (
+            AccountsMessage::ListPage {
+                rows,
+                has_more,
+                replace: false,
+                ..
+            },
+            Some(mut prev),
+        ) => {
+            prev.rows.extend(rows);
+            prev.has_more = has_more;
+            prev.error = None;
+            prev
+        }
+        (AccountsMessage::ListPage { rows, has_more, .. }, _) => AccountListState {
+            rows,
+            has_more,
+            error: None,
+        },
+        // Keep what was already loaded, but stop asking for more.
+        (AccountsMessage::Error(e), Some(mut prev)) => {
+            prev.has_more = false;
+            prev.error = Some(e);
+            prev
+        }
+        (AccountsMessage::Error(e), None) => AccountListState {
+            rows: vec![],
+            has_more: false,
+            error: Some(e),
+        },
+        _ => panic!("can't handle this response."),
+    }
+}
+
+enum RowAction {
+    Open,
+    Send(AccountsMessage),
+}
+
+fn account_row_ui(
+    ui: &mut egui::Ui,
+    row: &AccountRow,
+    list: &AccountList,
+    me: &Account,
+) -> Option<RowAction> {
+    let mut action = None;
+    ui.horizontal(|ui| {
+        ui.add(avatar(&row.account));
+        ui.vertical(|ui| {
+            if ui
+                .link(egui::RichText::new(display_name(&row.account)).strong())
+                .clicked()
+            {
+                action = Some(RowAction::Open);
+            }
+            ui.horizontal(|ui| {
+                ui.weak(format!("@{}", row.account.acct));
+                if let Some(relationship) = &row.relationship {
+                    if relationship.followed_by {
+                        ui.small("follows you");
+                    }
+                }
+            });
+        });
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            let id = row.account.id.clone();
+            if let AccountList::FollowRequests = list {
+                if ui.button("Reject").clicked() {
+                    action = Some(RowAction::Send(AccountsMessage::RejectFollowRequest(
+                        id.clone(),
+                    )));
+                }
+                if ui.button("Accept").clicked() {
+                    action = Some(RowAction::Send(AccountsMessage::AuthorizeFollowRequest(id)));
+                }
+                return;
+            }
+            if row.account.id == me.id {
+                return;
+            }
+            match &row.relationship {
+                Some(r) if r.following || r.requested => {
+                    let label = if r.requested {
+                        "Cancel request"
+                    } else {
+                        "Unfollow"
+                    };
+                    if ui.button(label).clicked() {
+                        action = Some(RowAction::Send(AccountsMessage::Unfollow(id)));
+                    }
+                }
+                // The server may leave out a relationship, e.g. for an account it's still
+                // fetching. Following gives back the real one.
+                _ => {
+                    if ui.button("Follow").clicked() {
+                        action = Some(RowAction::Send(AccountsMessage::Follow(id)));
+                    }
+                }
+            }
+        });
+    });
+    action
+}
@@ -0,0 +1,459 @@
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, Message},
+    datetime::{format_duration, format_local, parse_local},
+    drafts::AccountDrafts,
+    statuses::{
+        ComposeDraft, InstanceLimits, PollDraft, PostVisibility, ScheduledStatus, StatusesMessage,
+        MIN_SCHEDULE_LEAD,
+    },
+};
+
+/// Poll lengths offered in the composer, in seconds. Ones outside the instance's limits are hidden.
+const POLL_DURATIONS: &[u64] = &[
+    5 * 60,
+    30 * 60,
+    60 * 60,
+    6 * 60 * 60,
+    24 * 60 * 60,
+    3 * 24 * 60 * 60,
+    7 * 24 * 60 * 60,
+];
+
+/// What became of a draft once the server accepted it.
+pub enum Composed {
+    /// `warning` says what went wrong after the server accepted the draft.
+    Posted {
+        status: Status,
+        warning: Option<String>,
+    },
+    Scheduled {
+        scheduled: ScheduledStatus,
+        warning: Option<String>,
+    },
+    /// Posting failed, and the user asked for the draft to be sent once the server is reachable.
+    Queued(ComposeDraft),
+}
+
+pub struct ComposeView {
+    pub draft: ComposeDraft,
+    content_warning: bool,
+    schedule: bool,
+    /// The publish time as typed, in the user's timezone.
+    schedule_text: String,
+    /// The name typed into the drafts menu for saving a copy.
+    draft_name: String,
+    limits: AsyncRequestBridge<StatusesMessage, InstanceLimits>,
+    post: AsyncRequestBridge<StatusesMessage, StatusesMessage>,
+    error: Option<String>,
+}
+
+impl ComposeView {
+    pub fn new(tx: mpsc::Sender<Message<StatusesMessage>>) -> Self {
+        let mut limits = AsyncRequestBridge::new(tx.clone());
+        limits.send(
+            StatusesMessage::LoadLimits,
+            Box::new(|m, _| match m {
+                StatusesMessage::Limits(limits) => limits,
+                _ => InstanceLimits::default(),
+            }),
+        );
+        ComposeView {
+            draft: Default::default(),
+            content_warning: false,
+            schedule: false,
+            schedule_text: String::new(),
+            draft_name: String::new(),
+            limits,
+            post: AsyncRequestBridge::new(tx),
+            error: None,
+        }
+    }
+
+    /// Replace whatever is being written with a draft, such as one for editing an existing status.
+    pub fn load(&mut self, draft: ComposeDraft) {
+        self.content_warning = !draft.spoiler_text.is_empty();
+        self.schedule = draft.scheduled_at.is_some();
+        self.schedule_text = draft.scheduled_at.map(format_local).unwrap_or_default();
+        self.draft = draft;
+        self.error = None;
+    }
+
+    /// Like `load`, but first keeps whatever was being written as a saved draft.
+    pub fn open(&mut self, draft: ComposeDraft, drafts: &mut AccountDrafts) {
+        if !self.draft.is_empty() {
+            drafts.save_as(&stash_name(&self.draft), self.draft.clone());
+        }
+        self.load(draft);
+    }
+
+    fn limits(&self) -> InstanceLimits {
+        self.limits.current_state().cloned().unwrap_or_default()
+    }
+
+    /// Problems that would make the server reject the post.
+    fn validate(&self) -> Vec<String> {
+        let limits = self.limits();
+        let mut problems = vec![];
+        let length = self.draft.text.chars().count() + self.draft.spoiler_text.chars().count();
+        if self.draft.text.trim().is_empty()
+            && self.draft.poll.is_none()
+            && self.draft.media_ids.is_empty()
+        {
+            problems.push("Write something first.".to_string());
+        }
+        if length > limits.statuses.max_characters {
+            problems.push(format!(
+                "Posts can be at most {} characters.",
+                limits.statuses.max_characters
+            ));
+        }
+        if let Some(poll) = &self.draft.poll {
+            let polls = &limits.polls;
+            if poll.options.len() < 2 {
+                problems.push("Polls need at least two options.".to_string());
+            }
+            if poll.options.len() > polls.max_options {
+                problems.push(format!(
+                    "Polls can have at most {} options.",
+                    polls.max_options
+                ));
+            }
+            if poll.options.iter().any(|o| o.trim().is_empty()) {
+                problems.push("Poll options can't be empty.".to_string());
+            }
+            if poll
+                .options
+                .iter()
+                .any(|o| o.chars().count() > polls.max_characters_per_option)
+            {
+                problems.push(format!(
+                    "Poll options can be at most {} characters.",
+                    polls.max_characters_per_option
+                ));
+            }
+            if poll.expires_in < polls.min_expiration || poll.expires_in > polls.max_expiration {
+                problems.push("That poll duration isn't allowed on this server.".to_string());
+            }
+        }
+        if self.schedule {
+            if let Err(e) = parse_schedule(&self.schedule_text) {
+                problems.push(e);
+            }
+        }
+        problems
+    }
+
+    /// Returns the status once it's been posted or scheduled.
+    pub fn ui(&mut self, ui: &mut egui::Ui, drafts: &mut AccountDrafts) -> Option<Composed> {
+        let limits_changed = self.limits.pump_messages();
+        if self.post.pump_messages() || limits_changed {
+            ui.ctx().request_repaint();
+        }
+        match self.post.take_complete() {
+            // The draft is done with even if there's a warning, as sending it again would make
+            // a second copy.
+            Some(StatusesMessage::Posted { status, warning }) => {
+                self.load(Default::default());
+                return Some(Composed::Posted { status, warning });
+            }
+            Some(StatusesMessage::Scheduled { scheduled, warning }) => {
+                self.load(Default::default());
+                return Some(Composed::Scheduled { scheduled, warning });
+            }
+            Some(StatusesMessage::Error(e)) => self.error = Some(e),
+            _ => (),
+        }
+
+        let limits = self.limits();
+
+        self.drafts_menu_ui(ui, drafts);
+
+        if self.draft.in_reply_to_id.is_some() {
+            ui.horizontal(|ui| {
+                ui.label("Replying to a post.");
+                if ui
+                    .small_button("✖")
+                    .on_hover_text("Post it on its own instead")
+                    .clicked()
+                {
+                    self.draft.in_reply_to_id = None;
+                }
+            });
+        }
+
+        if self.draft.editing.is_some() || self.draft.scheduled_id.is_some() {
+            ui.horizontal(|ui| {
+                ui.label(if self.draft.editing.is_some() {
+                    "Editing a post."
+                } else {
+                    "Editing a scheduled post."
+                });
+                if ui.button("Cancel").clicked() {
+                    self.load(Default::default());
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.content_warning, "Content warning");
+            egui::ComboBox::from_id_source("compose visibility")
+                .selected_text(self.draft.visibility.label())
+                .show_ui(ui, |ui| {
+                    for visibility in PostVisibility::ALL {
+                        ui.selectable_value(
+                            &mut self.draft.visibility,
+                            visibility,
+                            visibility.label(),
+                        );
+                    }
+                });
+        });
+        if self.content_warning {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.draft.spoiler_text)
+                    .hint_text("Content warning"),
+            );
+        } else {
+            self.draft.spoiler_text.clear();
+        }
+
+        ui.add(
+            egui::TextEdit::multiline(&mut self.draft.text)
+                .hint_text("What's on your mind?")
+                .desired_width(f32::INFINITY),
+        );
+        let length = self.draft.text.chars().count() + self.draft.spoiler_text.chars().count();
+        ui.weak(format!("{} / {}", length, limits.statuses.max_characters));
+
+        if !self.draft.media_ids.is_empty() {
+            ui.horizontal(|ui| {
+                let count = self.draft.media_ids.len();
+                ui.label(format!(
+                    "{} {} attached",
+                    count,
+                    if count == 1 { "file" } else { "files" }
+                ));
+                if ui.small_button("Remove").clicked() {
+                    self.draft.media_ids.clear();
+                }
+            });
+        }
+
+        let mut has_poll = self.draft.poll.is_some();
+        if ui.checkbox(&mut has_poll, "Poll").changed() {
+            self.draft.poll = has_poll.then(PollDraft::default);
+        }
+        if let Some(poll) = &mut self.draft.poll {
+            poll_editor_ui(ui, poll, &limits);
+        }
+
+        // Published posts can't be moved back into the schedule.
+        if self.draft.editing.is_none() {
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut self.schedule, "Schedule for").changed()
+                    && self.schedule
+                    && self.schedule_text.is_empty()
+                {
+                    let soon = time::OffsetDateTime::now_utc() + time::Duration::hours(1);
+                    self.schedule_text = format_local(soon);
+                }
+                if self.schedule {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.schedule_text)
+                            .hint_text("YYYY-MM-DD HH:MM")
+                            .desired_width(140.0),
+                    );
+                }
+            });
+        }
+        self.draft.scheduled_at = if self.schedule && self.draft.editing.is_none() {
+            parse_local(&self.schedule_text).ok()
+        } else {
+            None
+        };
+
+        for problem in self.validate() {
+            ui.colored_label(ui.visuals().warn_fg_color, problem);
+        }
+        let mut queue = false;
+        if let Some(e) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+            // Edits and scheduled posts need an answer from the server, so only new posts can wait.
+            let plain_post = self.draft.editing.is_none()
+                && self.draft.scheduled_at.is_none()
+                && self.draft.scheduled_id.is_none();
+            queue = plain_post && ui.button("Send when back online").clicked();
+        }
+        if queue {
+            let draft = std::mem::take(&mut self.draft);
+            self.load(Default::default());
+            return Some(Composed::Queued(draft));
+        }
+
+        ui.horizontal(|ui| {
+            let posting = self.post.is_awaiting();
+            let valid = self.validate().is_empty();
+            let label = if self.draft.scheduled_at.is_some() {
+                "Schedule"
+            } else if self.draft.editing.is_some() {
+                "Save"
+            } else {
+                "Post"
+            };
+            if ui
+                .add_enabled(valid && !posting, egui::Button::new(label))
+                .clicked()
+            {
+                self.error = None;
+                self.post.send(
+                    StatusesMessage::Post(self.draft.clone()),
+                    Box::new(|m, _| m),
+                );
+            }
+            if posting {
+                ui.spinner();
+            }
+        });
+        None
+    }
+
+    fn drafts_menu_ui(&mut self, ui: &mut egui::Ui, drafts: &mut AccountDrafts) {
+        let mut action = None;
+        ui.menu_button(format!("Drafts ({})", drafts.saved.len()), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.draft_name)
+                        .hint_text("Name")
+                        .desired_width(120.0),
+                );
+                let name = self.draft_name.trim();
+                if ui
+                    .add_enabled(
+                        !name.is_empty() && !self.draft.is_empty(),
+                        egui::Button::new("Save a copy"),
+                    )
+                    .clicked()
+                {
+                    drafts.save_as(name, self.draft.clone());
+                    self.draft_name.clear();
+                }
+            });
+            if ui
+                .add_enabled(!self.draft.is_empty(), egui::Button::new("New draft"))
+                .on_hover_text("Keep what's here as a saved draft and start again")
+                .clicked()
+            {
+                action = Some(DraftsAction::New);
+            }
+            ui.separator();
+            if drafts.saved.is_empty() {
+                ui.weak("No saved drafts.");
+            }
+            for saved in &drafts.saved {
+                ui.horizontal(|ui| {
+                    if ui.button(&saved.name).clicked() {
+                        action = Some(DraftsAction::Open(saved.name.clone()));
+                    }
+                    ui.weak(format_local(saved.saved_at));
+                    if ui.small_button("✖").on_hover_text("Delete draft").clicked() {
+                        action = Some(DraftsAction::Delete(saved.name.clone()));
+                    }
+                });
+            }
+            if matches!(action, Some(DraftsAction::New | DraftsAction::Open(_))) {
+                ui.close_menu();
+            }
+        });
+
+        match action {
+            Some(DraftsAction::New) => self.open(Default::default(), drafts),
+            Some(DraftsAction::Open(name)) => {
+                if let Some(draft) = drafts.take(&name) {
+                    self.open(draft, drafts);
+                }
+            }
+            Some(DraftsAction::Delete(name)) => {
+                drafts.take(&name);
+            }
+            None => (),
+        }
+    }
+}
+
+enum DraftsAction {
+    New,
+    Open(String),
+    Delete(String),
+}
+
+/// A name for a draft that's put aside to make room for another.
+fn stash_name(draft: &ComposeDraft) -> String {
+    let first_line = draft.text.lines().next().unwrap_or_default().trim();
+    if first_line.is_empty() {
+        format!(
+            "Untitled, {}",
+            format_local(time::OffsetDateTime::now_utc())
+        )
+    } else {
+        first_line.chars().take(30).collect()
+    }
+}
+
+/// Read a publish time typed in the user's timezone, checking it's far enough ahead.
+pub fn parse_schedule(text: &str) -> Result<time::OffsetDateTime, String> {
+    let at = parse_local(text)?;
+    if at < time::OffsetDateTime::now_utc() + MIN_SCHEDULE_LEAD {
+        return Err(format!(
+            "Scheduled posts have to be at least {} away.",
+            format_duration(MIN_SCHEDULE_LEAD)
+        ));
+    }
+    Ok(at)
+}
+
+fn poll_editor_ui(ui: &mut egui::Ui, poll: &mut PollDraft, limits: &InstanceLimits) {
+    let polls = &limits.polls;
+    let mut remove = None;
+    for (i, option) in poll.options.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(option)
+                    .hint_text(format!("Option {}", i + 1))
+                    .char_limit(polls.max_characters_per_option),
+            );
+            if ui.small_button("✖").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        poll.options.remove(i);
+    }
+    if poll.options.len() < polls.max_options && ui.button("Add option").clicked() {
+        poll.options.push(String::new());
+    }
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Duration")
+            .selected_text(format_duration(time::Duration::seconds(
+                poll.expires_in as i64,
+            )))
+            .show_ui(ui, |ui| {
+                for duration in POLL_DURATIONS
+                    .iter()
+                    .filter(|d| **d >= polls.min_expiration && **d <= polls.max_expiration)
+                {
+                    ui.selectable_value(
+                        &mut poll.expires_in,
+                        *duration,
+                        format_duration(time::Duration::seconds(*duration as i64)),
+                    );
+                }
+            });
+        ui.checkbox(&mut poll.multiple, "Multiple choice");
+        ui.checkbox(&mut poll.hide_totals, "Hide totals until it ends");
+    });
+}
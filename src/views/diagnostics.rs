@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use crate::diagnostics::{self, BridgeRecord, HttpRecord, LogRecord};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum Tab {
+    #[default]
+    Log,
+    Network,
+    Bridges,
+}
+
+/// Recent log records, http exchanges and the state of each bridge, for working out what an
+/// instance is doing differently.
+#[derive(Default)]
+pub struct DiagnosticsWindow {
+    pub open: bool,
+    tab: Tab,
+    /// Only records containing this are shown.
+    filter: String,
+    /// Where the last export went, or why it didn't.
+    exported: Option<Result<String, String>>,
+}
+
+impl DiagnosticsWindow {
+    pub fn ui(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+        let snapshot = diagnostics::snapshot();
+
+        let mut open = true;
+        egui::Window::new("Diagnostics")
+            .open(&mut open)
+            .default_size([600.0, 400.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let logs = format!("Log ({})", snapshot.logs.len());
+                    ui.selectable_value(&mut self.tab, Tab::Log, logs);
+                    let http = format!("Network ({})", snapshot.http.len());
+                    ui.selectable_value(&mut self.tab, Tab::Network, http);
+                    let bridges = format!("Bridges ({})", snapshot.bridges.len());
+                    ui.selectable_value(&mut self.tab, Tab::Bridges, bridges);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Filter:");
+                    ui.text_edit_singleline(&mut self.filter);
+                    if ui.button("Clear").clicked() {
+                        diagnostics::clear();
+                    }
+                    if ui
+                        .button("Export")
+                        .on_hover_text("Save all of this to attach to a bug report")
+                        .clicked()
+                    {
+                        self.exported = Some(diagnostics::export());
+                    }
+                });
+                match &self.exported {
+                    Some(Ok(path)) => {
+                        ui.weak(format!("Exported to {}", path));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!("Couldn't export: {}", e),
+                        );
+                    }
+                    None => (),
+                }
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| match self.tab {
+                        Tab::Log => log_ui(ui, &snapshot.logs, &self.filter),
+                        Tab::Network => network_ui(ui, &snapshot.http, &self.filter),
+                        Tab::Bridges => bridges_ui(ui, &snapshot.bridges, &self.filter),
+                    });
+            });
+        self.open = open;
+
+        // Nothing wakes the ui when a record comes in, so keep up while the window's open.
+        ctx.request_repaint_after(Duration::from_secs(1));
+    }
+}
+
+/// Whether any of `fields` contains `filter`, ignoring case.
+fn matches(filter: &str, fields: &[&str]) -> bool {
+    let filter = filter.to_lowercase();
+    fields
+        .iter()
+        .any(|field| field.to_lowercase().contains(&filter))
+}
+
+fn log_ui(ui: &mut egui::Ui, logs: &[LogRecord], filter: &str) {
+    for record in logs
+        .iter()
+        .filter(|r| matches(filter, &[&r.target, &r.message]))
+    {
+        ui.horizontal_wrapped(|ui| {
+            ui.weak(&record.at);
+            let level = egui::RichText::new(&record.level).monospace();
+            match record.level.as_str() {
+                "ERROR" => ui.colored_label(ui.visuals().error_fg_color, level),
+                "WARN" => ui.colored_label(ui.visuals().warn_fg_color, level),
+                _ => ui.label(level),
+            };
+            ui.weak(&record.target);
+            ui.label(&record.message);
+        });
+    }
+}
+
+fn network_ui(ui: &mut egui::Ui, http: &[HttpRecord], filter: &str) {
+    for record in http
+        .iter()
+        .filter(|r| matches(filter, &[&r.method, &r.url]))
+    {
+        let failed = record.error.is_some() || record.status.map_or(false, |s| s >= 400);
+        let status = record
+            .status
+            .map_or("failed".to_string(), |status| status.to_string());
+        let mut summary = egui::RichText::new(format!(
+            "{} {} {} · {} ms",
+            status, record.method, record.url, record.elapsed_ms
+        ));
+        if failed {
+            summary = summary.color(ui.visuals().error_fg_color);
+        }
+        egui::CollapsingHeader::new(summary)
+            .id_source((&record.at, &record.url))
+            .show(ui, |ui| {
+                ui.weak(&record.at);
+                for (name, value) in &record.rate_limit {
+                    ui.label(format!("{}: {}", name, value));
+                }
+                if let Some(e) = &record.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                if let Some(body) = &record.body {
+                    ui.add(egui::Label::new(egui::RichText::new(body).monospace()).wrap(true));
+                }
+            });
+    }
+}
+
+fn bridges_ui(ui: &mut egui::Ui, bridges: &[BridgeRecord], filter: &str) {
+    egui::Grid::new("diagnostics_bridges")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Messages");
+            ui.strong("State");
+            ui.end_row();
+            for bridge in bridges
+                .iter()
+                .filter(|b| matches(filter, &[&b.messages, b.state]))
+            {
+                ui.label(&bridge.messages);
+                if bridge.queued {
+                    ui.label(format!("{}, queued", bridge.state));
+                } else {
+                    ui.label(bridge.state);
+                }
+                ui.end_row();
+            }
+        });
+}
@@ -0,0 +1,99 @@
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    datetime::format_server_time,
+    diff::{diff_words, DiffSpan},
+    html::to_plain_text,
+    statuses::{StatusEdit, StatusesMessage},
+};
+
+/// Shows each revision of an edited status, with what changed since the one before.
+pub struct HistoryDialog {
+    acct: String,
+    history: AsyncRequestBridge<StatusesMessage, StatusesMessage>,
+}
+
+impl HistoryDialog {
+    pub fn new(status: &Status, tx: mpsc::Sender<Message<StatusesMessage>>) -> Self {
+        let mut history = AsyncRequestBridge::new(tx);
+        history.send(
+            StatusesMessage::LoadHistory(status.id.clone()),
+            Box::new(|m, _| m),
+        );
+        HistoryDialog {
+            acct: status.account.acct.clone(),
+            history,
+        }
+    }
+
+    /// Returns false once the dialog has been closed.
+    pub fn ui(&mut self, ctx: &egui::Context) -> bool {
+        if self.history.pump_messages() {
+            ctx.request_repaint();
+        }
+
+        let mut open = true;
+        egui::Window::new(format!("Edit history of @{}'s post", self.acct))
+            .open(&mut open)
+            .collapsible(false)
+            .vscroll(true)
+            .show(ctx, |ui| match &self.history.state {
+                AsyncRequestBridgeState::Complete(StatusesMessage::History(revisions)) => {
+                    // The server lists revisions oldest first; show the newest at the top.
+                    for (i, revision) in revisions.iter().enumerate().rev() {
+                        let previous = i.checked_sub(1).map(|p| &revisions[p]);
+                        revision_ui(ui, revision, previous);
+                        ui.separator();
+                    }
+                }
+                AsyncRequestBridgeState::Complete(StatusesMessage::Error(e))
+                | AsyncRequestBridgeState::Error(e) => {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                _ => {
+                    ui.spinner();
+                }
+            });
+        open
+    }
+}
+
+fn revision_ui(ui: &mut egui::Ui, revision: &StatusEdit, previous: Option<&StatusEdit>) {
+    let created_at = format_server_time(&revision.created_at);
+    ui.weak(match previous {
+        Some(_) => format!("Edited {}", created_at),
+        None => format!("Original, posted {}", created_at),
+    });
+
+    let text = to_plain_text(&revision.content);
+    let previous_text = previous.map(|p| to_plain_text(&p.content));
+    if !revision.spoiler_text.is_empty() {
+        ui.label(egui::RichText::new(format!("CW: {}", revision.spoiler_text)).italics());
+    }
+    ui.horizontal_wrapped(|ui| match &previous_text {
+        Some(previous_text) => {
+            for span in diff_words(previous_text, &text) {
+                match span {
+                    DiffSpan::Same(word) => {
+                        ui.label(word);
+                    }
+                    DiffSpan::Added(word) => {
+                        ui.label(egui::RichText::new(word).color(egui::Color32::GREEN));
+                    }
+                    DiffSpan::Removed(word) => {
+                        ui.label(
+                            egui::RichText::new(word)
+                                .color(ui.visuals().error_fg_color)
+                                .strikethrough(),
+                        );
+                    }
+                }
+            }
+        }
+        None => {
+            ui.label(&text);
+        }
+    });
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use egui::load::{Bytes, BytesLoadResult, BytesLoader, BytesPoll, LoadError};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{channels::Message, images::ImageMessage};
+
+enum Loading {
+    Waiting(oneshot::Receiver<ImageMessage>),
+    Ready(Arc<[u8]>),
+    Failed(String),
+}
+
+/// Gives egui the bytes of images by their url, through the images service so they're cached.
+///
+/// Installed when a session starts. egui's own image loaders still decode what it hands back.
+pub struct ImageLoader {
+    tx: mpsc::Sender<Message<ImageMessage>>,
+    images: Mutex<HashMap<String, Loading>>,
+}
+
+impl ImageLoader {
+    pub const ID: &'static str = egui::generate_loader_id!(ImageLoader);
+
+    pub fn new(tx: mpsc::Sender<Message<ImageMessage>>) -> Self {
+        ImageLoader {
+            tx,
+            images: Default::default(),
+        }
+    }
+}
+
+impl BytesLoader for ImageLoader {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn load(&self, _ctx: &egui::Context, uri: &str) -> BytesLoadResult {
+        if !uri.starts_with("https://") && !uri.starts_with("http://") {
+            return Err(LoadError::NotSupported);
+        }
+        let mut images = self.images.lock().unwrap();
+        // egui asks again every frame until the image is ready, and the service wakes the ui
+        // when it answers.
+        if let Some(Loading::Waiting(reply)) = images.get_mut(uri) {
+            let loaded = match reply.try_recv() {
+                Ok(ImageMessage::Loaded(data)) => Loading::Ready(data.into()),
+                Ok(ImageMessage::Error(e)) => Loading::Failed(e),
+                Ok(ImageMessage::Load(_)) => Loading::Failed("Unexpected reply".to_string()),
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    return Ok(BytesPoll::Pending { size: None })
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    Loading::Failed("The images service stopped".to_string())
+                }
+            };
+            images.insert(uri.to_string(), loaded);
+        }
+        match images.get(uri) {
+            Some(Loading::Ready(data)) => Ok(BytesPoll::Ready {
+                size: None,
+                bytes: Bytes::Shared(data.clone()),
+                mime: None,
+            }),
+            Some(Loading::Failed(e)) => Err(LoadError::Loading(e.clone())),
+            Some(Loading::Waiting(_)) => Ok(BytesPoll::Pending { size: None }),
+            None => {
+                let (reply, response) = oneshot::channel();
+                let msg = ImageMessage::Load(uri.to_string());
+                // If the service is busy, this is tried again on the next frame.
+                if self.tx.try_send(Message::Request { msg, reply }).is_ok() {
+                    images.insert(uri.to_string(), Loading::Waiting(response));
+                }
+                Ok(BytesPoll::Pending { size: None })
+            }
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.images.lock().unwrap().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.images.lock().unwrap().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.images
+            .lock()
+            .unwrap()
+            .values()
+            .map(|loading| match loading {
+                Loading::Ready(data) => data.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+}
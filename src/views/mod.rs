@@ -0,0 +1,680 @@
+use mastodon_async::prelude::*;
+
+use crate::{
+    accounts::{AccountList, AccountsService},
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState},
+    datetime::format_duration,
+    drafts::{AccountDrafts, DraftStore},
+    images::ImagesService,
+    launch::LaunchAction,
+    moderation::{ModerationEvent, ModerationList, ModerationMessage, ModerationService},
+    outbox::{OutboxAction, OutboxService, OutboxStore},
+    ratelimit::{self, RateLimiter},
+    reports::ReportsService,
+    router::Route,
+    search::{LinksService, ResolvedLink, SearchMessage, SearchService},
+    session::SessionChannels,
+    statuses::{ComposeDraft, StatusesMessage, StatusesService},
+    timeline::{TimelineMessage, TimelineService},
+    views::{
+        accounts::AccountListView,
+        compose::{ComposeView, Composed},
+        history::HistoryDialog,
+        images::ImageLoader,
+        moderation::{DialogOutcome, ModerationDialog, ModerationListView},
+        notifications::NotificationsView,
+        outbox::OutboxIndicator,
+        profile::ProfileView,
+        report::{ReportDialog, ReportOutcome},
+        scheduled::ScheduledView,
+        search::SearchView,
+        status::{apply_interaction, replace_poll, status_ui},
+        timeline::TimelineView,
+    },
+};
+
+pub mod accounts;
+pub mod compose;
+pub mod diagnostics;
+pub mod history;
+pub mod images;
+pub mod moderation;
+pub mod notifications;
+pub mod outbox;
+pub mod poll;
+pub mod profile;
+pub mod report;
+pub mod scheduled;
+pub mod search;
+pub mod status;
+pub mod timeline;
+
+/// Something the user did in a view that the session needs to react to.
+pub enum ViewAction {
+    OpenProfile(Account),
+    Moderate(ModerationRequest),
+    Report(Status),
+    OpenStatus(Status),
+    /// A link that may be a remote post or profile, to be resolved by the user's instance.
+    OpenUrl(String),
+    Vote {
+        poll: PollId,
+        choices: Vec<usize>,
+    },
+    RefreshPoll(PollId),
+    Reply(Status),
+    /// A favourite, boost or bookmark, sent through the outbox so it survives going offline.
+    Queue(OutboxAction),
+    /// Reopen one of the user's own statuses in the composer.
+    Edit(Status),
+    Delete(Status),
+    DeleteAndRedraft(Status),
+    ShowHistory(Status),
+}
+
+/// A moderation action the user picked from a menu, which still needs confirming.
+pub enum ModerationRequest {
+    Mute(Account),
+    Block(Account),
+    BlockDomain(String),
+}
+
+pub fn display_name(account: &Account) -> &str {
+    if account.display_name.is_empty() {
+        &account.username
+    } else {
+        &account.display_name
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum SessionPage {
+    Home,
+    Notifications,
+    Compose,
+    Scheduled,
+    Search,
+    Profile,
+    Status,
+    FollowRequests,
+    Settings,
+}
+
+/// Everything shown once the user has signed in.
+pub struct SessionView {
+    channels: SessionChannels,
+    /// Where this account's drafts and outbox are kept between runs.
+    account_key: String,
+    /// Whether saved drafts and outbox items have been loaded back in.
+    restored: bool,
+    /// Whether egui has been given the session's `ImageLoader`.
+    images_installed: bool,
+    outbox: OutboxIndicator,
+    page: SessionPage,
+    timeline: TimelineView,
+    notifications: NotificationsView,
+    search: SearchView,
+    compose: ComposeView,
+    scheduled: ScheduledView,
+    status_actions: AsyncRequestBridge<StatusesMessage, StatusesMessage>,
+    /// Profiles opened from lists, most recent last. The signed in account is always first.
+    profiles: Vec<ProfileView>,
+    follow_requests: AccountListView,
+    /// The post opened from a link, shown on its own page.
+    status: Option<Status>,
+    links: AsyncRequestBridge<SearchMessage, SearchMessage>,
+    moderation_lists: Vec<ModerationListView>,
+    moderation_tab: usize,
+    moderation_dialog: Option<ModerationDialog>,
+    moderation: AsyncRequestBridge<ModerationMessage, ModerationMessage>,
+    moderation_error: Option<String>,
+    report_dialog: Option<ReportDialog>,
+    cache: AsyncRequestBridge<TimelineMessage, TimelineMessage>,
+    history_dialog: Option<HistoryDialog>,
+    /// A short confirmation shown after something was done in a dialog.
+    notice: Option<String>,
+    rate_limit: RateLimiter,
+}
+
+impl SessionView {
+    pub fn new(channels: SessionChannels) -> Self {
+        let own_profile = ProfileView::new(
+            channels.account.clone(),
+            channels.sender::<AccountsService>(),
+        );
+        let follow_requests = AccountListView::new(
+            AccountList::FollowRequests,
+            channels.sender::<AccountsService>(),
+        );
+        let moderation_lists = [
+            ModerationList::Mutes,
+            ModerationList::Blocks,
+            ModerationList::DomainBlocks,
+        ]
+        .into_iter()
+        .map(|list| ModerationListView::new(list, channels.sender::<ModerationService>()))
+        .collect();
+        SessionView {
+            account_key: channels.account_key(),
+            restored: false,
+            images_installed: false,
+            outbox: OutboxIndicator::new(channels.sender::<OutboxService>()),
+            page: SessionPage::Home,
+            timeline: TimelineView::new(channels.sender::<TimelineService>()),
+            notifications: NotificationsView::new(channels.sender::<TimelineService>()),
+            search: SearchView::new(channels.sender::<SearchService>()),
+            compose: ComposeView::new(channels.sender::<StatusesService>()),
+            scheduled: ScheduledView::new(channels.sender::<StatusesService>()),
+            status_actions: channels.bridge::<StatusesService, _>(),
+            profiles: vec![own_profile],
+            follow_requests,
+            status: None,
+            links: channels.bridge::<LinksService, _>(),
+            moderation_lists,
+            moderation_tab: 0,
+            moderation_dialog: None,
+            moderation: channels.bridge::<ModerationService, _>(),
+            moderation_error: None,
+            report_dialog: None,
+            cache: channels.bridge::<TimelineService, _>(),
+            history_dialog: None,
+            notice: None,
+            rate_limit: ratelimit::for_instance(&channels.mastodon.data.base),
+            channels,
+        }
+    }
+
+    /// `drafts` and `outbox` are kept up to date with this account's state, for eframe to persist.
+    pub fn ui(&mut self, ui: &mut egui::Ui, drafts: &mut DraftStore, outbox: &mut OutboxStore) {
+        let drafts = drafts.for_account(&self.account_key);
+        let outbox = outbox.entry(self.account_key.clone()).or_default();
+        if !self.restored {
+            self.compose.load(drafts.current.clone());
+            self.outbox.restore(outbox.clone());
+            self.restored = true;
+        }
+        if !self.images_installed {
+            ui.ctx()
+                .add_bytes_loader(std::sync::Arc::new(ImageLoader::new(
+                    self.channels.sender::<ImagesService>(),
+                )));
+            self.images_installed = true;
+        }
+
+        if self.moderation.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        match self.moderation.take_complete() {
+            Some(ModerationMessage::Applied(event)) => self.apply_moderation(&event),
+            Some(ModerationMessage::Error(e)) => self.moderation_error = Some(e),
+            _ => (),
+        }
+
+        if self.links.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        match self.links.take_complete() {
+            Some(SearchMessage::Resolved {
+                link: Some(link), ..
+            }) => self.open_resolved(link),
+            // Fall back to the browser when the instance can't make sense of the link.
+            Some(SearchMessage::Resolved { url, link: None }) if url.starts_with("http") => {
+                ui.ctx().open_url(egui::OpenUrl::new_tab(url));
+            }
+            Some(SearchMessage::Resolved { url, link: None }) => {
+                self.notice = Some(format!("Couldn't find @{}.", url));
+            }
+            Some(SearchMessage::Error(e)) => self.notice = Some(e),
+            _ => (),
+        }
+
+        if self.status_actions.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        match self.status_actions.take_complete() {
+            Some(StatusesMessage::PollUpdated(poll)) => self.update_poll(&poll),
+            Some(StatusesMessage::EditReady(draft)) => self.open_draft(draft, drafts),
+            Some(StatusesMessage::Redrafted { deleted, draft }) => {
+                self.remove_status(&deleted);
+                self.open_draft(draft, drafts);
+            }
+            Some(StatusesMessage::Deleted(id)) => self.remove_status(&id),
+            Some(StatusesMessage::Error(e)) => self.notice = Some(e),
+            _ => (),
+        }
+
+        if let Some(dialog) = &mut self.moderation_dialog {
+            match dialog.ui(ui.ctx()) {
+                DialogOutcome::Open => (),
+                DialogOutcome::Cancelled => self.moderation_dialog = None,
+                DialogOutcome::Confirmed(msg) => {
+                    self.moderation_dialog = None;
+                    self.moderation_error = None;
+                    self.moderation.send(msg, Box::new(|m, _| m));
+                }
+            }
+        }
+
+        if let Some(dialog) = &mut self.report_dialog {
+            match dialog.ui(ui.ctx()) {
+                ReportOutcome::Open => (),
+                ReportOutcome::Closed => self.report_dialog = None,
+                ReportOutcome::Submitted => {
+                    self.report_dialog = None;
+                    self.notice = Some("Thanks, your report was sent to the moderators.".into());
+                }
+            }
+        }
+
+        if let Some(dialog) = &mut self.history_dialog {
+            if !dialog.ui(ui.ctx()) {
+                self.history_dialog = None;
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Signed in as @{}", self.channels.account.acct));
+            ui.selectable_value(&mut self.page, SessionPage::Home, "Home");
+            ui.selectable_value(&mut self.page, SessionPage::Notifications, "Notifications");
+            ui.selectable_value(&mut self.page, SessionPage::Compose, "Compose");
+            ui.selectable_value(&mut self.page, SessionPage::Scheduled, "Scheduled");
+            ui.selectable_value(&mut self.page, SessionPage::Search, "Search");
+            ui.selectable_value(&mut self.page, SessionPage::Profile, "Profile");
+            if self.status.is_some() {
+                ui.selectable_value(&mut self.page, SessionPage::Status, "Post");
+            }
+            // Only locked accounts need to approve followers.
+            if self.channels.account.locked {
+                ui.selectable_value(
+                    &mut self.page,
+                    SessionPage::FollowRequests,
+                    "Follow requests",
+                );
+            }
+            ui.selectable_value(&mut self.page, SessionPage::Settings, "Settings");
+            self.outbox.ui(ui, outbox);
+            self.rate_limit_ui(ui);
+            if self.links.is_awaiting() {
+                ui.spinner();
+                ui.weak("Opening link…");
+                if ui.small_button("Cancel").clicked() {
+                    self.links.cancel();
+                }
+            } else if self.status_actions.is_awaiting() {
+                ui.spinner();
+            }
+        });
+        if let Some(e) = &self.moderation_error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+        if let Some(notice) = &self.notice {
+            let mut dismissed = false;
+            ui.horizontal(|ui| {
+                ui.label(notice);
+                dismissed = ui.small_button("✖").clicked();
+            });
+            if dismissed {
+                self.notice = None;
+            }
+        }
+        ui.separator();
+
+        let me = &self.channels.account;
+        let action = match self.page {
+            SessionPage::Home => self.timeline.ui(ui, me),
+            SessionPage::Notifications => self.notifications.ui(ui, me),
+            SessionPage::Compose => {
+                let editing = self.compose.draft.editing.is_some();
+                let was_scheduled = self.compose.draft.scheduled_id.is_some();
+                match self.compose.ui(ui, drafts) {
+                    Some(Composed::Posted { status, warning }) if editing => {
+                        self.notice = warning;
+                        self.replace_status(status);
+                    }
+                    Some(Composed::Posted { status, warning }) => {
+                        self.notice = warning;
+                        if was_scheduled {
+                            self.scheduled.reload();
+                        }
+                        self.timeline.prepend(status);
+                        self.page = SessionPage::Home;
+                    }
+                    Some(Composed::Queued(draft)) => {
+                        self.outbox.enqueue(OutboxAction::Post(draft));
+                        self.notice =
+                            Some("Your post will be sent once the server can be reached.".into());
+                    }
+                    Some(Composed::Scheduled { warning, .. }) => {
+                        self.notice = warning;
+                        self.scheduled.reload();
+                        self.page = SessionPage::Scheduled;
+                    }
+                    None => (),
+                }
+                None
+            }
+            SessionPage::Scheduled => {
+                if let Some(draft) = self.scheduled.ui(ui) {
+                    self.open_draft(draft, drafts);
+                }
+                None
+            }
+            SessionPage::Search => self.search.ui(ui, me),
+            SessionPage::Profile => {
+                if self.profiles.len() > 1 && ui.button("⬅ Back").clicked() {
+                    self.profiles.pop();
+                }
+                self.profiles.last_mut().and_then(|p| p.ui(ui, me))
+            }
+            SessionPage::Status => match &self.status {
+                Some(status) => {
+                    egui::ScrollArea::vertical()
+                        .id_source("opened status")
+                        .show(ui, |ui| status_ui(ui, status, me))
+                        .inner
+                }
+                None => None,
+            },
+            SessionPage::FollowRequests => self.follow_requests.ui(ui, me),
+            SessionPage::Settings => {
+                self.settings_ui(ui);
+                None
+            }
+        };
+
+        match action {
+            Some(ViewAction::OpenProfile(account)) => self.open_profile(account),
+            Some(ViewAction::Moderate(request)) => {
+                self.moderation_dialog = Some(ModerationDialog::new(request));
+            }
+            Some(ViewAction::OpenStatus(status)) => self.open_status(status),
+            Some(ViewAction::OpenUrl(url)) => {
+                self.links
+                    .send(SearchMessage::Resolve(url), Box::new(|m, _| m));
+            }
+            Some(ViewAction::Vote { poll, choices }) => {
+                self.status_actions
+                    .send(StatusesMessage::Vote { poll, choices }, Box::new(|m, _| m));
+            }
+            Some(ViewAction::RefreshPoll(poll)) => {
+                self.status_actions
+                    .send(StatusesMessage::RefreshPoll(poll), Box::new(|m, _| m));
+            }
+            Some(ViewAction::Queue(action)) => {
+                self.timeline.apply_interaction(&action);
+                if let Some(status) = &mut self.status {
+                    apply_interaction(status, &action);
+                }
+                self.outbox.enqueue(action);
+            }
+            Some(ViewAction::Reply(status)) => {
+                self.open_draft(ComposeDraft::reply_to(&status), drafts);
+            }
+            Some(ViewAction::Edit(status)) => {
+                self.status_actions.send(
+                    StatusesMessage::LoadForEdit(ComposeDraft::from_status(&status)),
+                    Box::new(|m, _| m),
+                );
+            }
+            Some(ViewAction::Delete(status)) => {
+                self.status_actions
+                    .send(StatusesMessage::Delete(status.id), Box::new(|m, _| m));
+            }
+            Some(ViewAction::DeleteAndRedraft(status)) => {
+                self.status_actions.send(
+                    StatusesMessage::DeleteAndRedraft(ComposeDraft::from_status(&status)),
+                    Box::new(|m, _| m),
+                );
+            }
+            Some(ViewAction::ShowHistory(status)) => {
+                self.history_dialog = Some(HistoryDialog::new(
+                    &status,
+                    self.channels.sender::<StatusesService>(),
+                ));
+            }
+            Some(ViewAction::Report(status)) => {
+                self.report_dialog = Some(ReportDialog::new(
+                    status,
+                    self.channels.sender::<ReportsService>(),
+                ));
+            }
+            None => (),
+        }
+
+        // eframe persists the store every few seconds, which is all autosaving needs.
+        if drafts.current != self.compose.draft {
+            drafts.current = self.compose.draft.clone();
+        }
+    }
+
+    /// Carry out what the app was opened to do, e.g. from another app's share sheet.
+    pub fn launch(&mut self, action: LaunchAction, drafts: &mut DraftStore) {
+        match action {
+            LaunchAction::Share { text } => {
+                let draft = ComposeDraft {
+                    text,
+                    ..Default::default()
+                };
+                self.open_draft(draft, drafts.for_account(&self.account_key));
+            }
+            LaunchAction::Open(link) => {
+                self.links
+                    .send(SearchMessage::Resolve(link), Box::new(|m, _| m));
+            }
+            LaunchAction::Go(route) => self.navigate(route),
+        }
+    }
+
+    /// Where the user is, to put in the address bar.
+    pub fn route(&self) -> Route {
+        match self.page {
+            SessionPage::Home => Route::Home,
+            SessionPage::Notifications => Route::Notifications,
+            SessionPage::Compose => Route::Compose,
+            SessionPage::Scheduled => Route::Scheduled,
+            SessionPage::Search => Route::Search,
+            SessionPage::Profile => match self.profiles.last() {
+                Some(profile) => Route::Profile(profile.account.acct.clone()),
+                None => Route::Home,
+            },
+            SessionPage::Status => match &self.status {
+                Some(status) => Route::Status(status.url.clone().unwrap_or(status.uri.clone())),
+                None => Route::Home,
+            },
+            SessionPage::FollowRequests => Route::FollowRequests,
+            SessionPage::Settings => Route::Settings,
+        }
+    }
+
+    /// Whether a link is still being looked up, so the page is about to change.
+    pub fn is_navigating(&self) -> bool {
+        self.links.is_awaiting()
+    }
+
+    /// Go to a page, e.g. from a deep link or the browser's back button.
+    pub fn navigate(&mut self, route: Route) {
+        // Anything not linked to yet is still found on the server.
+        self.links.state = AsyncRequestBridgeState::Init;
+        match route {
+            Route::Home => self.page = SessionPage::Home,
+            Route::Notifications => self.page = SessionPage::Notifications,
+            Route::Compose => self.page = SessionPage::Compose,
+            Route::Scheduled => self.page = SessionPage::Scheduled,
+            Route::Search => self.page = SessionPage::Search,
+            Route::Hashtag(tag) => {
+                self.search.search_for(format!("#{}", tag));
+                self.page = SessionPage::Search;
+            }
+            Route::Profile(acct) => {
+                // Going back to a profile that's still open shouldn't look it up again.
+                match self.profiles.iter().rposition(|p| p.account.acct == acct) {
+                    Some(index) => {
+                        self.profiles.truncate(index + 1);
+                        self.page = SessionPage::Profile;
+                    }
+                    None => self
+                        .links
+                        .send(SearchMessage::ResolveAccount(acct), Box::new(|m, _| m)),
+                }
+            }
+            Route::Status(url) => {
+                let open = self.status.as_ref().map_or(false, |status| {
+                    status.url.as_deref() == Some(url.as_str()) || status.uri == url
+                });
+                if open {
+                    self.page = SessionPage::Status;
+                } else {
+                    self.links
+                        .send(SearchMessage::Resolve(url), Box::new(|m, _| m));
+                }
+            }
+            Route::FollowRequests if self.channels.account.locked => {
+                self.page = SessionPage::FollowRequests;
+            }
+            Route::FollowRequests => self.page = SessionPage::Home,
+            Route::Settings => self.page = SessionPage::Settings,
+        }
+    }
+
+    fn open_profile(&mut self, account: Account) {
+        self.profiles.push(ProfileView::new(
+            account,
+            self.channels.sender::<AccountsService>(),
+        ));
+        self.page = SessionPage::Profile;
+    }
+
+    fn open_status(&mut self, status: Status) {
+        self.status = Some(status);
+        self.page = SessionPage::Status;
+    }
+
+    fn open_resolved(&mut self, link: ResolvedLink) {
+        match link {
+            ResolvedLink::Status(status) => self.open_status(status),
+            ResolvedLink::Account(account) => self.open_profile(account),
+        }
+    }
+
+    fn open_draft(&mut self, draft: ComposeDraft, drafts: &mut AccountDrafts) {
+        self.compose.open(draft, drafts);
+        self.page = SessionPage::Compose;
+    }
+
+    /// Show the saved copy of an edited status wherever the old one was.
+    fn replace_status(&mut self, status: Status) {
+        self.timeline.replace(&status);
+        if self.status.as_ref().map_or(false, |s| s.id == status.id) {
+            self.status = Some(status);
+            self.page = SessionPage::Status;
+        } else {
+            self.page = SessionPage::Home;
+        }
+    }
+
+    fn remove_status(&mut self, id: &StatusId) {
+        self.timeline.remove(id);
+        if self.status.as_ref().map_or(false, |s| &s.id == id) {
+            self.status = None;
+            if self.page == SessionPage::Status {
+                self.page = SessionPage::Home;
+            }
+        }
+    }
+
+    fn update_poll(&mut self, poll: &Poll) {
+        self.timeline.update_poll(poll);
+        if let Some(status) = &mut self.status {
+            replace_poll(status, poll);
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        self.cache_ui(ui);
+        ui.separator();
+
+        ui.heading("Mutes and blocks");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.moderation_tab, 0, "Muted accounts");
+            ui.selectable_value(&mut self.moderation_tab, 1, "Blocked accounts");
+            ui.selectable_value(&mut self.moderation_tab, 2, "Blocked domains");
+        });
+        ui.separator();
+        if let Some(undone) = self.moderation_lists[self.moderation_tab].ui(ui) {
+            self.timeline.apply_moderation(&undone);
+        }
+    }
+
+    fn cache_ui(&mut self, ui: &mut egui::Ui) {
+        if self.cache.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        if let AsyncRequestBridgeState::Init = self.cache.state {
+            self.cache
+                .send(TimelineMessage::CacheSize, Box::new(|m, _| m));
+        }
+
+        ui.heading("Cache");
+        ui.horizontal(|ui| {
+            match &self.cache.state {
+                AsyncRequestBridgeState::Complete(TimelineMessage::CacheSizeIs(bytes)) => {
+                    ui.label(format!(
+                        "Posts, profiles, notifications and images kept offline: {:.1} MB",
+                        *bytes as f64 / (1024.0 * 1024.0)
+                    ));
+                }
+                AsyncRequestBridgeState::Complete(TimelineMessage::Error(e)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                _ => {
+                    ui.spinner();
+                }
+            }
+            if ui
+                .add_enabled(!self.cache.is_awaiting(), egui::Button::new("Clear cache"))
+                .clicked()
+            {
+                self.cache
+                    .send(TimelineMessage::ClearCache, Box::new(|m, _| m));
+            }
+        });
+    }
+
+    /// Says so when the instance is refusing requests, or background fetches are held back.
+    fn rate_limit_ui(&self, ui: &mut egui::Ui) {
+        let Some(status) = self.rate_limit.status().filter(|s| s.is_low()) else {
+            return;
+        };
+        let wait = status.blocked_for.or(status.resets_in);
+        let until = wait
+            .and_then(|wait| time::Duration::try_from(wait).ok())
+            .map(format_duration)
+            .unwrap_or_default();
+        if status.blocked_for.is_some() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("⏱ Rate limited, {}", until),
+            )
+            .on_hover_text("The server asked for a break. Requests will be sent when it's over.");
+        } else {
+            ui.weak(format!("⏱ {} requests left", status.remaining))
+                .on_hover_text(format!(
+                    "Only what you ask for is being loaded until the limit resets in {}.",
+                    until
+                ));
+        }
+        // Keep the countdown moving.
+        if let Some(wait) = wait {
+            ui.ctx()
+                .request_repaint_after(wait.min(std::time::Duration::from_secs(1)));
+        }
+    }
+
+    fn apply_moderation(&mut self, event: &ModerationEvent) {
+        self.timeline.apply_moderation(event);
+        // The settings lists are now stale.
+        for list in self.moderation_lists.iter_mut() {
+            list.reload();
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use instant::Duration;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    moderation::{ModerationEntry, ModerationEvent, ModerationList, ModerationMessage},
+    views::{display_name, ModerationRequest},
+};
+
+const MUTE_DURATIONS: &[(&str, Option<u64>)] = &[
+    ("Indefinitely", None),
+    ("5 minutes", Some(5 * 60)),
+    ("30 minutes", Some(30 * 60)),
+    ("1 hour", Some(60 * 60)),
+    ("6 hours", Some(6 * 60 * 60)),
+    ("1 day", Some(24 * 60 * 60)),
+    ("3 days", Some(3 * 24 * 60 * 60)),
+    ("7 days", Some(7 * 24 * 60 * 60)),
+];
+
+pub enum DialogOutcome {
+    Open,
+    Cancelled,
+    Confirmed(ModerationMessage),
+}
+
+/// Asks for confirmation (and mute options) before applying a moderation action.
+pub struct ModerationDialog {
+    request: ModerationRequest,
+    hide_notifications: bool,
+    duration: usize,
+}
+
+impl ModerationDialog {
+    pub fn new(request: ModerationRequest) -> Self {
+        ModerationDialog {
+            request,
+            hide_notifications: true,
+            duration: 0,
+        }
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context) -> DialogOutcome {
+        let title = match &self.request {
+            ModerationRequest::Mute(account) => format!("Mute @{}", account.acct),
+            ModerationRequest::Block(account) => format!("Block @{}", account.acct),
+            ModerationRequest::BlockDomain(domain) => format!("Block {}", domain),
+        };
+        let mut outcome = DialogOutcome::Open;
+
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                match &self.request {
+                    ModerationRequest::Mute(account) => {
+                        ui.label(format!(
+                            "You won't see posts from {} in your timelines.",
+                            display_name(account)
+                        ));
+                        ui.checkbox(&mut self.hide_notifications, "Hide notifications");
+                        egui::ComboBox::from_label("Duration")
+                            .selected_text(MUTE_DURATIONS[self.duration].0)
+                            .show_ui(ui, |ui| {
+                                for (i, (label, _)) in MUTE_DURATIONS.iter().enumerate() {
+                                    ui.selectable_value(&mut self.duration, i, *label);
+                                }
+                            });
+                    }
+                    ModerationRequest::Block(account) => {
+                        ui.label(format!(
+                            "{} won't be able to follow you or see your posts.",
+                            display_name(account)
+                        ));
+                    }
+                    ModerationRequest::BlockDomain(domain) => {
+                        ui.label(format!(
+                            "You won't see anything from {}, and your followers there will be removed.",
+                            domain
+                        ));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Confirm").clicked() {
+                        outcome = DialogOutcome::Confirmed(self.message());
+                    }
+                    if ui.button("Cancel").clicked() {
+                        outcome = DialogOutcome::Cancelled;
+                    }
+                });
+            });
+        outcome
+    }
+
+    fn message(&self) -> ModerationMessage {
+        match &self.request {
+            ModerationRequest::Mute(account) => ModerationMessage::Mute {
+                account: account.id.clone(),
+                notifications: self.hide_notifications,
+                duration: MUTE_DURATIONS[self.duration].1.map(Duration::from_secs),
+            },
+            ModerationRequest::Block(account) => ModerationMessage::Block(account.id.clone()),
+            ModerationRequest::BlockDomain(domain) => {
+                ModerationMessage::BlockDomain(domain.clone())
+            }
+        }
+    }
+}
+
+pub struct ModerationListState {
+    pub entries: Vec<ModerationEntry>,
+    pub has_more: bool,
+    pub error: Option<String>,
+}
+
+/// A paged list of mutes, blocks or domain blocks, each of which can be undone.
+pub struct ModerationListView {
+    list: ModerationList,
+    pages: AsyncRequestBridge<ModerationMessage, ModerationListState>,
+    actions: AsyncRequestBridge<ModerationMessage, ModerationMessage>,
+    action_error: Option<String>,
+}
+
+impl ModerationListView {
+    pub fn new(list: ModerationList, tx: mpsc::Sender<Message<ModerationMessage>>) -> Self {
+        ModerationListView {
+            list,
+            pages: AsyncRequestBridge::new(tx.clone()),
+            actions: AsyncRequestBridge::new(tx),
+            action_error: None,
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.pages
+            .send(ModerationMessage::LoadList(self.list), Box::new(merge_page));
+    }
+
+    /// Returns an undo which succeeded, so other views can stop hiding content.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<ModerationEvent> {
+        let pages_changed = self.pages.pump_messages();
+        if self.actions.pump_messages() || pages_changed {
+            ui.ctx().request_repaint();
+        }
+
+        let mut undone = None;
+        match self.actions.take_complete() {
+            Some(ModerationMessage::Applied(event)) => {
+                if let Some(state) = self.pages.current_state_mut() {
+                    state.entries.retain(|entry| !is_undone_by(entry, &event));
+                }
+                undone = Some(event);
+            }
+            Some(ModerationMessage::Error(e)) => self.action_error = Some(e),
+            _ => (),
+        }
+
+        if let AsyncRequestBridgeState::Init = self.pages.state {
+            self.reload();
+        }
+        if let AsyncRequestBridgeState::Error(e) = &self.pages.state {
+            ui.label(format!("error: {}", e));
+            if ui.button("Retry").clicked() {
+                self.reload();
+            }
+            return undone;
+        }
+        if let Some(e) = &self.action_error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+
+        let mut pending = None;
+        let mut reached_end = false;
+        let loading = self.pages.is_awaiting();
+
+        egui::ScrollArea::vertical()
+            .id_source(format!("{:?}", self.list))
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                let state = match self.pages.current_state() {
+                    Some(state) => state,
+                    None => {
+                        ui.spinner();
+                        return;
+                    }
+                };
+                if let Some(e) = &state.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                } else if state.entries.is_empty() && !loading {
+                    ui.label(format!("No {}.", self.list));
+                }
+                for entry in &state.entries {
+                    ui.horizontal(|ui| {
+                        let (label, undo, msg) = match entry {
+                            ModerationEntry::Account(account) => (
+                                format!("{} @{}", display_name(account), account.acct),
+                                if self.list == ModerationList::Mutes {
+                                    "Unmute"
+                                } else {
+                                    "Unblock"
+                                },
+                                if self.list == ModerationList::Mutes {
+                                    ModerationMessage::Unmute(account.id.clone())
+                                } else {
+                                    ModerationMessage::Unblock(account.id.clone())
+                                },
+                            ),
+                            ModerationEntry::Domain(domain) => (
+                                domain.clone(),
+                                "Unblock",
+                                ModerationMessage::UnblockDomain(domain.clone()),
+                            ),
+                        };
+                        ui.label(label);
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button(undo).clicked() {
+                                pending = Some(msg);
+                            }
+                        });
+                    });
+                    ui.separator();
+                }
+                if loading {
+                    ui.spinner();
+                } else if state.has_more {
+                    let end = ui.label("…");
+                    reached_end = ui.is_rect_visible(end.rect);
+                }
+            });
+
+        if let Some(msg) = pending {
+            self.action_error = None;
+            self.actions.send(msg, Box::new(|m, _| m));
+        }
+        if reached_end {
+            self.pages
+                .send(ModerationMessage::NextPage(self.list), Box::new(merge_page));
+        }
+        undone
+    }
+}
+
+fn is_undone_by(entry: &ModerationEntry, event: &ModerationEvent) -> bool {
+    match (entry, event) {
+        (ModerationEntry::Account(account), ModerationEvent::Unmuted(id))
+        | (ModerationEntry::Account(account), ModerationEvent::Unblocked(id)) => &account.id == id,
+        (ModerationEntry::Domain(domain), ModerationEvent::DomainUnblocked(d)) => domain == d,
+        _ => false,
+    }
+}
+
+fn merge_page(
+    m: ModerationMessage,
+    prev_state: Option<ModerationListState>,
+) -> ModerationListState {
+    match (m, prev_state) {
+        (
+            ModerationMessage::ListPage {
+                entries,
+                has_more,
+                replace: false,
+                ..
+            },
+            Some(mut prev),
+        ) => {
+            prev.entries.extend(entries);
+            prev.has_more = has_more;
+            prev.error = None;
+            prev
+        }
+        (
+            ModerationMessage::ListPage {
+                entries, has_more, ..
+            },
+            _,
+        ) => ModerationListState {
+            entries,
+            has_more,
+            error: None,
+        },
+        // Keep what was already loaded, but stop asking for more.
+        (ModerationMessage::Error(e), Some(mut prev)) => {
+            prev.has_more = false;
+            prev.error = Some(e);
+            prev
+        }
+        (ModerationMessage::Error(e), None) => ModerationListState {
+            entries: vec![],
+            has_more: false,
+            error: Some(e),
+        },
+        _ => panic!("can't handle this response."),
+    }
+}
@@ -0,0 +1,202 @@
+use mastodon_async::{entities::notification::NotificationType, prelude::*};
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    timeline::TimelineMessage,
+    views::{
+        display_name,
+        status::{avatar, status_ui},
+        ViewAction,
+    },
+};
+
+pub struct NotificationsState {
+    pub notifications: Vec<Notification>,
+    pub has_more: bool,
+    pub error: Option<String>,
+    /// Loaded from the cache, and not yet refreshed from the server.
+    pub cached: bool,
+}
+
+/// Mentions, boosts, favourites and follows, shown from the cache until the server answers.
+pub struct NotificationsView {
+    bridge: AsyncRequestBridge<TimelineMessage, NotificationsState>,
+}
+
+impl NotificationsView {
+    pub fn new(tx: mpsc::Sender<Message<TimelineMessage>>) -> Self {
+        NotificationsView {
+            bridge: AsyncRequestBridge::new(tx),
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.bridge.send(
+            TimelineMessage::LoadNotifications,
+            Box::new(merge_notifications),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, me: &Account) -> Option<ViewAction> {
+        if self.bridge.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        if let AsyncRequestBridgeState::Init = self.bridge.state {
+            self.bridge.send(
+                TimelineMessage::LoadCachedNotifications,
+                Box::new(merge_notifications),
+            );
+        }
+        // Show the cache straight away, then replace it with what's on the server.
+        if !self.bridge.is_awaiting() && self.bridge.current_state().map_or(false, |s| s.cached) {
+            self.reload();
+        }
+        if let AsyncRequestBridgeState::Error(e) = &self.bridge.state {
+            ui.label(format!("error: {}", e));
+            if ui.button("Retry").clicked() {
+                self.reload();
+            }
+            return None;
+        }
+
+        let mut action = None;
+        let mut refresh = false;
+        let mut reached_end = false;
+        let loading = self.bridge.is_awaiting();
+
+        ui.horizontal(|ui| {
+            ui.heading("Notifications");
+            refresh = ui.add_enabled(!loading, egui::Button::new("⟳")).clicked();
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("notifications")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                let state = match self.bridge.current_state() {
+                    Some(state) => state,
+                    None => {
+                        ui.spinner();
+                        return;
+                    }
+                };
+                if state.notifications.is_empty() && !loading {
+                    ui.weak("Nothing yet.");
+                }
+                for notification in &state.notifications {
+                    if let Some(a) = notification_ui(ui, notification, me) {
+                        action = Some(a);
+                    }
+                    ui.separator();
+                }
+                if let Some(e) = &state.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                if loading {
+                    ui.spinner();
+                } else if state.has_more {
+                    // Load older notifications as soon as the end of the list scrolls into view.
+                    let end = ui.label("…");
+                    reached_end = ui.is_rect_visible(end.rect);
+                }
+            });
+
+        if refresh {
+            self.reload();
+        } else if reached_end {
+            self.bridge.send(
+                TimelineMessage::LoadOlderNotifications,
+                Box::new(merge_notifications),
+            );
+        }
+        action
+    }
+}
+
+fn notification_ui(
+    ui: &mut egui::Ui,
+    notification: &Notification,
+    me: &Account,
+) -> Option<ViewAction> {
+    let mut action = None;
+    let (icon, what) = match notification.notification_type {
+        NotificationType::Mention => ("💬", "mentioned you"),
+        NotificationType::Reblog => ("🔁", "boosted your post"),
+        NotificationType::Favourite => ("⭐", "favourited your post"),
+        NotificationType::Follow => ("👤", "followed you"),
+        _ => ("🔔", "sent a notification"),
+    };
+    ui.horizontal(|ui| {
+        ui.weak(icon);
+        ui.add(avatar(&notification.account));
+        if ui.link(display_name(&notification.account)).clicked() {
+            action = Some(ViewAction::OpenProfile(notification.account.clone()));
+        }
+        ui.weak(what);
+    });
+    if let Some(status) = &notification.status {
+        ui.indent(("notification", notification.id.to_string()), |ui| {
+            if let Some(a) = status_ui(ui, status, me) {
+                action = Some(a);
+            }
+        });
+    }
+    action
+}
+
+fn merge_notifications(
+    m: TimelineMessage,
+    prev_state: Option<NotificationsState>,
+) -> NotificationsState {
+    match (m, prev_state) {
+        (TimelineMessage::CachedNotifications(notifications), _) => NotificationsState {
+            notifications,
+            has_more: false,
+            error: None,
+            cached: true,
+        },
+        (
+            TimelineMessage::Notifications {
+                notifications,
+                has_more,
+                replace: false,
+            },
+            Some(mut prev),
+        ) => {
+            prev.notifications.extend(notifications);
+            prev.has_more = has_more;
+            prev.error = None;
+            prev.cached = false;
+            prev
+        }
+        (
+            TimelineMessage::Notifications {
+                notifications,
+                has_more,
+                ..
+            },
+            _,
+        ) => NotificationsState {
+            notifications,
+            has_more,
+            error: None,
+            cached: false,
+        },
+        // Keep what was already loaded, but stop asking for more. Cached notifications stay up
+        // while offline rather than being retried every frame.
+        (TimelineMessage::Error(e), Some(mut prev)) => {
+            prev.has_more = false;
+            prev.error = Some(e);
+            prev.cached = false;
+            prev
+        }
+        (TimelineMessage::Error(e), None) => NotificationsState {
+            notifications: vec![],
+            has_more: false,
+            error: Some(e),
+            cached: false,
+        },
+        _ => panic!("can't handle this response."),
+    }
+}
@@ -0,0 +1,92 @@
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, Message},
+    outbox::{OutboxAction, OutboxItem, OutboxMessage},
+};
+
+/// Keeps track of the outbox service, and shows what it's still trying to send.
+pub struct OutboxIndicator {
+    /// Sends actions on to the outbox, keeping them while its channel is full.
+    actions: AsyncRequestBridge<OutboxMessage, ()>,
+    watch: AsyncRequestBridge<OutboxMessage, OutboxMessage>,
+    version: u64,
+    items: Vec<OutboxItem>,
+}
+
+impl OutboxIndicator {
+    pub fn new(tx: mpsc::Sender<Message<OutboxMessage>>) -> Self {
+        OutboxIndicator {
+            watch: AsyncRequestBridge::new(tx.clone()),
+            actions: AsyncRequestBridge::new(tx),
+            version: 0,
+            items: vec![],
+        }
+    }
+
+    /// Hand back items that were still pending when the app last closed.
+    pub fn restore(&mut self, items: Vec<OutboxItem>) {
+        if !items.is_empty() {
+            self.actions.notify(OutboxMessage::Restore(items));
+        }
+    }
+
+    pub fn enqueue(&mut self, action: OutboxAction) {
+        self.actions.notify(OutboxMessage::Enqueue(action));
+    }
+
+    /// `saved` is kept up to date with the pending items, for eframe to persist.
+    pub fn ui(&mut self, ui: &mut egui::Ui, saved: &mut Vec<OutboxItem>) {
+        // Passes on anything that didn't fit in the channel last time.
+        self.actions.pump_messages();
+        if self.watch.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        if let Some(OutboxMessage::Changed { version, items }) = self.watch.take_complete() {
+            self.version = version;
+            saved.clone_from(&items);
+            self.items = items;
+        }
+        if !self.watch.is_awaiting() {
+            self.watch
+                .send(OutboxMessage::Watch(self.version), Box::new(|m, _| m));
+        }
+
+        if self.items.is_empty() {
+            return;
+        }
+        let failed = self.items.iter().filter(|item| item.failed).count();
+        let label = if failed > 0 {
+            format!("⚠ {} failed", failed)
+        } else {
+            format!("⏳ {} pending", self.items.len())
+        };
+        let mut msg = None;
+        ui.menu_button(label, |ui| {
+            for item in &self.items {
+                ui.horizontal(|ui| {
+                    ui.label(item.action.describe());
+                    if item.failed {
+                        ui.colored_label(ui.visuals().error_fg_color, "failed");
+                    } else if item.attempts > 0 {
+                        ui.weak(format!("retrying, {} attempts", item.attempts));
+                    }
+                    if ui.small_button("✖").on_hover_text("Discard").clicked() {
+                        msg = Some(OutboxMessage::Discard(item.id));
+                    }
+                });
+                if let Some(e) = &item.last_error {
+                    ui.weak(e);
+                }
+            }
+            ui.separator();
+            if ui.button("Retry now").clicked() {
+                msg = Some(OutboxMessage::RetryAll);
+                ui.close_menu();
+            }
+        });
+        if let Some(msg) = msg {
+            self.actions.notify(msg);
+        }
+    }
+}
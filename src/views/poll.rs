@@ -0,0 +1,115 @@
+use mastodon_async::prelude::*;
+
+use crate::{
+    datetime::{format_duration, remaining_until},
+    views::ViewAction,
+};
+
+/// Draw a poll, with voting controls if the user can still vote in it.
+pub fn poll_ui(ui: &mut egui::Ui, poll: &Poll, own_status: bool) -> Option<ViewAction> {
+    let mut action = None;
+    let voted = poll.voted.unwrap_or(false);
+    let show_results = poll.expired || voted || own_status;
+    let own_votes = poll.own_votes.clone().unwrap_or_default();
+
+    // Choices stay in egui's memory between frames until the vote is sent.
+    let selection_id = egui::Id::new(("poll", poll.id.to_string()));
+    let mut selected: Vec<usize> = ui.data_mut(|d| d.get_temp(selection_id).unwrap_or_default());
+
+    // Multiple choice percentages are of people, not votes, so they can add up to more than 100%.
+    let total = if poll.multiple {
+        poll.voters_count.unwrap_or(poll.votes_count)
+    } else {
+        poll.votes_count
+    };
+
+    for (i, option) in poll.options.iter().enumerate() {
+        if show_results {
+            let own = own_votes.iter().any(|v| *v as usize == i);
+            let title = if own {
+                format!("✔ {}", option.title)
+            } else {
+                option.title.clone()
+            };
+            match option.votes_count {
+                Some(votes) => {
+                    let fraction = if total == 0 {
+                        0.0
+                    } else {
+                        votes as f32 / total as f32
+                    };
+                    ui.add(egui::ProgressBar::new(fraction).text(format!(
+                        "{} — {:.0}%",
+                        title,
+                        fraction * 100.0
+                    )));
+                }
+                // The poll's author asked for totals to be hidden until it ends.
+                None => {
+                    ui.label(title);
+                }
+            }
+        } else if poll.multiple {
+            let mut checked = selected.contains(&i);
+            if ui.checkbox(&mut checked, &option.title).changed() {
+                if checked {
+                    selected.push(i);
+                } else {
+                    selected.retain(|s| *s != i);
+                }
+            }
+        } else if ui
+            .radio(selected.first() == Some(&i), &option.title)
+            .clicked()
+        {
+            selected = vec![i];
+        }
+    }
+
+    ui.horizontal(|ui| {
+        let people = poll.voters_count.unwrap_or(poll.votes_count);
+        ui.weak(format!(
+            "{} {}",
+            people,
+            if people == 1 { "person" } else { "people" }
+        ));
+        match poll.expires_at.and_then(remaining_until) {
+            _ if poll.expired => {
+                ui.weak("· closed");
+            }
+            Some(remaining) => {
+                ui.weak(format!("· {} left", format_duration(remaining)));
+                // Keep the countdown ticking.
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_secs(60));
+            }
+            None if poll.expires_at.is_some() => {
+                ui.weak("· closed");
+            }
+            None => (),
+        }
+
+        if !show_results
+            && ui
+                .add_enabled(!selected.is_empty(), egui::Button::new("Vote"))
+                .clicked()
+        {
+            let mut choices = selected.clone();
+            choices.sort_unstable();
+            action = Some(ViewAction::Vote {
+                poll: poll.id.clone(),
+                choices,
+            });
+        }
+        if ui
+            .small_button("⟳")
+            .on_hover_text("Refresh results")
+            .clicked()
+        {
+            action = Some(ViewAction::RefreshPoll(poll.id.clone()));
+        }
+    });
+
+    ui.data_mut(|d| d.insert_temp(selection_id, selected));
+    action
+}
@@ -0,0 +1,86 @@
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    accounts::{AccountList, AccountsMessage},
+    channels::Message,
+    html::to_plain_text,
+    views::{
+        accounts::AccountListView,
+        display_name,
+        status::{account_menu_ui, avatar},
+        ViewAction,
+    },
+};
+
+#[derive(PartialEq, Eq)]
+enum ProfileTab {
+    About,
+    Followers,
+    Following,
+}
+
+pub struct ProfileView {
+    pub account: Account,
+    tab: ProfileTab,
+    followers: AccountListView,
+    following: AccountListView,
+}
+
+impl ProfileView {
+    pub fn new(account: Account, tx: mpsc::Sender<Message<AccountsMessage>>) -> Self {
+        let id = account.id.to_string();
+        ProfileView {
+            account,
+            tab: ProfileTab::About,
+            followers: AccountListView::new(AccountList::Followers(id.clone()), tx.clone()),
+            following: AccountListView::new(AccountList::Following(id), tx),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, me: &Account) -> Option<ViewAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.add(avatar(&self.account).fit_to_exact_size(egui::vec2(48.0, 48.0)));
+            ui.heading(display_name(&self.account));
+            ui.weak(format!("@{}", self.account.acct));
+            if self.account.locked {
+                ui.small("🔒");
+            }
+            if self.account.id != me.id {
+                ui.menu_button("⋯", |ui| {
+                    if let Some(a) = account_menu_ui(ui, &self.account) {
+                        action = Some(a);
+                        ui.close_menu();
+                    }
+                });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tab, ProfileTab::About, "About");
+            ui.selectable_value(
+                &mut self.tab,
+                ProfileTab::Followers,
+                format!("{} followers", self.account.followers_count),
+            );
+            ui.selectable_value(
+                &mut self.tab,
+                ProfileTab::Following,
+                format!("{} following", self.account.following_count),
+            );
+        });
+        ui.separator();
+
+        let list_action = match self.tab {
+            ProfileTab::About => {
+                ui.label(format!("{} posts", self.account.statuses_count));
+                ui.label(to_plain_text(&self.account.note));
+                None
+            }
+            ProfileTab::Followers => self.followers.ui(ui, me),
+            ProfileTab::Following => self.following.ui(ui, me),
+        };
+        list_action.or(action)
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    html::to_plain_text,
+    moderation::account_domain,
+    reports::{ReportCategory, ReportDraft, ReportsMessage, Rule},
+};
+
+/// Lets the user report a status (and optionally more by the same account) to moderators.
+pub struct ReportDialog {
+    status: Status,
+    category: ReportCategory,
+    selected_rules: HashSet<String>,
+    selected_statuses: HashSet<String>,
+    comment: String,
+    forward: bool,
+    rules: AsyncRequestBridge<ReportsMessage, Result<Vec<Rule>, String>>,
+    statuses: AsyncRequestBridge<ReportsMessage, Result<Vec<Status>, String>>,
+    submit: AsyncRequestBridge<ReportsMessage, ReportsMessage>,
+    error: Option<String>,
+}
+
+pub enum ReportOutcome {
+    Open,
+    Closed,
+    Submitted,
+}
+
+impl ReportDialog {
+    pub fn new(status: Status, tx: mpsc::Sender<Message<ReportsMessage>>) -> Self {
+        let mut selected_statuses = HashSet::new();
+        selected_statuses.insert(status.id.to_string());
+        let mut dialog = ReportDialog {
+            status,
+            category: ReportCategory::Other,
+            selected_rules: HashSet::new(),
+            selected_statuses,
+            comment: String::new(),
+            forward: false,
+            rules: AsyncRequestBridge::new(tx.clone()),
+            statuses: AsyncRequestBridge::new(tx.clone()),
+            submit: AsyncRequestBridge::new(tx),
+            error: None,
+        };
+        dialog.load_rules();
+        dialog
+    }
+
+    fn load_rules(&mut self) {
+        self.rules.send(
+            ReportsMessage::LoadRules,
+            Box::new(|m, _| match m {
+                ReportsMessage::Rules(rules) => Ok(rules),
+                ReportsMessage::Error(e) => Err(e),
+                other => Err(format!("Unexpected response: {:?}", other)),
+            }),
+        );
+    }
+
+    fn load_statuses(&mut self) {
+        let account = self.status.account.id.clone();
+        self.statuses.send(
+            ReportsMessage::LoadAccountStatuses(account),
+            Box::new(|m, _| match m {
+                ReportsMessage::AccountStatuses(statuses) => Ok(statuses),
+                ReportsMessage::Error(e) => Err(e),
+                other => Err(format!("Unexpected response: {:?}", other)),
+            }),
+        );
+    }
+
+    fn draft(&self) -> ReportDraft {
+        ReportDraft {
+            account: self.status.account.id.clone(),
+            statuses: self
+                .selected_statuses
+                .iter()
+                .map(|id| StatusId::new(id.clone()))
+                .collect(),
+            category: self.category,
+            rules: self.selected_rules.iter().cloned().collect(),
+            comment: self.comment.clone(),
+            forward: self.forward,
+        }
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context) -> ReportOutcome {
+        let rules_changed = self.rules.pump_messages();
+        let statuses_changed = self.statuses.pump_messages();
+        if self.submit.pump_messages() || rules_changed || statuses_changed {
+            ctx.request_repaint();
+        }
+        match self.submit.take_complete() {
+            Some(ReportsMessage::Submitted) => return ReportOutcome::Submitted,
+            Some(ReportsMessage::Error(e)) => self.error = Some(e),
+            _ => (),
+        }
+
+        let mut outcome = ReportOutcome::Open;
+        let mut open = true;
+        egui::Window::new(format!("Report @{}", self.status.account.acct))
+            .open(&mut open)
+            .collapsible(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.label("Why are you reporting this?");
+                for category in ReportCategory::ALL {
+                    ui.radio_value(&mut self.category, category, category.to_string());
+                }
+
+                if self.category == ReportCategory::Violation {
+                    ui.separator();
+                    ui.label("Which rules are being broken?");
+                    let mut retry = false;
+                    match &self.rules.state {
+                        AsyncRequestBridgeState::Complete(Ok(rules)) if rules.is_empty() => {
+                            ui.weak("This server hasn't published any rules.");
+                        }
+                        AsyncRequestBridgeState::Complete(Ok(rules)) => {
+                            for rule in rules {
+                                let mut checked = self.selected_rules.contains(&rule.id);
+                                if ui.checkbox(&mut checked, &rule.text).changed() {
+                                    toggle(&mut self.selected_rules, &rule.id, checked);
+                                }
+                            }
+                        }
+                        AsyncRequestBridgeState::Complete(Err(e))
+                        | AsyncRequestBridgeState::Error(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e);
+                            retry = ui.button("Retry").clicked();
+                        }
+                        _ => {
+                            ui.spinner();
+                        }
+                    }
+                    if retry {
+                        self.load_rules();
+                    }
+                }
+
+                ui.separator();
+                self.statuses_ui(ui);
+
+                ui.separator();
+                ui.label("Anything else moderators should know?");
+                ui.text_edit_multiline(&mut self.comment);
+                if let Some(domain) = account_domain(&self.status.account) {
+                    ui.checkbox(
+                        &mut self.forward,
+                        format!(
+                            "Also send an anonymous copy to the moderators of {}",
+                            domain
+                        ),
+                    );
+                }
+
+                if let Some(e) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                ui.horizontal(|ui| {
+                    let sending = self.submit.is_awaiting();
+                    // A report of a rule violation has to say which rules.
+                    let ready = self.category != ReportCategory::Violation
+                        || !self.selected_rules.is_empty();
+                    let mut submit =
+                        ui.add_enabled(!sending && ready, egui::Button::new("Submit report"));
+                    if !ready {
+                        submit = submit.on_disabled_hover_text("Pick the rules being broken");
+                    }
+                    if submit.clicked() {
+                        self.error = None;
+                        let draft = self.draft();
+                        self.submit
+                            .send(ReportsMessage::Submit(draft), Box::new(|m, _| m));
+                    }
+                    if sending {
+                        ui.spinner();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        outcome = ReportOutcome::Closed;
+                    }
+                });
+            });
+
+        if !open {
+            outcome = ReportOutcome::Closed;
+        }
+        outcome
+    }
+
+    fn statuses_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Posts to include");
+        let reported = self.status.id.to_string();
+        ui.add_enabled_ui(false, |ui| {
+            ui.checkbox(&mut true, to_plain_text(&self.status.content));
+        });
+
+        let mut load = false;
+        match &self.statuses.state {
+            AsyncRequestBridgeState::Init => {
+                load = ui.button("Attach more posts…").clicked();
+            }
+            AsyncRequestBridgeState::Complete(Ok(statuses)) => {
+                for status in statuses.iter().filter(|s| s.id.to_string() != reported) {
+                    let id = status.id.to_string();
+                    let mut checked = self.selected_statuses.contains(&id);
+                    if ui
+                        .checkbox(&mut checked, to_plain_text(&status.content))
+                        .changed()
+                    {
+                        toggle(&mut self.selected_statuses, &id, checked);
+                    }
+                }
+            }
+            AsyncRequestBridgeState::Complete(Err(e)) | AsyncRequestBridgeState::Error(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+                load = ui.button("Retry").clicked();
+            }
+            _ => {
+                ui.spinner();
+            }
+        }
+        if load {
+            self.load_statuses();
+        }
+    }
+}
+
+fn toggle(set: &mut HashSet<String>, id: &str, checked: bool) {
+    if checked {
+        set.insert(id.to_string());
+    } else {
+        set.remove(id);
+    }
+}
@@ -0,0 +1,227 @@
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    datetime::format_local,
+    statuses::{ComposeDraft, ScheduledStatus, StatusesMessage},
+    views::compose::parse_schedule,
+};
+
+pub struct ScheduledState {
+    pub statuses: Vec<ScheduledStatus>,
+    pub error: Option<String>,
+}
+
+/// Posts waiting to be published, which can be moved, edited or cancelled.
+pub struct ScheduledView {
+    list: AsyncRequestBridge<StatusesMessage, ScheduledState>,
+    actions: AsyncRequestBridge<StatusesMessage, StatusesMessage>,
+    /// The id of the post being moved, and its new time as typed.
+    rescheduling: Option<(String, String)>,
+    action_error: Option<String>,
+}
+
+impl ScheduledView {
+    pub fn new(tx: mpsc::Sender<Message<StatusesMessage>>) -> Self {
+        ScheduledView {
+            list: AsyncRequestBridge::new(tx.clone()),
+            actions: AsyncRequestBridge::new(tx),
+            rescheduling: None,
+            action_error: None,
+        }
+    }
+
+    pub fn reload(&mut self) {
+        self.list.send(
+            StatusesMessage::LoadScheduled,
+            Box::new(|m, _| match m {
+                StatusesMessage::ScheduledList(mut statuses) => {
+                    statuses.sort_by_key(|s| s.scheduled_at);
+                    ScheduledState {
+                        statuses,
+                        error: None,
+                    }
+                }
+                StatusesMessage::Error(e) => ScheduledState {
+                    statuses: vec![],
+                    error: Some(e),
+                },
+                _ => panic!("can't handle this response."),
+            }),
+        );
+    }
+
+    fn send_action(&mut self, msg: StatusesMessage) {
+        self.action_error = None;
+        self.actions.send(msg, Box::new(|m, _| m));
+    }
+
+    fn apply_action_result(&mut self) {
+        let result = match self.actions.take_complete() {
+            Some(result) => result,
+            None => return,
+        };
+        let state = self.list.current_state_mut();
+        match (result, state) {
+            (
+                StatusesMessage::Scheduled {
+                    scheduled: updated, ..
+                },
+                Some(state),
+            ) => {
+                state.statuses.retain(|s| s.id != updated.id);
+                state.statuses.push(updated);
+                state.statuses.sort_by_key(|s| s.scheduled_at);
+            }
+            (StatusesMessage::ScheduledCancelled(id), Some(state)) => {
+                state.statuses.retain(|s| s.id != id);
+            }
+            (StatusesMessage::Error(e), _) => self.action_error = Some(e),
+            _ => (),
+        }
+    }
+
+    /// Returns a draft when the user wants to edit one of the posts.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<ComposeDraft> {
+        let list_changed = self.list.pump_messages();
+        if self.actions.pump_messages() || list_changed {
+            ui.ctx().request_repaint();
+        }
+        self.apply_action_result();
+
+        if let AsyncRequestBridgeState::Init = self.list.state {
+            self.reload();
+        }
+
+        let loading = self.list.is_awaiting();
+        let mut refresh = false;
+        ui.horizontal(|ui| {
+            ui.heading("Scheduled posts");
+            refresh = ui.add_enabled(!loading, egui::Button::new("⟳")).clicked();
+            if self.actions.is_awaiting() {
+                ui.spinner();
+            }
+        });
+        if let Some(e) = &self.action_error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+
+        let mut edit = None;
+        let mut pending = None;
+        egui::ScrollArea::vertical()
+            .id_source("scheduled posts")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                let state = match self.list.current_state() {
+                    Some(state) => state,
+                    None => {
+                        ui.spinner();
+                        return;
+                    }
+                };
+                if let Some(e) = &state.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                } else if state.statuses.is_empty() {
+                    ui.label("Nothing is scheduled.");
+                }
+                for scheduled in &state.statuses {
+                    match scheduled_row_ui(ui, scheduled, &mut self.rescheduling) {
+                        Some(RowAction::Edit) => edit = Some(scheduled.draft()),
+                        Some(RowAction::Send(msg)) => pending = Some(msg),
+                        None => (),
+                    }
+                    ui.separator();
+                }
+            });
+
+        if let Some(msg) = pending {
+            self.send_action(msg);
+        }
+        if refresh {
+            self.reload();
+        }
+        edit
+    }
+}
+
+enum RowAction {
+    Edit,
+    Send(StatusesMessage),
+}
+
+fn scheduled_row_ui(
+    ui: &mut egui::Ui,
+    scheduled: &ScheduledStatus,
+    rescheduling: &mut Option<(String, String)>,
+) -> Option<RowAction> {
+    let mut action = None;
+    let params = &scheduled.params;
+
+    ui.strong(format_local(scheduled.scheduled_at));
+    if let Some(cw) = params.spoiler_text.as_ref().filter(|cw| !cw.is_empty()) {
+        ui.label(egui::RichText::new(format!("CW: {}", cw)).italics());
+    }
+    ui.label(params.text.as_deref().unwrap_or_default());
+    if let Some(poll) = &params.poll {
+        ui.weak(format!("Poll: {}", poll.options.join(" / ")));
+    }
+    if let Some(media) = params.media_ids.as_ref().filter(|m| !m.is_empty()) {
+        ui.weak(format!("{} attached", media.len()));
+    }
+
+    match rescheduling {
+        Some((id, text)) if *id == scheduled.id => {
+            let parsed = parse_schedule(text);
+            if let Err(e) = &parsed {
+                ui.colored_label(ui.visuals().warn_fg_color, e);
+            }
+            let mut done = false;
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(text)
+                        .hint_text("YYYY-MM-DD HH:MM")
+                        .desired_width(140.0),
+                );
+                if ui
+                    .add_enabled(parsed.is_ok(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    if let Ok(at) = parsed {
+                        action = Some(RowAction::Send(StatusesMessage::Reschedule {
+                            id: scheduled.id.clone(),
+                            at,
+                        }));
+                    }
+                    done = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    done = true;
+                }
+            });
+            if done {
+                *rescheduling = None;
+            }
+        }
+        _ => {
+            ui.horizontal(|ui| {
+                if ui.button("Reschedule").clicked() {
+                    *rescheduling =
+                        Some((scheduled.id.clone(), format_local(scheduled.scheduled_at)));
+                }
+                if ui.button("Edit").clicked() {
+                    action = Some(RowAction::Edit);
+                }
+                // Like deleting a post, cancelling takes a second click.
+                ui.menu_button("Cancel post", |ui| {
+                    if ui.button("Don't publish this post").clicked() {
+                        action = Some(RowAction::Send(StatusesMessage::CancelScheduled(
+                            scheduled.id.clone(),
+                        )));
+                        ui.close_menu();
+                    }
+                });
+            });
+        }
+    }
+    action
+}
@@ -0,0 +1,158 @@
+use instant::{Duration, Instant};
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    search::{SearchMessage, SearchResults},
+    views::{display_name, status::status_ui, ViewAction},
+};
+
+/// How long typing has to pause for before a search is sent.
+const DEBOUNCE: Duration = Duration::from_millis(400);
+
+pub struct SearchState {
+    pub query: String,
+    pub results: SearchResults,
+    pub error: Option<String>,
+}
+
+pub struct SearchView {
+    query: String,
+    edited_at: Option<Instant>,
+    bridge: AsyncRequestBridge<SearchMessage, SearchState>,
+}
+
+impl SearchView {
+    pub fn new(tx: mpsc::Sender<Message<SearchMessage>>) -> Self {
+        SearchView {
+            query: String::new(),
+            edited_at: None,
+            bridge: AsyncRequestBridge::new(tx),
+        }
+    }
+
+    /// Search straight away, as if `query` had been typed in.
+    pub fn search_for(&mut self, query: String) {
+        self.query = query;
+        self.search();
+    }
+
+    fn search(&mut self) {
+        self.edited_at = None;
+        let query = self.query.trim().to_string();
+        if query.is_empty() {
+            self.bridge.state = AsyncRequestBridgeState::Init;
+            return;
+        }
+        // Sending replaces any search still in flight, so its results are never shown.
+        self.bridge.send(
+            SearchMessage::Search(query),
+            Box::new(|m, _| match m {
+                SearchMessage::Results { query, results } => SearchState {
+                    query,
+                    results,
+                    error: None,
+                },
+                SearchMessage::Error(e) => SearchState {
+                    query: String::new(),
+                    results: Default::default(),
+                    error: Some(e),
+                },
+                _ => panic!("can't handle this response."),
+            }),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, me: &Account) -> Option<ViewAction> {
+        if self.bridge.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            let edit = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("Search, or paste a link to a post or profile"),
+            );
+            if edit.changed() {
+                self.edited_at = Some(Instant::now());
+            }
+            if edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                self.search();
+            }
+            if self.bridge.is_awaiting() {
+                ui.spinner();
+            }
+        });
+
+        if let Some(edited_at) = self.edited_at {
+            let idle = edited_at.elapsed();
+            if idle >= DEBOUNCE {
+                self.search();
+            } else {
+                ui.ctx().request_repaint_after(DEBOUNCE - idle);
+            }
+        }
+
+        let mut action = None;
+        let state = match self.bridge.current_state() {
+            Some(state) => state,
+            None => return None,
+        };
+
+        egui::ScrollArea::vertical()
+            .id_source("search results")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                if let Some(e) = &state.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                    return;
+                }
+                let results = &state.results;
+                if results.accounts.is_empty()
+                    && results.hashtags.is_empty()
+                    && results.statuses.is_empty()
+                {
+                    ui.label(format!("Nothing found for \"{}\".", state.query));
+                    return;
+                }
+
+                if !results.accounts.is_empty() {
+                    ui.heading("Accounts");
+                    for account in &results.accounts {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .link(egui::RichText::new(display_name(account)).strong())
+                                .clicked()
+                            {
+                                action = Some(ViewAction::OpenProfile(account.clone()));
+                            }
+                            ui.weak(format!("@{}", account.acct));
+                        });
+                    }
+                    ui.separator();
+                }
+
+                if !results.hashtags.is_empty() {
+                    ui.heading("Hashtags");
+                    ui.horizontal_wrapped(|ui| {
+                        for tag in &results.hashtags {
+                            ui.hyperlink_to(format!("#{}", tag.name), &tag.url);
+                        }
+                    });
+                    ui.separator();
+                }
+
+                if !results.statuses.is_empty() {
+                    ui.heading("Posts");
+                    for status in &results.statuses {
+                        if let Some(a) = status_ui(ui, status, me) {
+                            action = Some(a);
+                        }
+                        ui.separator();
+                    }
+                }
+            });
+        action
+    }
+}
@@ -0,0 +1,284 @@
+use mastodon_async::prelude::*;
+
+use crate::{
+    html::{parse_content, ContentSpan},
+    links::is_likely_fediverse_url,
+    moderation::account_domain,
+    outbox::OutboxAction,
+    views::{display_name, poll::poll_ui, ModerationRequest, ViewAction},
+};
+
+/// Draw a single status. `me` is the signed in account, which doesn't get moderation actions.
+pub fn status_ui(ui: &mut egui::Ui, status: &Status, me: &Account) -> Option<ViewAction> {
+    let mut action = None;
+
+    if let Some(reblog) = &status.reblog {
+        ui.horizontal(|ui| {
+            ui.weak("🔁");
+            if ui.link(display_name(&status.account)).clicked() {
+                action = Some(ViewAction::OpenProfile(status.account.clone()));
+            }
+            ui.weak("boosted");
+        });
+        return status_body_ui(ui, reblog, me).or(action);
+    }
+
+    status_body_ui(ui, status, me)
+}
+
+fn status_body_ui(ui: &mut egui::Ui, status: &Status, me: &Account) -> Option<ViewAction> {
+    let mut action = None;
+
+    ui.horizontal(|ui| {
+        ui.add(avatar(&status.account));
+        if ui
+            .link(egui::RichText::new(display_name(&status.account)).strong())
+            .clicked()
+        {
+            action = Some(ViewAction::OpenProfile(status.account.clone()));
+        }
+        ui.weak(format!("@{}", status.account.acct));
+        if status.edited_at.is_some()
+            && ui
+                .link(egui::RichText::new("edited").small().weak())
+                .on_hover_text("Show edit history")
+                .clicked()
+        {
+            action = Some(ViewAction::ShowHistory(status.clone()));
+        }
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.menu_button("⋯", |ui| {
+                if let Some(a) = status_menu_ui(ui, status, me) {
+                    action = Some(a);
+                    ui.close_menu();
+                }
+            });
+        });
+    });
+
+    let content_action = if status.spoiler_text.is_empty() {
+        content_ui(ui, &status.content)
+    } else {
+        egui::CollapsingHeader::new(&status.spoiler_text)
+            .id_source(("cw", status.id.to_string()))
+            .show(ui, |ui| content_ui(ui, &status.content))
+            .body_returned
+            .flatten()
+    };
+    if content_action.is_some() {
+        action = content_action;
+    }
+
+    if !status.media_attachments.is_empty() {
+        if status.sensitive {
+            egui::CollapsingHeader::new("Sensitive media")
+                .id_source(("sensitive", status.id.to_string()))
+                .show(ui, |ui| media_ui(ui, &status.media_attachments));
+        } else {
+            media_ui(ui, &status.media_attachments);
+        }
+    }
+
+    if let Some(poll) = &status.poll {
+        if let Some(a) = poll_ui(ui, poll, status.account.id == me.id) {
+            action = Some(a);
+        }
+    }
+
+    ui.horizontal(|ui| {
+        let id = status.id.to_string();
+        let boosted = status.reblogged.unwrap_or(false);
+        if ui
+            .selectable_label(boosted, format!("🔁 {}", status.reblogs_count))
+            .on_hover_text(if boosted { "Undo boost" } else { "Boost" })
+            .clicked()
+        {
+            action = Some(ViewAction::Queue(OutboxAction::Boost {
+                status: id.clone(),
+                on: !boosted,
+            }));
+        }
+        let favourited = status.favourited.unwrap_or(false);
+        if ui
+            .selectable_label(favourited, format!("⭐ {}", status.favourites_count))
+            .on_hover_text(if favourited {
+                "Unfavourite"
+            } else {
+                "Favourite"
+            })
+            .clicked()
+        {
+            action = Some(ViewAction::Queue(OutboxAction::Favourite {
+                status: id.clone(),
+                on: !favourited,
+            }));
+        }
+        let bookmarked = status.bookmarked.unwrap_or(false);
+        if ui
+            .selectable_label(bookmarked, "🔖")
+            .on_hover_text(if bookmarked {
+                "Remove bookmark"
+            } else {
+                "Bookmark"
+            })
+            .clicked()
+        {
+            action = Some(ViewAction::Queue(OutboxAction::Bookmark {
+                status: id,
+                on: !bookmarked,
+            }));
+        }
+    });
+
+    action
+}
+
+/// An account's picture, fetched through the session's `ImageLoader` and kept in its cache.
+pub fn avatar(account: &Account) -> egui::Image<'_> {
+    egui::Image::new(account.avatar_static.as_str())
+        .fit_to_exact_size(egui::vec2(24.0, 24.0))
+        .rounding(4.0)
+}
+
+/// Previews of a status's attachments, which are only loaded once they're shown.
+fn media_ui(ui: &mut egui::Ui, attachments: &[Attachment]) {
+    ui.horizontal_wrapped(|ui| {
+        for attachment in attachments {
+            let preview = ui.add(
+                egui::Image::new(attachment.preview_url.as_str())
+                    .max_size(egui::vec2(240.0, 180.0))
+                    .rounding(4.0),
+            );
+            if let Some(description) = &attachment.description {
+                preview.on_hover_text(description);
+            }
+        }
+    });
+}
+
+/// Show a favourite, boost or bookmark straight away, while the outbox sends it.
+pub fn apply_interaction(status: &mut Status, action: &OutboxAction) {
+    if let Some(reblog) = &mut status.reblog {
+        apply_interaction(reblog, action);
+    }
+    let count = |count: &mut u64, on: bool| {
+        *count = if on {
+            count.saturating_add(1)
+        } else {
+            count.saturating_sub(1)
+        };
+    };
+    match action {
+        OutboxAction::Favourite { status: id, on } if *id == status.id.to_string() => {
+            if status.favourited != Some(*on) {
+                count(&mut status.favourites_count, *on);
+            }
+            status.favourited = Some(*on);
+        }
+        OutboxAction::Boost { status: id, on } if *id == status.id.to_string() => {
+            if status.reblogged != Some(*on) {
+                count(&mut status.reblogs_count, *on);
+            }
+            status.reblogged = Some(*on);
+        }
+        OutboxAction::Bookmark { status: id, on } if *id == status.id.to_string() => {
+            status.bookmarked = Some(*on);
+        }
+        _ => (),
+    }
+}
+
+fn status_menu_ui(ui: &mut egui::Ui, status: &Status, me: &Account) -> Option<ViewAction> {
+    let mut action = None;
+    if ui.button("Open post").clicked() {
+        action = Some(ViewAction::OpenStatus(status.clone()));
+    }
+    if ui.button("Reply").clicked() {
+        action = Some(ViewAction::Reply(status.clone()));
+    }
+    if let Some(url) = &status.url {
+        ui.hyperlink_to("Open in browser", url);
+    }
+    if status.account.id == me.id {
+        if ui.button("Edit").clicked() {
+            action = Some(ViewAction::Edit(status.clone()));
+        }
+        // Deleting can't be undone, so it takes a second click in a submenu.
+        ui.menu_button("Delete", |ui| {
+            if ui.button("Delete").clicked() {
+                action = Some(ViewAction::Delete(status.clone()));
+            }
+            if ui
+                .button("Delete & redraft")
+                .on_hover_text("Delete the post and start a new one with its text")
+                .clicked()
+            {
+                action = Some(ViewAction::DeleteAndRedraft(status.clone()));
+            }
+        });
+    } else {
+        if let Some(a) = account_menu_ui(ui, &status.account) {
+            action = Some(a);
+        }
+        if ui
+            .button(format!("Report @{}…", status.account.acct))
+            .clicked()
+        {
+            action = Some(ViewAction::Report(status.clone()));
+        }
+    }
+    action
+}
+
+/// Swap in a newer copy of a poll, if the status (or the status it boosts) has it.
+pub fn replace_poll(status: &mut Status, poll: &Poll) {
+    if let Some(reblog) = &mut status.reblog {
+        replace_poll(reblog, poll);
+    }
+    if let Some(existing) = &mut status.poll {
+        if existing.id == poll.id {
+            *existing = poll.clone();
+        }
+    }
+}
+
+/// Moderation actions for someone else's account, shared by status and profile menus.
+pub fn account_menu_ui(ui: &mut egui::Ui, account: &Account) -> Option<ViewAction> {
+    let mut request = None;
+    if ui.button(format!("Mute @{}", account.acct)).clicked() {
+        request = Some(ModerationRequest::Mute(account.clone()));
+    }
+    if ui.button(format!("Block @{}", account.acct)).clicked() {
+        request = Some(ModerationRequest::Block(account.clone()));
+    }
+    if let Some(domain) = account_domain(account) {
+        if ui.button(format!("Block {}", domain)).clicked() {
+            request = Some(ModerationRequest::BlockDomain(domain.to_string()));
+        }
+    }
+    request.map(ViewAction::Moderate)
+}
+
+fn content_ui(ui: &mut egui::Ui, content: &str) -> Option<ViewAction> {
+    let mut action = None;
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for span in parse_content(content) {
+            match span {
+                ContentSpan::Text(text) => {
+                    ui.label(text);
+                }
+                ContentSpan::Link { text, href } if is_likely_fediverse_url(&href) => {
+                    if ui.link(text).on_hover_text(&href).clicked() {
+                        action = Some(ViewAction::OpenUrl(href));
+                    }
+                }
+                ContentSpan::Link { text, href } => {
+                    ui.hyperlink_to(text, href);
+                }
+            }
+        }
+    });
+    action
+}
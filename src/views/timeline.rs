@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+
+use mastodon_async::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    channels::{AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    moderation::{account_domain, ModerationEvent},
+    outbox::OutboxAction,
+    timeline::TimelineMessage,
+    views::{
+        status::{apply_interaction, replace_poll, status_ui},
+        ViewAction,
+    },
+};
+
+pub struct TimelineState {
+    pub statuses: Vec<Status>,
+    pub has_more: bool,
+    pub error: Option<String>,
+    /// Loaded from the cache, and not yet refreshed from the server.
+    pub cached: bool,
+}
+
+/// Accounts and domains whose statuses shouldn't be shown, even if they were already loaded.
+#[derive(Default)]
+pub struct ContentFilter {
+    accounts: HashSet<String>,
+    domains: HashSet<String>,
+}
+
+impl ContentFilter {
+    pub fn apply(&mut self, event: &ModerationEvent) {
+        match event {
+            ModerationEvent::Muted(id) | ModerationEvent::Blocked(id) => {
+                self.accounts.insert(id.to_string());
+            }
+            ModerationEvent::Unmuted(id) | ModerationEvent::Unblocked(id) => {
+                self.accounts.remove(&id.to_string());
+            }
+            ModerationEvent::DomainBlocked(domain) => {
+                self.domains.insert(domain.clone());
+            }
+            ModerationEvent::DomainUnblocked(domain) => {
+                self.domains.remove(domain);
+            }
+        }
+    }
+
+    fn hides_account(&self, account: &Account) -> bool {
+        self.accounts.contains(&account.id.to_string())
+            || account_domain(account).map_or(false, |d| self.domains.contains(d))
+    }
+
+    /// Whether a status, or the status it boosts, is by a filtered account.
+    pub fn hides(&self, status: &Status) -> bool {
+        self.hides_account(&status.account)
+            || status
+                .reblog
+                .as_ref()
+                .map_or(false, |r| self.hides_account(&r.account))
+    }
+}
+
+pub struct TimelineView {
+    bridge: AsyncRequestBridge<TimelineMessage, TimelineState>,
+    pub filter: ContentFilter,
+}
+
+impl TimelineView {
+    pub fn new(tx: mpsc::Sender<Message<TimelineMessage>>) -> Self {
+        TimelineView {
+            bridge: AsyncRequestBridge::new(tx),
+            filter: Default::default(),
+        }
+    }
+
+    fn load_cached(&mut self) {
+        self.bridge
+            .send(TimelineMessage::LoadCached, Box::new(merge_statuses));
+    }
+
+    pub fn reload(&mut self) {
+        self.bridge
+            .send(TimelineMessage::LoadHome, Box::new(merge_statuses));
+    }
+
+    /// Hide statuses affected by a moderation action straight away, without refetching.
+    pub fn apply_moderation(&mut self, event: &ModerationEvent) {
+        self.filter.apply(event);
+        let filter = &self.filter;
+        if let Some(state) = self.bridge.current_state_mut() {
+            state.statuses.retain(|s| !filter.hides(s));
+        }
+    }
+
+    /// Show a status the user just posted without waiting for a refresh.
+    pub fn prepend(&mut self, status: Status) {
+        if let Some(state) = self.bridge.current_state_mut() {
+            state.statuses.insert(0, status);
+        }
+    }
+
+    /// Swap in a newer copy of a status, including where it's been boosted.
+    pub fn replace(&mut self, updated: &Status) {
+        if let Some(state) = self.bridge.current_state_mut() {
+            for status in state.statuses.iter_mut() {
+                if status.id == updated.id {
+                    *status = updated.clone();
+                } else if let Some(reblog) = &mut status.reblog {
+                    if reblog.id == updated.id {
+                        **reblog = updated.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop a deleted status, and any boosts of it.
+    pub fn remove(&mut self, id: &StatusId) {
+        if let Some(state) = self.bridge.current_state_mut() {
+            state
+                .statuses
+                .retain(|s| &s.id != id && s.reblog.as_ref().map_or(true, |r| &r.id != id));
+        }
+    }
+
+    pub fn apply_interaction(&mut self, action: &OutboxAction) {
+        if let Some(state) = self.bridge.current_state_mut() {
+            for status in state.statuses.iter_mut() {
+                apply_interaction(status, action);
+            }
+        }
+    }
+
+    pub fn update_poll(&mut self, poll: &Poll) {
+        if let Some(state) = self.bridge.current_state_mut() {
+            for status in state.statuses.iter_mut() {
+                replace_poll(status, poll);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, me: &Account) -> Option<ViewAction> {
+        if self.bridge.pump_messages() {
+            ui.ctx().request_repaint();
+        }
+        if let AsyncRequestBridgeState::Init = self.bridge.state {
+            self.load_cached();
+        }
+        // Show the cache straight away, then replace it with what's on the server.
+        if !self.bridge.is_awaiting() && self.bridge.current_state().map_or(false, |s| s.cached) {
+            self.reload();
+        }
+        if let AsyncRequestBridgeState::Error(e) = &self.bridge.state {
+            ui.label(format!("error: {}", e));
+            if ui.button("Retry").clicked() {
+                self.reload();
+            }
+            return None;
+        }
+
+        let mut action = None;
+        let mut refresh = false;
+        let mut reached_end = false;
+        let loading = self.bridge.is_awaiting();
+
+        ui.horizontal(|ui| {
+            ui.heading("Home");
+            refresh = ui.add_enabled(!loading, egui::Button::new("⟳")).clicked();
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("home timeline")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                let state = match self.bridge.current_state() {
+                    Some(state) => state,
+                    None => {
+                        ui.spinner();
+                        return;
+                    }
+                };
+                for status in state.statuses.iter().filter(|s| !self.filter.hides(s)) {
+                    if let Some(a) = status_ui(ui, status, me) {
+                        action = Some(a);
+                    }
+                    ui.separator();
+                }
+                if let Some(e) = &state.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                if loading {
+                    ui.spinner();
+                } else if state.has_more {
+                    // Load older statuses as soon as the end of the timeline scrolls into view.
+                    let end = ui.label("…");
+                    reached_end = ui.is_rect_visible(end.rect);
+                }
+            });
+
+        if refresh {
+            self.reload();
+        } else if reached_end {
+            self.bridge
+                .send(TimelineMessage::LoadOlder, Box::new(merge_statuses));
+        }
+        action
+    }
+}
+
+fn merge_statuses(m: TimelineMessage, prev_state: Option<TimelineState>) -> TimelineState {
+    match (m, prev_state) {
+        (TimelineMessage::Cached(statuses), _) => TimelineState {
+            statuses,
+            has_more: false,
+            error: None,
+            cached: true,
+        },
+        (
+            TimelineMessage::Statuses {
+                statuses,
+                has_more,
+                replace: false,
+            },
+            Some(mut prev),
+        ) => {
+            prev.statuses.extend(statuses);
+            prev.has_more = has_more;
+            prev.error = None;
+            prev.cached = false;
+            prev
+        }
+        (
+            TimelineMessage::Statuses {
+                statuses, has_more, ..
+            },
+            _,
+        ) => TimelineState {
+            statuses,
+            has_more,
+            error: None,
+            cached: false,
+        },
+        // Keep what was already loaded, but stop asking for more. Cached statuses stay up
+        // while offline rather than being retried every frame.
+        (TimelineMessage::Error(e), Some(mut prev)) => {
+            prev.has_more = false;
+            prev.error = Some(e);
+            prev.cached = false;
+            prev
+        }
+        (TimelineMessage::Error(e), None) => TimelineState {
+            statuses: vec![],
+            has_more: false,
+            error: Some(e),
+            cached: false,
+        },
+        _ => panic!("can't handle this response."),
+    }
+}
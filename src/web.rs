@@ -0,0 +1,151 @@
+//! Talking to the page around the app, when running in a browser.
+
+use js_sys::{Function, Reflect};
+use log::warn;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::{router::Route, views::SessionView};
+
+fn global(name: &str) -> Option<JsValue> {
+    Reflect::get(&js_sys::global(), &JsValue::from_str(name)).ok()
+}
+
+/// Whether the service worker has installed a newer version of the app (see `index.html`).
+pub fn update_ready() -> bool {
+    global("hedgehogUpdateReady").map_or(false, |ready| ready.is_truthy())
+}
+
+/// Let the new version take over. The page reloads once it has.
+pub fn apply_update() {
+    match global("hedgehogApplyUpdate").and_then(|f| f.dyn_into::<Function>().ok()) {
+        Some(apply) => {
+            if let Err(e) = apply.call0(&JsValue::NULL) {
+                warn!("Failed to apply update: {:?}", e);
+            }
+        }
+        None => warn!("No way to apply an update on this page"),
+    }
+}
+
+/// The query string the page was opened with, which is then removed from the address bar so
+/// reloading doesn't do the same thing again.
+pub fn take_launch_query() -> Option<String> {
+    let window = web_sys::window()?;
+    let location = window.location();
+    let query = location.search().ok().filter(|q| !q.is_empty())?;
+    let rest = format!(
+        "{}{}",
+        location.pathname().unwrap_or_default(),
+        location.hash().unwrap_or_default()
+    );
+    if let Ok(history) = window.history() {
+        if let Err(e) = history.replace_state_with_url(&JsValue::NULL, "", Some(&rest)) {
+            warn!("Failed to tidy up the address bar: {:?}", e);
+        }
+    }
+    Some(query)
+}
+
+/// The fragment of the page's url, e.g. `#/@user@host`.
+pub fn fragment() -> Option<String> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    (!hash.is_empty()).then_some(hash)
+}
+
+/// Have the browser save `contents` to the user's downloads as `name`.
+pub fn download(name: &str, contents: &str) -> Result<(), String> {
+    let failed = |e: JsValue| format!("{:?}", e);
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("No page to download from")?;
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_("application/json");
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob =
+        web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).map_err(failed)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(failed)?;
+    let link: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(failed)?
+        .dyn_into()
+        .map_err(|_| "Failed to make a link")?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    web_sys::Url::revoke_object_url(&url).map_err(failed)
+}
+
+/// Keeps the address bar in step with the page shown, so the browser's back button works.
+#[derive(Default)]
+pub struct History {
+    /// The route in the address bar, once the session has started showing it.
+    shown: Option<Route>,
+    /// The next change should replace the current entry rather than add one, since the page
+    /// was opened from the address bar.
+    replacing: bool,
+    listening: bool,
+}
+
+impl History {
+    pub fn sync(&mut self, ctx: &egui::Context, session: &mut SessionView) {
+        if !self.listening {
+            self.listening = true;
+            repaint_on_popstate(ctx.clone());
+        }
+
+        let location = fragment()
+            .and_then(|f| Route::from_fragment(&f))
+            .unwrap_or(Route::Home);
+        match &self.shown {
+            // The user went back or forward.
+            Some(shown) if *shown != location => {
+                session.navigate(location.clone());
+                self.shown = Some(location);
+                self.replacing = true;
+                return;
+            }
+            Some(_) => (),
+            None => self.replacing = true,
+        }
+
+        // Wait until a link has been looked up, so its page gets the entry rather than this one.
+        if session.is_navigating() {
+            return;
+        }
+        let route = session.route();
+        if self.shown.as_ref() == Some(&route) {
+            return;
+        }
+        let fragment = route.to_fragment();
+        let result = web_sys::window()
+            .ok_or(JsValue::NULL)
+            .and_then(|w| w.history())
+            .and_then(|history| {
+                if self.replacing {
+                    history.replace_state_with_url(&JsValue::NULL, "", Some(&fragment))
+                } else {
+                    history.push_state_with_url(&JsValue::NULL, "", Some(&fragment))
+                }
+            });
+        if let Err(e) = result {
+            warn!("Failed to update the address bar: {:?}", e);
+        }
+        self.replacing = false;
+        self.shown = Some(route);
+    }
+}
+
+/// egui doesn't hear about the back button, so it needs waking up to notice the address changed.
+fn repaint_on_popstate(ctx: egui::Context) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let listener = Closure::<dyn Fn()>::new(move || ctx.request_repaint());
+    if let Err(e) =
+        window.add_event_listener_with_callback("popstate", listener.as_ref().unchecked_ref())
+    {
+        warn!("Failed to listen for navigation: {:?}", e);
+    }
+    // The page and the app go away together, so the listener can live forever.
+    listener.forget();
+}
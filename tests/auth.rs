@@ -0,0 +1,148 @@
+//! Signing in against the mock server, through the same services the ui uses.
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
+use axum::http::StatusCode;
+use hedgehog::{
+    authenticate::{start_auth_service, AuthMessage, AuthService},
+    channels::Spawner,
+    diagnostics::snapshot,
+    registry::Registry,
+};
+use support::{
+    mock_server::{MockServer, ScriptedError, AUTH_CODE, USERNAME},
+    request, start,
+};
+
+fn start_auth(
+    spawner: &Spawner,
+) -> tokio::sync::mpsc::Sender<hedgehog::channels::Message<AuthMessage>> {
+    let auth_spawner = spawner.clone();
+    start(spawner, move |rx| start_auth_service(rx, auth_spawner))
+}
+
+#[test]
+fn signs_in_with_the_code_from_the_authorize_page() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let auth = start_auth(&spawner);
+
+    let url = match request(&auth, AuthMessage::Initialize(server.base.clone())) {
+        AuthMessage::AuthorizeUrl(url) => url,
+        other => panic!("expected an authorize url, got {:?}", other),
+    };
+    assert!(url.starts_with(&format!("{}/oauth/authorize", server.base)));
+
+    // Codes are often pasted with whitespace around them.
+    match request(
+        &auth,
+        AuthMessage::CompleteAuth(format!(" {}\n", AUTH_CODE)),
+    ) {
+        AuthMessage::SignedIn(channels) => {
+            assert_eq!(channels.account.acct, USERNAME);
+            assert!(channels.account_key().starts_with("hedgehog@127.0.0.1:"));
+        }
+        other => panic!("expected to be signed in, got {:?}", other),
+    }
+    assert!(server
+        .requests()
+        .contains(&"GET /api/v1/accounts/verify_credentials".to_string()));
+}
+
+#[test]
+fn a_wrong_code_is_an_error() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let auth = start_auth(&spawner);
+
+    request(&auth, AuthMessage::Initialize(server.base.clone()));
+    match request(&auth, AuthMessage::CompleteAuth("not the code".to_string())) {
+        AuthMessage::Error(e) => assert!(e.contains("Failed to complete login"), "{}", e),
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn completing_before_initializing_is_an_error() {
+    support::init();
+    let spawner = Spawner::new();
+    let auth = start_auth(&spawner);
+
+    match request(&auth, AuthMessage::CompleteAuth(AUTH_CODE.to_string())) {
+        AuthMessage::Error(e) => assert_eq!(e, "Login was not started"),
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn failing_to_verify_credentials_is_an_error() {
+    support::init();
+    let server = MockServer::start();
+    server.fail(
+        "/api/v1/accounts/verify_credentials",
+        // Once, and then both retries.
+        3,
+        ScriptedError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: "{\"error\":\"down for maintenance\"}".to_string(),
+        },
+    );
+    let spawner = Spawner::new();
+    let auth = start_auth(&spawner);
+
+    request(&auth, AuthMessage::Initialize(server.base.clone()));
+    match request(&auth, AuthMessage::CompleteAuth(AUTH_CODE.to_string())) {
+        AuthMessage::Error(e) => assert!(e.contains("Failed to verify credentials"), "{}", e),
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_registry_hands_out_the_auth_service() {
+    support::init();
+    let server = MockServer::start();
+    let registry = Registry::new(Spawner::new());
+    assert!(registry.sender::<AuthService>().is_none());
+    registry.start(AuthService);
+
+    let auth = registry.sender::<AuthService>().expect("an auth service");
+    match request(&auth, AuthMessage::Initialize(server.base.clone())) {
+        AuthMessage::AuthorizeUrl(_) => (),
+        other => panic!("expected an authorize url, got {:?}", other),
+    }
+}
+
+#[test]
+fn signing_in_shows_up_in_the_network_tab() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let auth = start_auth(&spawner);
+
+    request(&auth, AuthMessage::Initialize(server.base.clone()));
+    request(&auth, AuthMessage::CompleteAuth(AUTH_CODE.to_string()));
+
+    let recorded: Vec<String> = snapshot()
+        .http
+        .into_iter()
+        .filter_map(|r| {
+            let path = r.url.strip_prefix(&server.base)?.to_string();
+            Some(format!("{} {}", r.method, path))
+        })
+        .collect();
+    for expected in [
+        "POST /api/v1/apps",
+        "POST /oauth/token",
+        "GET /api/v1/accounts/verify_credentials",
+    ] {
+        assert!(
+            recorded.iter().any(|r| r == expected),
+            "{} wasn't recorded in {:?}",
+            expected,
+            recorded
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! The bridge between the ui and the services, which has to behave the same natively and in the
+//! browser. Run these on the web with `wasm-pack test --node`.
+
+use std::future::pending;
+
+use hedgehog::{
+    channels::{cancellable, AsyncRequestBridge, AsyncRequestBridgeState, Message},
+    timer::{sleep, timeout},
+};
+use instant::Duration;
+use tokio::sync::{mpsc, oneshot};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// A bridge whose channel only has room for one message, which is already taken.
+fn full_bridge() -> (
+    AsyncRequestBridge<u32, u32>,
+    mpsc::Sender<Message<u32>>,
+    mpsc::Receiver<Message<u32>>,
+) {
+    let (tx, rx) = mpsc::channel(1);
+    tx.try_send(Message::Notification { msg: 0 }).unwrap();
+    (AsyncRequestBridge::new(tx.clone()), tx, rx)
+}
+
+fn take_request(rx: &mut mpsc::Receiver<Message<u32>>) -> (u32, oneshot::Sender<u32>) {
+    match rx.try_recv() {
+        Ok(Message::Request { msg, reply }) => (msg, reply),
+        Ok(Message::Notification { msg }) => panic!("expected a request, got notification {}", msg),
+        Err(e) => panic!("expected a request, got {:?}", e),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn sending_to_a_full_channel_queues_instead_of_blocking() {
+    let (mut bridge, _tx, mut rx) = full_bridge();
+
+    bridge.send(1, Box::new(|m, _| m * 10));
+    assert!(bridge.is_awaiting());
+    assert!(bridge.has_queued());
+
+    // The service catches up, and the request goes out on the next frame.
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(Message::Notification { msg: 0 })
+    ));
+    assert!(!bridge.pump_messages());
+    assert!(!bridge.has_queued());
+
+    let (msg, reply) = take_request(&mut rx);
+    assert_eq!(msg, 1);
+    reply.send(2).unwrap();
+    assert!(bridge.pump_messages());
+    assert_eq!(bridge.current_state(), Some(&20));
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn a_replaced_request_still_goes_out_when_queued() {
+    let (mut bridge, _tx, mut rx) = full_bridge();
+
+    bridge.send(1, Box::new(|m, _| m));
+    bridge.send(2, Box::new(|m, _| m));
+
+    rx.try_recv().unwrap();
+    bridge.pump_messages();
+    let (msg, _reply) = take_request(&mut rx);
+    assert_eq!(msg, 1);
+    bridge.pump_messages();
+    let (msg, _reply) = take_request(&mut rx);
+    assert_eq!(msg, 2);
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn a_replaced_request_runs_to_completion() {
+    let (tx, mut rx) = mpsc::channel(2);
+    let mut bridge = AsyncRequestBridge::<u32, u32>::new(tx);
+
+    bridge.send(1, Box::new(|m, _| m));
+    let (_, first) = take_request(&mut rx);
+    bridge.send(2, Box::new(|m, _| m));
+    let (_, second) = take_request(&mut rx);
+
+    // The first request may be a vote or a delete, so the service isn't told to stop.
+    assert!(!first.is_closed());
+    first.send(10).unwrap();
+    assert!(!bridge.pump_messages());
+    assert!(bridge.is_awaiting());
+
+    second.send(20).unwrap();
+    assert!(bridge.pump_messages());
+    assert_eq!(bridge.current_state(), Some(&20));
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn a_request_dropped_unanswered_fails_the_bridge() {
+    let (tx, mut rx) = mpsc::channel(1);
+    let mut bridge = AsyncRequestBridge::<u32, u32>::new(tx);
+
+    bridge.send(1, Box::new(|m, _| m));
+    drop(take_request(&mut rx));
+
+    assert!(bridge.pump_messages());
+    assert!(matches!(bridge.state, AsyncRequestBridgeState::Error(_)));
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn sending_to_a_stopped_service_fails_the_bridge() {
+    let (tx, rx) = mpsc::channel(1);
+    drop(rx);
+    let mut bridge = AsyncRequestBridge::<u32, u32>::new(tx);
+
+    bridge.send(1, Box::new(|m, _| m));
+
+    assert!(matches!(bridge.state, AsyncRequestBridgeState::Error(_)));
+    assert!(!bridge.has_queued());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn cancelling_goes_back_to_the_last_answer() {
+    let (tx, mut rx) = mpsc::channel(1);
+    let mut bridge = AsyncRequestBridge::<u32, u32>::new(tx);
+    bridge.send(1, Box::new(|m, _| m));
+    take_request(&mut rx).1.send(1).unwrap();
+    bridge.pump_messages();
+
+    bridge.send(2, Box::new(|m, _| m));
+    let (_, reply) = take_request(&mut rx);
+    bridge.cancel();
+
+    assert!(!bridge.is_awaiting());
+    assert_eq!(bridge.current_state(), Some(&1));
+    assert!(reply.is_closed());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+async fn timeouts_fire() {
+    let slow = timeout(Duration::from_millis(10), sleep(Duration::from_secs(60))).await;
+    assert!(slow.is_err());
+    let quick = timeout(Duration::from_secs(60), sleep(Duration::from_millis(10))).await;
+    assert!(quick.is_ok());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+async fn work_stops_when_no_one_is_waiting() {
+    let (mut reply, response) = oneshot::channel::<u32>();
+    drop(response);
+
+    let result = cancellable(&mut reply, pending::<Result<u32, String>>()).await;
+    assert!(result.is_none());
+}
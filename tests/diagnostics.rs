@@ -0,0 +1,107 @@
+//! What's kept for the diagnostics window, which ends up attached to bug reports.
+
+use hedgehog::{
+    channels::AsyncRequestBridge,
+    diagnostics::{init_logger, record_http, snapshot},
+};
+use instant::Duration;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Method, StatusCode, Url,
+};
+use tokio::sync::mpsc;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn secrets_are_left_out_of_http_records() {
+    let url = Url::parse("https://mastodon.test/oauth/token?code=1234&scope=read").unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-ratelimit-remaining", HeaderValue::from_static("299"));
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+    let body = r#"{"access_token":"hunter2","token_type":"Bearer"}"#;
+
+    record_http(
+        &Method::POST,
+        &url,
+        Duration::from_millis(12),
+        Ok((StatusCode::OK, &headers)),
+        Some(body),
+    );
+
+    let snapshot = snapshot();
+    let record = snapshot
+        .http
+        .iter()
+        .find(|r| r.url.starts_with("https://mastodon.test/oauth/token"))
+        .expect("the request to be recorded");
+    assert_eq!(record.status, Some(200));
+    assert!(!record.url.contains("1234"));
+    assert!(record.url.contains("scope=read"));
+    let body = record.body.as_deref().unwrap();
+    assert!(!body.contains("hunter2"));
+    assert!(body.contains("Bearer"));
+    assert_eq!(
+        record.rate_limit,
+        vec![("x-ratelimit-remaining".to_string(), "299".to_string())]
+    );
+}
+
+/// Stands in for the platform's logger, so only the diagnostics see anything.
+struct Discard;
+
+impl log::Log for Discard {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        false
+    }
+
+    fn log(&self, _: &log::Record<'_>) {}
+
+    fn flush(&self) {}
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn recent_log_records_are_kept() {
+    init_logger(Box::new(Discard), log::LevelFilter::Off);
+    let logged = |message: &str| snapshot().logs.iter().any(|r| r.message == message);
+
+    log::debug!(target: "hedgehog::tests", "our own debugging");
+    log::debug!(target: "some_crate", "someone else's debugging");
+    log::warn!(target: "some_crate", "someone else's warning");
+    assert!(logged("our own debugging"));
+    assert!(!logged("someone else's debugging"));
+    assert!(logged("someone else's warning"));
+
+    // Only the last 500 are kept.
+    for n in 0..=500 {
+        log::debug!(target: "hedgehog::tests", "record {}", n);
+    }
+    let logs = snapshot().logs;
+    assert_eq!(logs.len(), 500);
+    assert!(!logged("record 0"));
+    assert!(logged("record 500"));
+}
+
+struct ProbedMessage;
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn bridges_are_listed_while_they_live() {
+    let listed = || {
+        snapshot()
+            .bridges
+            .into_iter()
+            .find(|b| b.messages == "ProbedMessage")
+    };
+    let (tx, _rx) = mpsc::channel(1);
+    let mut bridge = AsyncRequestBridge::<ProbedMessage, ()>::new(tx);
+    assert_eq!(listed().map(|b| b.state), Some("Init"));
+
+    bridge.send(ProbedMessage, Box::new(|_, _| ()));
+    assert_eq!(listed().map(|b| b.state), Some("Awaiting"));
+
+    drop(bridge);
+    assert!(listed().is_none());
+}
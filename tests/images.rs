@@ -0,0 +1,67 @@
+//! Avatars and media, through the images service and the account's cache.
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
+use hedgehog::{
+    authenticate::{start_auth_service, AuthMessage},
+    channels::Spawner,
+    images::{ImageMessage, ImagesService},
+    session::SessionChannels,
+};
+use support::{
+    mock_server::{MockServer, AUTH_CODE, IMAGE},
+    request, start,
+};
+
+fn sign_in(server: &MockServer, spawner: &Spawner) -> SessionChannels {
+    let auth_spawner = spawner.clone();
+    let auth = start(spawner, move |rx| start_auth_service(rx, auth_spawner));
+    request(&auth, AuthMessage::Initialize(server.base.clone()));
+    match request(&auth, AuthMessage::CompleteAuth(AUTH_CODE.to_string())) {
+        AuthMessage::SignedIn(channels) => channels,
+        other => panic!("expected to be signed in, got {:?}", other),
+    }
+}
+
+#[test]
+fn images_are_fetched_once_then_cached() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+    let url = session.account.avatar_static.to_string();
+
+    for _ in 0..2 {
+        match request(
+            &session.sender::<ImagesService>(),
+            ImageMessage::Load(url.clone()),
+        ) {
+            ImageMessage::Loaded(data) => assert_eq!(data, IMAGE),
+            ImageMessage::Error(e) => panic!("expected the image, got {}", e),
+            ImageMessage::Load(_) => panic!("expected the image"),
+        }
+    }
+    let fetched = server
+        .requests()
+        .iter()
+        .filter(|r| r.starts_with("GET /avatars/"))
+        .count();
+    assert_eq!(fetched, 1);
+}
+
+#[test]
+fn missing_images_are_an_error() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    match request(
+        &session.sender::<ImagesService>(),
+        ImageMessage::Load(format!("{}/nowhere.png", server.base)),
+    ) {
+        ImageMessage::Error(e) => assert!(e.contains("404"), "{}", e),
+        _ => panic!("expected an error"),
+    }
+}
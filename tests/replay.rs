@@ -0,0 +1,47 @@
+//! The service layer against fixtures in the format the app records conversations in.
+//!
+//! See `support::fixtures` for how to record one.
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
+use hedgehog::{
+    channels::Spawner,
+    timeline::{start_timeline_service, TimelineMessage},
+};
+use support::{fixtures::FixtureServer, request, start};
+
+/// Some servers, GoToSocial among them, send a link to an older page that turns out to be empty.
+/// This fixture is written by hand, as no recording of that has been made yet.
+#[test]
+fn an_empty_last_page_ends_the_home_timeline() {
+    support::init();
+    let server = FixtureServer::start("handwritten-home-timeline-empty-last-page");
+    let spawner = Spawner::new();
+    let mastodon = server.mastodon();
+    let timeline = start(&spawner, move |rx| {
+        start_timeline_service(mastodon, "hedgehog@fixture".to_string(), rx)
+    });
+
+    match request(&timeline, TimelineMessage::LoadHome) {
+        TimelineMessage::Statuses {
+            statuses, has_more, ..
+        } => {
+            assert_eq!(statuses.len(), 3);
+            assert!(has_more);
+            // Links in the fixture point back at whichever server replays it.
+            assert!(statuses[0].uri.starts_with(&server.base));
+        }
+        other => panic!("expected statuses, got {:?}", other),
+    }
+    match request(&timeline, TimelineMessage::LoadOlder) {
+        TimelineMessage::Statuses {
+            statuses, has_more, ..
+        } => {
+            assert!(statuses.is_empty());
+            assert!(!has_more);
+        }
+        other => panic!("expected statuses, got {:?}", other),
+    }
+    assert_eq!(server.unmatched(), Vec::<String>::new());
+}
@@ -0,0 +1,181 @@
+//! Recorded conversations with real instances, to replay in tests.
+//!
+//! A fixture is every request the app made and the response it got, kept in
+//! `tests/fixtures/<name>.json`. Tests point the app at `FixtureServer::base`, which replays it.
+//!
+//! Fixtures are recorded by the app itself: run it with `cargo run --features record`,
+//! `HEDGEHOG_RECORD_FIXTURE=tests/fixtures/<name>.json` and `HEDGEHOG_RECORD_SOFTWARE` set to
+//! what the instance runs, sign in and do what the test needs.
+
+use std::{
+    borrow::Cow,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use hedgehog::{
+    fixture::{Exchange, Fixture, BASE_PLACEHOLDER},
+    redact::{self, REDACTED},
+};
+use mastodon_async::{Data, Mastodon};
+use tokio::sync::oneshot;
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(format!("{}.json", name))
+}
+
+struct Replay {
+    exchanges: Vec<Exchange>,
+    used: Vec<bool>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    base: String,
+    replay: Arc<Mutex<Replay>>,
+    /// Requests a replay had no answer for.
+    unmatched: Arc<Mutex<Vec<String>>>,
+}
+
+pub struct FixtureServer {
+    /// Where to point the app.
+    pub base: String,
+    state: ServerState,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FixtureServer {
+    /// Replay the fixture called `name`.
+    pub fn start(name: &str) -> FixtureServer {
+        let exchanges = Fixture::load(&fixture_path(name))
+            .unwrap_or_else(|e| panic!("{}", e))
+            .exchanges;
+        let replay = Replay {
+            used: vec![false; exchanges.len()],
+            exchanges,
+        };
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("no free port");
+        listener.set_nonblocking(true).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let state = ServerState {
+            base: base.clone(),
+            replay: Arc::new(Mutex::new(replay)),
+            unmatched: Default::default(),
+        };
+        let app = Router::new().fallback(handle).with_state(state.clone());
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await
+                    .unwrap();
+            });
+        });
+
+        FixtureServer {
+            base,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// A client signed in to the server, with the token the recording was redacted to.
+    pub fn mastodon(&self) -> Mastodon {
+        Mastodon::from(Data {
+            base: Cow::Owned(self.base.clone()),
+            client_id: Cow::Borrowed(REDACTED),
+            client_secret: Cow::Borrowed(REDACTED),
+            redirect: Cow::Borrowed("urn:ietf:wg:oauth:2.0:oob"),
+            token: Cow::Borrowed(REDACTED),
+        })
+    }
+
+    /// Requests made during a replay that the fixture had no answer for.
+    pub fn unmatched(&self) -> Vec<String> {
+        self.state.unmatched.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(State(state): State<ServerState>, request: Request) -> Response {
+    let method = request.method().to_string();
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/".to_string(), |p| p.to_string());
+
+    let exchange = {
+        let mut replay = state.replay.lock().unwrap();
+        let Replay { exchanges, used } = &mut *replay;
+        let wanted = redact::query(&path);
+        // The first unused match, so repeated requests get their answers in order. Once
+        // those run out, the last answer is given again.
+        let index = exchanges
+            .iter()
+            .enumerate()
+            .position(|(i, e)| !used[i] && e.method == method && e.path == wanted)
+            .or_else(|| {
+                exchanges
+                    .iter()
+                    .rposition(|e| e.method == method && e.path == wanted)
+            });
+        match index {
+            Some(index) => {
+                used[index] = true;
+                exchanges[index].clone()
+            }
+            None => {
+                let request = format!("{} {}", method, path);
+                state.unmatched.lock().unwrap().push(request.clone());
+                return (
+                    StatusCode::NOT_IMPLEMENTED,
+                    format!("No fixture for {}", request),
+                )
+                    .into_response();
+            }
+        }
+    };
+
+    let mut response = (
+        StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        exchange.body.replace(BASE_PLACEHOLDER, &state.base),
+    )
+        .into_response();
+    for (name, value) in exchange.headers {
+        let value = value.replace(BASE_PLACEHOLDER, &state.base);
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,610 @@
+//! A stand-in Mastodon server, running in the test process on a port of its own.
+//!
+//! It knows one app, one account, one home timeline, its notifications and scheduled posts, which
+//! is enough to sign in, page through statuses and notifications, post, upload media and listen to
+//! the streaming api. Tests can script error responses for a path, and turn on rate limiting.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Form, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use futures::stream::{self, Stream};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, oneshot};
+
+/// The code the mock's authorize page would show, to be pasted into the app.
+pub const AUTH_CODE: &str = "mock-auth-code";
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+pub const CLIENT_ID: &str = "mock-client-id";
+pub const CLIENT_SECRET: &str = "mock-client-secret";
+pub const USERNAME: &str = "hedgehog";
+/// What's served for every image.
+pub const IMAGE: &[u8] = b"\x89PNG\r\n\x1a\nnot really";
+
+/// How many statuses or notifications are given back at once, unless asked for fewer.
+const PAGE_SIZE: usize = 20;
+
+/// An error response to give instead of the real one.
+#[derive(Clone, Debug)]
+pub struct ScriptedError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds from now until the limit resets.
+    pub reset_in: i64,
+}
+
+#[derive(Default)]
+struct MockState {
+    /// Newest first, like the api returns them.
+    home: Vec<Value>,
+    /// Newest first, too.
+    notifications: Vec<Value>,
+    /// Posts waiting to go out, newest first like the rest.
+    scheduled: Vec<Value>,
+    next_id: u64,
+    posted: Vec<HashMap<String, String>>,
+    uploads: Vec<usize>,
+    /// Errors to give for a path, used up one at a time.
+    errors: HashMap<String, VecDeque<ScriptedError>>,
+    rate_limit: Option<RateLimit>,
+    /// `METHOD /path` of every request, in the order they came in.
+    requests: Vec<String>,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        // Ids sort by age in real instances, so keep them the same width.
+        format!("{:012}", self.next_id)
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    base: String,
+    inner: Arc<Mutex<MockState>>,
+    stream: broadcast::Sender<(String, String)>,
+}
+
+pub struct MockServer {
+    /// Where the server can be reached, e.g. `http://127.0.0.1:43210`.
+    pub base: String,
+    state: AppState,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Start a server on a free port, in a thread of its own so tests can stay synchronous.
+    pub fn start() -> MockServer {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("no free port");
+        listener.set_nonblocking(true).unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let base = format!("http://{}", addr);
+
+        let (stream, _) = broadcast::channel(64);
+        let state = AppState {
+            base: base.clone(),
+            inner: Default::default(),
+            stream,
+        };
+        let app = router(state.clone());
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await
+                    .unwrap();
+            });
+        });
+
+        MockServer {
+            base,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut MockState) -> R) -> R {
+        f(&mut self.state.inner.lock().unwrap())
+    }
+
+    /// Add statuses to the top of the home timeline, as if they were just posted by others.
+    pub fn add_statuses(&self, count: usize) {
+        let base = self.base.clone();
+        self.with(|state| {
+            for _ in 0..count {
+                let id = state.next_id();
+                let text = format!("Status {}", id);
+                state.home.insert(0, status_json(&base, &id, &text));
+            }
+        });
+    }
+
+    /// Add mentions to the top of the notifications, each with a status of its own.
+    pub fn add_notifications(&self, count: usize) {
+        let base = self.base.clone();
+        self.with(|state| {
+            for _ in 0..count {
+                let id = state.next_id();
+                let status = status_json(&base, &id, &format!("Mention {}", id));
+                state.notifications.insert(
+                    0,
+                    json!({
+                        "id": id,
+                        "type": "mention",
+                        "created_at": "2024-01-01T00:00:00.000Z",
+                        "account": account_json(&base),
+                        "status": status,
+                    }),
+                );
+            }
+        });
+    }
+
+    /// Add posts scheduled to go out in January 2099.
+    pub fn add_scheduled(&self, count: usize) {
+        self.with(|state| {
+            for n in 0..count {
+                let id = state.next_id();
+                state.scheduled.insert(
+                    0,
+                    json!({
+                        "id": id,
+                        "scheduled_at": format!("2099-01-{:02}T12:00:00.000Z", n % 28 + 1),
+                        "params": { "text": format!("Scheduled {}", id), "visibility": "public" },
+                        "media_attachments": [],
+                    }),
+                );
+            }
+        });
+    }
+
+    /// Give `error` for the next `times` requests to `path`, instead of the real response.
+    pub fn fail(&self, path: &str, times: usize, error: ScriptedError) {
+        self.with(|state| {
+            let errors = state.errors.entry(path.to_string()).or_default();
+            errors.extend(std::iter::repeat(error).take(times));
+        });
+    }
+
+    /// Send rate limit headers with every response, and 429 once `remaining` runs out.
+    pub fn rate_limit(&self, limit: RateLimit) {
+        self.with(|state| state.rate_limit = Some(limit));
+    }
+
+    /// Send an event to everyone listening on the streaming api.
+    pub fn stream_event(&self, event: &str, payload: &Value) {
+        let _ = self
+            .state
+            .stream
+            .send((event.to_string(), payload.to_string()));
+    }
+
+    /// The forms of statuses posted so far.
+    pub fn posted(&self) -> Vec<HashMap<String, String>> {
+        self.with(|state| state.posted.clone())
+    }
+
+    /// The sizes of the media uploaded so far.
+    pub fn uploads(&self) -> Vec<usize> {
+        self.with(|state| state.uploads.clone())
+    }
+
+    /// `METHOD /path` of every request so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.with(|state| state.requests.clone())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/apps", post(register_app))
+        .route("/oauth/authorize", get(authorize))
+        .route("/oauth/token", post(token))
+        .route(
+            "/api/v1/accounts/verify_credentials",
+            get(verify_credentials),
+        )
+        .route("/api/v1/timelines/home", get(home_timeline))
+        .route("/api/v1/notifications", get(notifications))
+        .route("/api/v1/scheduled_statuses", get(scheduled_statuses))
+        .route("/api/v1/statuses", post(post_status))
+        .route("/api/v1/statuses/:id", get(get_status))
+        .route("/api/v2/media", post(upload_media))
+        .route("/api/v1/streaming/user", get(stream_user))
+        .route("/avatars/*file", get(image))
+        .route("/media/*file", get(image))
+        .layer(middleware::from_fn_with_state(state.clone(), scripted))
+        .with_state(state)
+}
+
+/// Log the request, then give a scripted error or a rate limited response if there is one.
+async fn scripted(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let (error, rate_limit) = {
+        let mut inner = state.inner.lock().unwrap();
+        inner
+            .requests
+            .push(format!("{} {}", request.method(), path));
+        let error = inner.errors.get_mut(&path).and_then(|e| e.pop_front());
+        let rate_limit = inner.rate_limit.as_mut().map(|limit| {
+            let limited = limit.remaining == 0;
+            limit.remaining = limit.remaining.saturating_sub(1);
+            (*limit, limited)
+        });
+        (error, rate_limit)
+    };
+
+    let mut response = match (error, rate_limit) {
+        (Some(error), _) => (error.status, error.body).into_response(),
+        (None, Some((_, true))) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({ "error": "Too many requests" })),
+        )
+            .into_response(),
+        (None, _) => next.run(request).await,
+    };
+    if let Some((limit, _)) = rate_limit {
+        let reset = time::OffsetDateTime::now_utc() + time::Duration::seconds(limit.reset_in);
+        let headers = response.headers_mut();
+        headers.insert("X-RateLimit-Limit", limit.limit.into());
+        headers.insert("X-RateLimit-Remaining", limit.remaining.into());
+        if let Ok(reset) = reset.format(&time::format_description::well_known::Rfc3339) {
+            headers.insert("X-RateLimit-Reset", HeaderValue::from_str(&reset).unwrap());
+        }
+    }
+    response
+}
+
+async fn register_app(body: Bytes) -> Json<Value> {
+    // Hedgehog sends a form, other clients send json, and only the redirect uri matters.
+    let redirect_uri = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|v| v["redirect_uris"].as_str().map(str::to_string))
+        .or_else(|| parse_form(&body)?.remove("redirect_uris"))
+        .unwrap_or_else(|| "urn:ietf:wg:oauth:2.0:oob".to_string());
+    Json(json!({
+        "id": "1",
+        "name": "hedgehog",
+        "website": null,
+        "redirect_uri": redirect_uri,
+        "client_id": CLIENT_ID,
+        "client_secret": CLIENT_SECRET,
+        "vapid_key": "",
+    }))
+}
+
+async fn authorize() -> String {
+    format!("Your authorization code is {}", AUTH_CODE)
+}
+
+async fn token(body: Bytes) -> Response {
+    let fields: HashMap<String, String> = serde_json::from_slice(&body)
+        .ok()
+        .or_else(|| parse_form(&body))
+        .unwrap_or_default();
+    if fields.get("code").map(String::as_str) != Some(AUTH_CODE) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "invalid_grant",
+                "error_description": "The provided authorization grant is invalid"
+            })),
+        )
+            .into_response();
+    }
+    Json(json!({
+        "access_token": ACCESS_TOKEN,
+        "token_type": "Bearer",
+        "scope": "read write follow push",
+        "created_at": 1700000000
+    }))
+    .into_response()
+}
+
+/// Form bodies are simple enough here to not need another dependency.
+fn parse_form(body: &[u8]) -> Option<HashMap<String, String>> {
+    let body = std::str::from_utf8(body).ok()?;
+    let url = reqwest::Url::parse(&format!("http://localhost/?{}", body)).ok()?;
+    Some(url.query_pairs().into_owned().collect())
+}
+
+fn authorized(headers: &HeaderMap) -> Result<(), Response> {
+    let expected = format!("Bearer {}", ACCESS_TOKEN);
+    match headers.get(header::AUTHORIZATION) {
+        Some(value) if value.as_bytes() == expected.as_bytes() => Ok(()),
+        _ => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "The access token is invalid" })),
+        )
+            .into_response()),
+    }
+}
+
+async fn verify_credentials(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    Json(account_json(&state.base)).into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct PageQuery {
+    max_id: Option<String>,
+    min_id: Option<String>,
+    since_id: Option<String>,
+    limit: Option<usize>,
+}
+
+/// A page of the home timeline, with `Link` headers pointing at the pages either side.
+async fn home_timeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let home = state.inner.lock().unwrap().home.clone();
+    page_of(
+        home,
+        query,
+        &format!("{}/api/v1/timelines/home", state.base),
+    )
+}
+
+/// A page of notifications, linked to the pages either side like the home timeline.
+async fn notifications(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let notifications = state.inner.lock().unwrap().notifications.clone();
+    page_of(
+        notifications,
+        query,
+        &format!("{}/api/v1/notifications", state.base),
+    )
+}
+
+/// A page of scheduled posts, linked to the pages either side like the home timeline.
+async fn scheduled_statuses(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let scheduled = state.inner.lock().unwrap().scheduled.clone();
+    page_of(
+        scheduled,
+        query,
+        &format!("{}/api/v1/scheduled_statuses", state.base),
+    )
+}
+
+/// The page of `items`, newest first, that `query` asks for, linking to the ones either side.
+fn page_of(items: Vec<Value>, query: PageQuery, url: &str) -> Response {
+    let id = |s: &Value| s["id"].as_str().unwrap_or_default().to_string();
+    let newer_than = query.min_id.or(query.since_id);
+    let page: Vec<Value> = items
+        .into_iter()
+        .filter(|s| query.max_id.as_ref().map_or(true, |max| id(s) < *max))
+        .filter(|s| newer_than.as_ref().map_or(true, |min| id(s) > *min))
+        .take(query.limit.unwrap_or(PAGE_SIZE).min(40))
+        .collect();
+
+    let mut response = Json(&page).into_response();
+    if let (Some(first), Some(last)) = (page.first(), page.last()) {
+        let link = format!(
+            "<{url}?max_id={}>; rel=\"next\", <{url}?min_id={}>; rel=\"prev\"",
+            id(last),
+            id(first),
+        );
+        response
+            .headers_mut()
+            .insert(header::LINK, HeaderValue::from_str(&link).unwrap());
+    }
+    response
+}
+
+async fn post_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let form: HashMap<String, String> = form.into_iter().collect();
+    let text = form.get("status").cloned().unwrap_or_default();
+    if text.trim().is_empty() && !form.contains_key("media_ids[]") {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "Validation failed: Text can't be blank" })),
+        )
+            .into_response();
+    }
+    let mut inner = state.inner.lock().unwrap();
+    let id = inner.next_id();
+    let status = status_json(&state.base, &id, &text);
+    inner.home.insert(0, status.clone());
+    inner.posted.push(form);
+    Json(status).into_response()
+}
+
+async fn get_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let inner = state.inner.lock().unwrap();
+    match inner.home.iter().find(|s| s["id"] == id.as_str()) {
+        Some(status) => Json(status.clone()).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Record not found" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Accepts anything as the file, only its size is remembered.
+async fn upload_media(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(e) = authorized(&headers) {
+        return e;
+    }
+    let mut inner = state.inner.lock().unwrap();
+    inner.uploads.push(body.len());
+    let id = inner.next_id();
+    Json(json!({
+        "id": id,
+        "type": "image",
+        "url": format!("{}/media/{}.png", state.base, id),
+        "preview_url": format!("{}/media/{}_small.png", state.base, id),
+        "remote_url": null,
+        "text_url": null,
+        "meta": null,
+        "description": null,
+        "blurhash": null
+    }))
+    .into_response()
+}
+
+/// The same few bytes for every avatar and attachment, as nothing here decodes them.
+async fn image() -> Response {
+    ([(header::CONTENT_TYPE, "image/png")], IMAGE.to_vec()).into_response()
+}
+
+async fn stream_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>>, Response> {
+    authorized(&headers)?;
+    let events = state.stream.subscribe();
+    let stream = stream::unfold(events, |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok((event, payload)) => {
+                    return Some((Ok(Event::default().event(event).data(payload)), events))
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream))
+}
+
+pub fn account_json(base: &str) -> Value {
+    json!({
+        "id": "1",
+        "username": USERNAME,
+        "acct": USERNAME,
+        "display_name": "Hedgehog",
+        "locked": false,
+        "bot": false,
+        "discoverable": true,
+        "group": false,
+        "created_at": "2023-01-01T00:00:00.000Z",
+        "note": "<p>A test account</p>",
+        "url": format!("{}/@{}", base, USERNAME),
+        "avatar": format!("{}/avatars/original/missing.png", base),
+        "avatar_static": format!("{}/avatars/original/missing.png", base),
+        "header": format!("{}/headers/original/missing.png", base),
+        "header_static": format!("{}/headers/original/missing.png", base),
+        "followers_count": 0,
+        "following_count": 0,
+        "statuses_count": 0,
+        "last_status_at": null,
+        "emojis": [],
+        "fields": [],
+        "source": {
+            "privacy": "public",
+            "sensitive": false,
+            "language": "en",
+            "note": "A test account",
+            "fields": []
+        }
+    })
+}
+
+pub fn status_json(base: &str, id: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "uri": format!("{}/users/{}/statuses/{}", base, USERNAME, id),
+        "url": format!("{}/@{}/{}", base, USERNAME, id),
+        "account": account_json(base),
+        "in_reply_to_id": null,
+        "in_reply_to_account_id": null,
+        "reblog": null,
+        "content": format!("<p>{}</p>", text),
+        "created_at": "2024-01-01T00:00:00.000Z",
+        "edited_at": null,
+        "emojis": [],
+        "replies_count": 0,
+        "reblogs_count": 0,
+        "favourites_count": 0,
+        "reblogged": false,
+        "favourited": false,
+        "bookmarked": false,
+        "muted": false,
+        "pinned": false,
+        "sensitive": false,
+        "spoiler_text": "",
+        "visibility": "public",
+        "media_attachments": [],
+        "mentions": [],
+        "tags": [],
+        "card": null,
+        "poll": null,
+        "application": null,
+        "language": "en",
+        "filtered": []
+    })
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests. Each test file pulls in what it needs with
+//! `mod support;`, so not every helper is used by every file.
+#![allow(dead_code)]
+
+pub mod fixtures;
+pub mod mock_server;
+pub mod ui;
+
+use std::sync::Once;
+
+use hedgehog::channels::{new_channel_pair, Message, Spawner};
+use tokio::sync::{mpsc, oneshot};
+
+static INIT: Once = Once::new();
+
+/// Keep the tests' caches out of the user's own storage directory.
+pub fn init() {
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("hedgehog-tests-{}", std::process::id()));
+        std::env::set_var("HEDGEHOG_CACHE_DIR", dir);
+        let _ = env_logger::builder().is_test(true).try_init();
+    });
+}
+
+/// Send a request to a service and wait for its reply, the way the ui's bridges do.
+pub fn request<T>(tx: &mpsc::Sender<Message<T>>, msg: T) -> T {
+    let (reply, response) = oneshot::channel();
+    tx.blocking_send(Message::Request { msg, reply })
+        .unwrap_or_else(|_| panic!("service went away"));
+    response
+        .blocking_recv()
+        .expect("service dropped the request")
+}
+
+/// Start a service on `spawner`, returning the channel to talk to it with.
+pub fn start<T, F, Fut>(spawner: &Spawner, service: F) -> mpsc::Sender<Message<T>>
+where
+    T: Send + 'static,
+    F: FnOnce(mpsc::Receiver<Message<T>>) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = new_channel_pair::<T>();
+    spawner.spawn_async(service(rx));
+    tx
+}
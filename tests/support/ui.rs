@@ -0,0 +1,237 @@
+//! Running `TemplateApp` without a window: frames are driven through `egui::Context::run` with
+//! made up input, and widgets are found through the accessibility tree egui builds for them.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use egui::accesskit::{NodeId, Role};
+use hedgehog::{
+    channels::Message,
+    registry::{Registry, Service},
+    TemplateApp,
+};
+use tokio::sync::mpsc;
+
+/// How long `run_until` waits for services to answer before giving up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const SCREEN: egui::Vec2 = egui::vec2(1024.0, 768.0);
+
+/// A widget as the accessibility tree describes it.
+#[derive(Clone, Debug)]
+pub struct Widget {
+    pub role: Role,
+    /// The text of a label, button or link.
+    pub name: Option<String>,
+    /// What's typed into a text edit.
+    pub value: Option<String>,
+    pub rect: egui::Rect,
+}
+
+pub struct Harness {
+    pub app: TemplateApp,
+    ctx: egui::Context,
+    events: Vec<egui::Event>,
+    widgets: HashMap<NodeId, Widget>,
+    frames: u64,
+}
+
+impl Harness {
+    pub fn new(app: TemplateApp) -> Self {
+        let ctx = egui::Context::default();
+        ctx.enable_accesskit();
+        hedgehog::channels::wake_ui_with(&ctx);
+        let mut harness = Harness {
+            app,
+            ctx,
+            events: vec![],
+            widgets: HashMap::new(),
+            frames: 0,
+        };
+        harness.run();
+        harness
+    }
+
+    /// Draw a frame, with whatever input has been queued since the last one.
+    pub fn run(&mut self) {
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, SCREEN)),
+            time: Some(self.frames as f64 / 60.0),
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        let app = &mut self.app;
+        let output = self.ctx.run(input, |ctx| app.ui(ctx));
+        self.frames += 1;
+
+        // egui describes every widget again each frame.
+        if let Some(update) = output.platform_output.accesskit_update {
+            self.widgets.clear();
+            let ppp = self.ctx.pixels_per_point();
+            for (id, node) in update.nodes {
+                let rect = node.bounds().map_or(egui::Rect::NOTHING, |b| {
+                    egui::Rect::from_min_max(
+                        egui::pos2(b.x0 as f32 / ppp, b.y0 as f32 / ppp),
+                        egui::pos2(b.x1 as f32 / ppp, b.y1 as f32 / ppp),
+                    )
+                });
+                self.widgets.insert(
+                    id,
+                    Widget {
+                        role: node.role(),
+                        name: node.name().map(str::to_string),
+                        value: node.value().map(str::to_string),
+                        rect,
+                    },
+                );
+            }
+            // Containers without bounds can't be seen or clicked.
+            self.widgets.retain(|_, w| w.rect.is_positive());
+        }
+    }
+
+    /// Draw frames until `done` is true, waiting a little between them for services to answer.
+    pub fn run_until(&mut self, what: &str, mut done: impl FnMut(&Harness) -> bool) {
+        let started = Instant::now();
+        loop {
+            self.run();
+            if done(self) {
+                return;
+            }
+            if started.elapsed() > TIMEOUT {
+                panic!(
+                    "timed out waiting for {}, showing {:#?}",
+                    what,
+                    self.texts()
+                );
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Wait for a widget with this text to show up.
+    pub fn wait_for(&mut self, text: &str) {
+        self.run_until(text, |h| h.find(text).is_some());
+    }
+
+    /// Widgets shown in the last frame, top to bottom.
+    pub fn widgets(&self) -> Vec<&Widget> {
+        let mut widgets: Vec<&Widget> = self.widgets.values().collect();
+        widgets.sort_by(|a, b| {
+            (a.rect.min.y, a.rect.min.x)
+                .partial_cmp(&(b.rect.min.y, b.rect.min.x))
+                .unwrap()
+        });
+        widgets
+    }
+
+    /// The text of every widget that has some, top to bottom.
+    pub fn texts(&self) -> Vec<String> {
+        self.widgets()
+            .into_iter()
+            .filter_map(|w| w.name.clone().or_else(|| w.value.clone()))
+            .collect()
+    }
+
+    /// The first widget whose text contains `text`.
+    pub fn find(&self, text: &str) -> Option<&Widget> {
+        self.widgets()
+            .into_iter()
+            .find(|w| w.name.as_deref().map_or(false, |n| n.contains(text)))
+    }
+
+    /// The text edits shown, top to bottom.
+    pub fn text_edits(&self) -> Vec<&Widget> {
+        self.widgets()
+            .into_iter()
+            .filter(|w| matches!(w.role, Role::TextInput | Role::MultilineTextInput))
+            .collect()
+    }
+
+    /// Click the first widget whose text contains `text`.
+    pub fn click(&mut self, text: &str) {
+        let rect = match self.find(text) {
+            Some(widget) => widget.rect,
+            None => panic!("nothing says {:?}, showing {:#?}", text, self.texts()),
+        };
+        self.click_at(rect.center());
+    }
+
+    pub fn click_at(&mut self, pos: egui::Pos2) {
+        self.events.push(egui::Event::PointerMoved(pos));
+        self.events.push(pointer_button(pos, true));
+        self.run();
+        self.events.push(pointer_button(pos, false));
+        self.run();
+    }
+
+    /// Click into the `n`th text edit from the top, and type into it.
+    pub fn type_into(&mut self, n: usize, text: &str) {
+        let rect = match self.text_edits().get(n) {
+            Some(widget) => widget.rect,
+            None => panic!("there aren't {} text edits", n + 1),
+        };
+        self.click_at(rect.center());
+        self.events.push(egui::Event::Text(text.to_string()));
+        self.run();
+    }
+}
+
+fn pointer_button(pos: egui::Pos2, pressed: bool) -> egui::Event {
+    egui::Event::PointerButton {
+        pos,
+        button: egui::PointerButton::Primary,
+        pressed,
+        modifiers: Default::default(),
+    }
+}
+
+/// Stand in for the service `S` in `registry`, answering requests with `script`.
+///
+/// Returns a log of what was asked.
+pub fn scripted<S, F>(registry: &Registry, script: F) -> Arc<Mutex<Vec<String>>>
+where
+    S: Service,
+    S::Message: std::fmt::Debug,
+    F: FnMut(S::Message) -> Option<S::Message> + Send + 'static,
+{
+    let (tx, rx) = hedgehog::channels::new_channel_pair();
+    let log = Arc::new(Mutex::new(vec![]));
+    registry
+        .spawner()
+        .spawn_async(answer(rx, script, log.clone(), |msg| format!("{:?}", msg)));
+    registry.insert::<S>(tx);
+    log
+}
+
+/// A stand-in for any service: replies to each request with what `script` gives back, or never
+/// if it gives back `None`. `describe` says what each request was, for the log.
+pub async fn answer<T, F, D>(
+    mut rx: mpsc::Receiver<Message<T>>,
+    mut script: F,
+    log: Arc<Mutex<Vec<String>>>,
+    describe: D,
+) where
+    F: FnMut(T) -> Option<T>,
+    D: Fn(&T) -> String,
+{
+    // Requests that are never answered are kept, so the ui sees them as still in flight.
+    let mut unanswered = vec![];
+    while let Some(message) = rx.recv().await {
+        match message {
+            Message::Request { msg, reply } => {
+                log.lock().unwrap().push(describe(&msg));
+                match script(msg) {
+                    Some(response) => {
+                        let _ = reply.send(response);
+                    }
+                    None => unanswered.push(reply),
+                }
+            }
+            Message::Notification { msg } => log.lock().unwrap().push(describe(&msg)),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! The home timeline, posting, media and streaming, against the mock server.
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use futures::TryStreamExt;
+use hedgehog::{
+    authenticate::{start_auth_service, AuthMessage},
+    channels::{Message, Spawner},
+    session::SessionChannels,
+    statuses::{ComposeDraft, StatusesMessage, StatusesService},
+    timeline::{TimelineMessage, TimelineService},
+};
+use mastodon_async::entities::event::Event;
+use support::{
+    mock_server::{status_json, MockServer, RateLimit, ScriptedError, AUTH_CODE},
+    request, start,
+};
+use tokio::sync::oneshot;
+
+fn sign_in(server: &MockServer, spawner: &Spawner) -> SessionChannels {
+    let auth_spawner = spawner.clone();
+    let auth = start(spawner, move |rx| start_auth_service(rx, auth_spawner));
+    request(&auth, AuthMessage::Initialize(server.base.clone()));
+    match request(&auth, AuthMessage::CompleteAuth(AUTH_CODE.to_string())) {
+        AuthMessage::SignedIn(channels) => channels,
+        other => panic!("expected to be signed in, got {:?}", other),
+    }
+}
+
+#[test]
+fn pages_through_the_home_timeline() {
+    support::init();
+    let server = MockServer::start();
+    server.add_statuses(30);
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    let first = match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    ) {
+        TimelineMessage::Statuses {
+            statuses, replace, ..
+        } => {
+            assert!(replace);
+            statuses
+        }
+        other => panic!("expected statuses, got {:?}", other),
+    };
+    assert_eq!(first.len(), 20);
+
+    let second = match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadOlder,
+    ) {
+        TimelineMessage::Statuses {
+            statuses, replace, ..
+        } => {
+            assert!(!replace);
+            statuses
+        }
+        other => panic!("expected statuses, got {:?}", other),
+    };
+    assert_eq!(second.len(), 10);
+    // Each page carries on from the oldest status of the one before.
+    assert!(second[0].id.to_string() < first[19].id.to_string());
+}
+
+#[test]
+fn server_errors_are_reported() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+    server.fail(
+        "/api/v1/timelines/home",
+        // Once, and then both retries.
+        3,
+        ScriptedError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: "{\"error\":\"oops\"}".to_string(),
+        },
+    );
+
+    match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    ) {
+        TimelineMessage::Error(e) => assert!(e.contains("Failed to load home timeline"), "{}", e),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    // Only the one load was scripted to fail.
+    match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    ) {
+        TimelineMessage::Statuses { .. } => (),
+        other => panic!("expected statuses, got {:?}", other),
+    }
+}
+
+#[test]
+fn rate_limited_requests_fail() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+    // Retries wait for the limit to reset, so it resets quickly, but never has room again.
+    server.rate_limit(RateLimit {
+        limit: 300,
+        remaining: 0,
+        reset_in: 1,
+    });
+
+    match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    ) {
+        TimelineMessage::Error(_) => (),
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn held_back_prefetches_dont_hold_up_the_user() {
+    support::init();
+    let server = MockServer::start();
+    server.add_statuses(30);
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    // Low enough that background requests wait a minute for the limit to reset, which the
+    // first page of the timeline tells the service.
+    server.rate_limit(RateLimit {
+        limit: 300,
+        remaining: 5,
+        reset_in: 60,
+    });
+    request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    );
+
+    let (reply, mut older) = oneshot::channel();
+    session
+        .sender::<TimelineService>()
+        .blocking_send(Message::Request {
+            msg: TimelineMessage::LoadOlder,
+            reply,
+        })
+        .unwrap_or_else(|_| panic!("service went away"));
+    let started = Instant::now();
+    match request(
+        &session.sender::<TimelineService>(),
+        TimelineMessage::LoadHome,
+    ) {
+        TimelineMessage::Statuses { .. } => (),
+        other => panic!("expected statuses, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert!(older.try_recv().is_err());
+}
+
+#[test]
+fn notifications_are_kept_in_the_cache() {
+    support::init();
+    let server = MockServer::start();
+    server.add_notifications(25);
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+    let timeline = session.sender::<TimelineService>();
+
+    let first = match request(&timeline, TimelineMessage::LoadNotifications) {
+        TimelineMessage::Notifications {
+            notifications,
+            has_more,
+            replace,
+        } => {
+            assert!(has_more);
+            assert!(replace);
+            notifications
+        }
+        other => panic!("expected notifications, got {:?}", other),
+    };
+    assert_eq!(first.len(), 20);
+    match request(&timeline, TimelineMessage::LoadOlderNotifications) {
+        TimelineMessage::Notifications { notifications, .. } => {
+            assert_eq!(notifications.len(), 5)
+        }
+        other => panic!("expected notifications, got {:?}", other),
+    }
+
+    // Signing in again opens the same cache, before anything has been fetched.
+    let again = sign_in(&server, &spawner);
+    match request(
+        &again.sender::<TimelineService>(),
+        TimelineMessage::LoadCachedNotifications,
+    ) {
+        TimelineMessage::CachedNotifications(cached) => {
+            assert_eq!(cached.len(), 25);
+            assert_eq!(cached[0].id, first[0].id);
+            assert!(cached[0].status.is_some());
+        }
+        other => panic!("expected cached notifications, got {:?}", other),
+    }
+}
+
+#[test]
+fn posts_a_draft() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    let draft = ComposeDraft {
+        text: "Hello from the tests".to_string(),
+        spoiler_text: "testing".to_string(),
+        ..Default::default()
+    };
+    match request(
+        &session.sender::<StatusesService>(),
+        StatusesMessage::Post(draft),
+    ) {
+        StatusesMessage::Posted { status, .. } => {
+            assert!(status.content.contains("Hello from the tests"))
+        }
+        other => panic!("expected the posted status, got {:?}", other),
+    }
+    let posted = server.posted();
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0]["status"], "Hello from the tests");
+    assert_eq!(posted[0]["spoiler_text"], "testing");
+}
+
+#[test]
+fn blank_posts_are_refused() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    match request(
+        &session.sender::<StatusesService>(),
+        StatusesMessage::Post(ComposeDraft::default()),
+    ) {
+        StatusesMessage::Error(e) => assert!(e.contains("can't be blank"), "{}", e),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    assert!(server.posted().is_empty());
+}
+
+#[test]
+fn loads_every_page_of_scheduled_posts() {
+    support::init();
+    let server = MockServer::start();
+    server.add_scheduled(50);
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    match request(
+        &session.sender::<StatusesService>(),
+        StatusesMessage::LoadScheduled,
+    ) {
+        StatusesMessage::ScheduledList(scheduled) => assert_eq!(scheduled.len(), 50),
+        other => panic!("expected the scheduled posts, got {:?}", other),
+    }
+    let pages = server
+        .requests()
+        .iter()
+        .filter(|r| r.starts_with("GET /api/v1/scheduled_statuses"))
+        .count();
+    // Two pages of 40 and 10, and the empty one the last links to.
+    assert_eq!(pages, 3);
+}
+
+#[test]
+fn posts_uploaded_media() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+    let file = std::env::temp_dir().join(format!("hedgehog-upload-{}.png", std::process::id()));
+    std::fs::write(&file, [0u8; 64]).unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let attachment = rt
+        .block_on(
+            session
+                .mastodon
+                .media(&file, Some("A test image".to_string())),
+        )
+        .expect("the upload to succeed");
+    std::fs::remove_file(&file).unwrap();
+    assert_eq!(server.uploads().len(), 1);
+
+    let draft = ComposeDraft {
+        media_ids: vec![attachment.id.to_string()],
+        ..Default::default()
+    };
+    match request(
+        &session.sender::<StatusesService>(),
+        StatusesMessage::Post(draft),
+    ) {
+        StatusesMessage::Posted { .. } => (),
+        other => panic!("expected the posted status, got {:?}", other),
+    }
+    assert_eq!(server.posted()[0]["media_ids[]"], attachment.id.to_string());
+}
+
+#[test]
+fn hears_about_new_statuses_on_the_stream() {
+    support::init();
+    let server = MockServer::start();
+    let spawner = Spawner::new();
+    let session = sign_in(&server, &spawner);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let event = rt.block_on(async {
+        // The server is listening for events once the stream has been answered.
+        let stream = session.mastodon.stream_user().await.expect("a stream");
+        futures::pin_mut!(stream);
+        server.stream_event(
+            "update",
+            &status_json(&server.base, "000000000042", "Streamed"),
+        );
+        stream.try_next().await.expect("an event")
+    });
+    match event {
+        Some((Event::Update(status), _)) => {
+            assert_eq!(status.id.to_string(), "000000000042");
+            assert!(status.content.contains("Streamed"));
+        }
+        other => panic!("expected an update, got {:?}", other.map(|(e, _)| e)),
+    }
+}
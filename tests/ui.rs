@@ -0,0 +1,104 @@
+//! Driving the app's ui headlessly, with scripted services behind it.
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
+use hedgehog::{
+    app::AuthUiState,
+    authenticate::{AuthMessage, AuthService},
+    channels::{AsyncRequestBridgeState, Spawner},
+    registry::Registry,
+    service::EchoService,
+    TemplateApp,
+};
+use support::{
+    mock_server::{MockServer, AUTH_CODE},
+    ui::{scripted, Harness},
+};
+
+/// Services that never answer, until a test scripts them.
+fn silent_services() -> Registry {
+    let registry = Registry::new(Spawner::new());
+    scripted::<EchoService, _>(&registry, |_| None);
+    scripted::<AuthService, _>(&registry, |_| None);
+    registry
+}
+
+#[test]
+fn asks_for_an_instance_first() {
+    support::init();
+    let registry = Registry::new(Spawner::new());
+    let log = scripted::<AuthService, _>(&registry, |_| None);
+    let harness = Harness::new(TemplateApp::with_services(registry, None));
+
+    assert!(harness.find("Instance:").is_some());
+    assert!(harness.find("Log in").is_some());
+    assert!(log.lock().unwrap().is_empty());
+}
+
+#[test]
+fn walks_through_logging_in() {
+    support::init();
+    let registry = Registry::new(Spawner::new());
+    // An auth service that hands out a url and turns down every code.
+    let log = scripted::<AuthService, _>(&registry, |msg| match msg {
+        AuthMessage::Initialize(_) => Some(AuthMessage::AuthorizeUrl(
+            "https://mastodon.test/oauth/authorize".to_string(),
+        )),
+        AuthMessage::CompleteAuth(code) => {
+            Some(AuthMessage::Error(format!("{} is not the code", code)))
+        }
+        _ => None,
+    });
+    let mut harness = Harness::new(TemplateApp::with_services(registry, None));
+
+    harness.type_into(0, "mastodon.test");
+    harness.click("Log in");
+    harness.wait_for("Continue logging in to mastodon.test");
+    assert!(log.lock().unwrap().is_empty());
+
+    harness.click("Continue logging in to mastodon.test");
+    harness.wait_for("get auth code from here");
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["Initialize(\"mastodon.test\")".to_string()]
+    );
+
+    // The instance field is gone, so the code's field is the first one.
+    harness.type_into(0, "1234");
+    harness.click("Sign in");
+    harness.wait_for("error: 1234 is not the code");
+}
+
+#[test]
+fn shows_while_waiting_for_the_server() {
+    support::init();
+    let mut harness = Harness::new(TemplateApp::with_services(silent_services(), None));
+
+    harness.click("Async invoke");
+    harness.wait_for("Async state: \"Awaiting\"");
+}
+
+#[test]
+fn signs_in_against_the_mock_server() {
+    support::init();
+    let server = MockServer::start();
+    let registry = Registry::new(Spawner::new());
+    registry.start(AuthService);
+    let mut harness = Harness::new(TemplateApp::with_services(registry, None));
+
+    harness.type_into(0, &server.base);
+    harness.click("Log in");
+    harness.wait_for("Continue logging in to");
+    harness.click("Continue logging in to");
+    harness.wait_for("get auth code from here");
+    harness.type_into(0, AUTH_CODE);
+    harness.click("Sign in");
+    harness.wait_for("Signed in as @hedgehog");
+
+    let auth = harness.app.auth_bridge().expect("an auth service");
+    assert!(matches!(
+        auth.state,
+        AsyncRequestBridgeState::Complete(AuthUiState::SignedIn(_))
+    ));
+}